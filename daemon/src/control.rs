@@ -0,0 +1,522 @@
+use crate::ServerSpec;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tracing::{info, warn};
+
+const BACKLOG: usize = 100;
+pub(crate) const DEFAULT_PORT: u16 = 12323;
+#[cfg(unix)]
+const SOCKET_MODE: u32 = 0o660;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event {
+    SyncStarted {
+        servers: usize,
+    },
+    SyncCompleted {
+        offset_ms: Option<f64>,
+    },
+    ServerRejected {
+        server: String,
+        reason: String,
+        falseticker: bool,
+    },
+    ClockStepped {
+        offset_ms: f64,
+    },
+    ClockSlewed {
+        offset_ms: f64,
+    },
+    SyncFailed {
+        error: String,
+    },
+    ClockAnomaly {
+        kind: String,
+        detail: String,
+    },
+    AddressMismatch {
+        server: String,
+        address: String,
+    },
+    SuspectedAttack {
+        offset_ms: f64,
+        trusted_offset_ms: Option<f64>,
+    },
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    time: DateTime<Utc>,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+pub(crate) fn default_path() -> PathBuf {
+    PathBuf::from("/run/tdctld.sock")
+}
+
+type Listener = Box<dyn FnMut(&Event) + Send>;
+type Client = Box<dyn Write + Send>;
+
+// A client that stops reading, or never sends its request, is dropped instead of holding up the
+// others.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// The servers the daemon was configured with, which `tdctld servers` changes while it runs.
+#[derive(Default)]
+struct ServerSet {
+    servers: Vec<ServerSpec>,
+    // Where changes are persisted to when asked to.
+    file: Option<PathBuf>,
+    generation: u64,
+}
+
+impl ServerSet {
+    // Handles `add SPEC [persist]` and `remove SERVER [persist]`, a server being removed by its
+    // host[:port] or by the whole spec it was added with.
+    fn change(&mut self, command: &str) -> Result<String, String> {
+        let mut words = command.split_whitespace();
+        let (Some(action), Some(server)) = (words.next(), words.next()) else {
+            return Err(format!("invalid servers command '{command}'"));
+        };
+        let persist = match words.next() {
+            None => false,
+            Some("persist") => true,
+            Some(word) => return Err(format!("unexpected '{word}'")),
+        };
+        if persist && self.file.is_none() {
+            return Err("the daemon was started without --servers-file to persist to".to_owned());
+        }
+        let message = match action {
+            "add" => {
+                let spec: ServerSpec = server.parse()?;
+                if self.servers.iter().any(|known| known.server == spec.server) {
+                    return Err(format!("{} is already a server", spec.server));
+                }
+                let message = format!("added {}", spec.server);
+                self.servers.push(spec);
+                message
+            }
+            "remove" => {
+                let count = self.servers.len();
+                self.servers
+                    .retain(|known| known.server != server && known.spec != server);
+                if self.servers.len() == count {
+                    return Err(format!("{server} is not a server"));
+                }
+                format!("removed {server}")
+            }
+            _ => return Err(format!("unknown servers command '{action}'")),
+        };
+        self.generation += 1;
+        match &self.file {
+            Some(file) if persist => {
+                crate::servers::save(file, &self.servers).map_err(|e| format!("{e:#}"))?;
+                Ok(format!("{message}, saved to {}", file.display()))
+            }
+            _ => Ok(message),
+        }
+    }
+}
+
+// Corrections are held while paused, until the given time or until resumed.
+#[derive(Clone, Copy, Serialize)]
+struct Pause {
+    since: DateTime<Utc>,
+    until: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct Subscribers {
+    backlog: VecDeque<String>,
+    listeners: Vec<Listener>,
+    // Each follower has a thread of its own writing to it, fed through a channel holding up to a
+    // backlog of events. One that falls that far behind is dropped.
+    followers: Vec<SyncSender<String>>,
+    status: serde_json::Value,
+    servers: ServerSet,
+    pause: Option<Pause>,
+}
+
+impl Subscribers {
+    // The pause in effect, forgetting one that has run out.
+    fn pause(&mut self) -> Option<Pause> {
+        let pause = self.pause?;
+        if pause.until.is_some_and(|until| until <= Utc::now()) {
+            info!("maintenance pause over, resuming corrections");
+            self.pause = None;
+        }
+        self.pause
+    }
+
+    // Handles `pause [SECS]` and `resume`.
+    fn set_pause(&mut self, command: &str) -> Result<String, String> {
+        let mut words = command.split_whitespace();
+        let message = match (words.next(), words.next(), words.next()) {
+            (Some("pause"), secs, None) => {
+                let until = secs
+                    .map(|secs| {
+                        secs.parse::<u32>()
+                            .map(|secs| Utc::now() + chrono::Duration::seconds(secs.into()))
+                            .map_err(|_| format!("invalid pause duration '{secs}'"))
+                    })
+                    .transpose()?;
+                self.pause = Some(Pause {
+                    since: Utc::now(),
+                    until,
+                });
+                match until {
+                    Some(until) => format!(
+                        "corrections paused until {}",
+                        until.format("%Y-%m-%d %H:%M:%S UTC")
+                    ),
+                    None => "corrections paused until resumed".to_owned(),
+                }
+            }
+            (Some("resume"), None, None) => {
+                if self.pause.take().is_none() {
+                    return Err("corrections are not paused".to_owned());
+                }
+                "corrections resumed".to_owned()
+            }
+            _ => return Err(format!("invalid command '{command}'")),
+        };
+        info!("{message}");
+        Ok(message)
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct EventBus {
+    inner: Arc<Mutex<Subscribers>>,
+}
+
+impl EventBus {
+    pub(crate) fn subscribe(&self, listener: impl FnMut(&Event) + Send + 'static) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.listeners.push(Box::new(listener));
+    }
+
+    pub(crate) fn publish(&self, event: Event) {
+        let record = Record {
+            time: Utc::now(),
+            event: &event,
+        };
+        let Ok(line) = serde_json::to_string(&record) else {
+            return;
+        };
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        for listener in &mut inner.listeners {
+            listener(&event);
+        }
+        if inner.backlog.len() == BACKLOG {
+            inner.backlog.pop_front();
+        }
+        inner
+            .followers
+            .retain(|follower| follower.try_send(line.clone()).is_ok());
+        inner.backlog.push_back(line);
+    }
+
+    // Replaces the snapshot served to `status` requests.
+    pub(crate) fn set_status(&self, status: serde_json::Value) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.status = status;
+    }
+
+    pub(crate) fn set_servers(&self, servers: Vec<ServerSpec>, file: Option<PathBuf>) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.servers = ServerSet {
+            servers,
+            file,
+            generation: 0,
+        };
+    }
+
+    // The server set, if it changed since `generation` was last seen.
+    pub(crate) fn changed_servers(&self, generation: &mut u64) -> Option<Vec<ServerSpec>> {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        (inner.servers.generation != *generation).then(|| {
+            *generation = inner.servers.generation;
+            inner.servers.servers.clone()
+        })
+    }
+
+    // Whether corrections are paused for maintenance, by `tdctld pause`.
+    pub(crate) fn paused(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.pause().is_some()
+    }
+
+    // Answers a request, writing to the client only once the bus is unlocked so that a slow one
+    // never holds up the daemon publishing. A follower is then fed events until it goes away.
+    fn serve(&self, request: &str, mut stream: Client, privileged: bool) {
+        let (response, follower) = self.respond(request, privileged);
+        if stream.write_all(response.as_bytes()).is_err() {
+            return;
+        }
+        for line in follower.into_iter().flatten() {
+            if writeln!(stream, "{line}").is_err() {
+                break;
+            }
+        }
+    }
+
+    // Only the Unix socket, which file permissions guard, may change what the daemon does or
+    // follow its events, each follower taking a thread.
+    fn respond(&self, request: &str, privileged: bool) -> (String, Option<Receiver<String>>) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let response = match request {
+            "follow" if !privileged => {
+                "error: events can only be followed over the control socket\n".to_owned()
+            }
+            command @ ("events" | "follow") => {
+                let response = inner
+                    .backlog
+                    .iter()
+                    .map(|line| format!("{line}\n"))
+                    .collect();
+                if command == "follow" {
+                    let (sender, receiver) = mpsc::sync_channel(BACKLOG);
+                    inner.followers.push(sender);
+                    return (response, Some(receiver));
+                }
+                response
+            }
+            "status" => {
+                let mut status = inner.status.clone();
+                status["paused"] = serde_json::json!(inner.pause());
+                let status = serde_json::json!({
+                    "time": Utc::now(),
+                    "status": status,
+                });
+                format!("{status}\n")
+            }
+            "health" => format!("{}\n", health(&inner.status).1),
+            "servers" => {
+                let servers: Vec<_> = inner
+                    .servers
+                    .servers
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                format!("{}\n", serde_json::json!({ "servers": servers }))
+            }
+            request
+                if request == "resume" || request.split_whitespace().next() == Some("pause") =>
+            {
+                message(if privileged {
+                    inner.set_pause(request)
+                } else {
+                    Err("corrections can only be paused over the control socket".to_owned())
+                })
+            }
+            request if request.starts_with("servers ") => message(if privileged {
+                inner.servers.change(&request["servers ".len()..])
+            } else {
+                Err("servers can only be changed over the control socket".to_owned())
+            }),
+            // Lets load balancers and orchestrators probe the TCP listener over plain HTTP.
+            request if request.starts_with("GET /healthz") => {
+                let (ok, body) = health(&inner.status);
+                let status = if ok {
+                    "200 OK"
+                } else {
+                    "503 Service Unavailable"
+                };
+                let body = body.to_string();
+                format!(
+                    "HTTP/1.0 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                )
+            }
+            command => format!("error: unknown command '{command}'\n"),
+        };
+        (response, None)
+    }
+}
+
+fn message(response: Result<String, String>) -> String {
+    match response {
+        Ok(message) => format!("{}\n", serde_json::json!({ "message": message })),
+        Err(e) => format!("error: {e}\n"),
+    }
+}
+
+fn health(status: &serde_json::Value) -> (bool, serde_json::Value) {
+    let health = status["health"].as_str().unwrap_or("degraded");
+    (
+        health == "ok",
+        serde_json::json!({
+            "time": Utc::now(),
+            "health": health,
+            "holdover_error_ms": status["holdover_error_ms"],
+        }),
+    )
+}
+
+// Reads the one-line command a client opens with, without buffering past it.
+fn read_request(stream: &mut impl Read) -> io::Result<String> {
+    let mut request = Vec::new();
+    let mut byte = [0];
+    while request.len() < 256 {
+        if stream.read(&mut byte)? == 0 || byte[0] == b'\n' {
+            break;
+        }
+        request.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&request).trim().to_owned())
+}
+
+#[cfg(unix)]
+pub(crate) fn listen(path: &Path, events: &EventBus) -> Result<()> {
+    use std::os::unix::{fs::PermissionsExt, net::UnixListener};
+
+    if path.exists() {
+        std::fs::remove_file(path).context(format!("Unable to remove {}", path.display()))?;
+    }
+    let listener =
+        UnixListener::bind(path).context(format!("Unable to listen on {}", path.display()))?;
+    // Whoever can connect can pause the daemon and change its servers, so the socket is left to
+    // its owner and group whatever the umask.
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(SOCKET_MODE))
+        .context(format!("Unable to restrict access to {}", path.display()))?;
+    let events = events.clone();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let events = events.clone();
+            std::thread::spawn(move || {
+                if stream.set_read_timeout(Some(CLIENT_TIMEOUT)).is_err()
+                    || stream.set_write_timeout(Some(CLIENT_TIMEOUT)).is_err()
+                {
+                    return;
+                }
+                if let Ok(request) = read_request(&mut stream) {
+                    events.serve(&request, Box::new(stream), true);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn listen(_path: &Path, _events: &EventBus) -> Result<()> {
+    warn!("the control socket is only available on Unix-like systems, use --control-listen");
+    Ok(())
+}
+
+// Serves the same read-only commands as the control socket over TCP, for `tdctld fleet`, one
+// client at a time. Without `follow` every client is done with within the timeouts.
+pub(crate) fn listen_tcp(addr: SocketAddr, events: &EventBus) -> Result<()> {
+    let listener = TcpListener::bind(addr).context(format!("Unable to listen on {addr}"))?;
+    let events = events.clone();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            if stream.set_read_timeout(Some(CLIENT_TIMEOUT)).is_err()
+                || stream.set_write_timeout(Some(CLIENT_TIMEOUT)).is_err()
+            {
+                continue;
+            }
+            if let Ok(request) = read_request(&mut stream) {
+                events.serve(&request, Box::new(stream), false);
+            }
+        }
+    });
+    Ok(())
+}
+
+pub(crate) fn close(path: &Path) {
+    #[cfg(unix)]
+    if let Err(e) = std::fs::remove_file(path) {
+        warn!("unable to remove {}: {e}", path.display());
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(unix)]
+pub(crate) fn stream_events(path: &Path, follow: bool) -> Result<()> {
+    use std::{io::BufRead, io::BufReader, os::unix::net::UnixStream};
+
+    let mut stream = UnixStream::connect(path).context(format!(
+        "Unable to connect to {}, is the daemon running?",
+        path.display()
+    ))?;
+    writeln!(stream, "{}", if follow { "follow" } else { "events" })?;
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    for line in BufReader::new(stream).lines() {
+        writeln!(stdout, "{}", line?)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn query_status(path: &Path) -> Result<serde_json::Value> {
+    use std::{io::BufRead, io::BufReader, os::unix::net::UnixStream};
+
+    let mut stream = UnixStream::connect(path).context(format!(
+        "Unable to connect to {}, is the daemon running?",
+        path.display()
+    ))?;
+    writeln!(stream, "status")?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    let response: serde_json::Value =
+        serde_json::from_str(&response).context("The daemon sent an invalid status response")?;
+    Ok(response["status"].clone())
+}
+
+// Sends a command and returns the one line the daemon answers with.
+#[cfg(unix)]
+pub(crate) fn request(path: &Path, command: &str) -> Result<String> {
+    use std::{io::BufRead, io::BufReader, os::unix::net::UnixStream};
+
+    let mut stream = UnixStream::connect(path).context(format!(
+        "Unable to connect to {}, is the daemon running?",
+        path.display()
+    ))?;
+    writeln!(stream, "{command}")?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    Ok(response.trim_end().to_owned())
+}
+
+// Sends a command that answers with JSON, or with an error the daemon refused it for.
+pub(crate) fn command(path: &Path, command: &str) -> Result<serde_json::Value> {
+    let response = request(path, command)?;
+    if let Some(error) = response.strip_prefix("error: ") {
+        anyhow::bail!("{error}");
+    }
+    serde_json::from_str(&response).context("The daemon sent an invalid response")
+}
+
+#[cfg(not(unix))]
+pub(crate) fn request(_path: &Path, _command: &str) -> Result<String> {
+    anyhow::bail!("the control socket is only available on Unix-like systems")
+}
+
+#[cfg(not(unix))]
+pub(crate) fn query_status(_path: &Path) -> Result<serde_json::Value> {
+    anyhow::bail!("the control socket is only available on Unix-like systems")
+}
+
+#[cfg(not(unix))]
+pub(crate) fn stream_events(_path: &Path, _follow: bool) -> Result<()> {
+    anyhow::bail!("the control socket is only available on Unix-like systems")
+}
@@ -0,0 +1,121 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing_subscriber::fmt::MakeWriter;
+
+struct Seen {
+    since: Instant,
+    suppressed: u64,
+}
+
+// Drops log lines identical to one already written within the suppression window, and writes a
+// "message repeated" summary once the window has passed.
+pub(crate) struct LogDedup {
+    window: Duration,
+    seen: Mutex<HashMap<Vec<u8>, Seen>>,
+}
+
+impl LogDedup {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::default(),
+        }
+    }
+
+    fn write_line(&self, line: &[u8]) -> io::Result<()> {
+        let mut stderr = io::stderr().lock();
+        if self.window.is_zero() {
+            return stderr.write_all(line);
+        }
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let mut expired = Vec::new();
+        seen.retain(|line, entry| {
+            if now.duration_since(entry.since) < self.window {
+                return true;
+            }
+            if entry.suppressed > 0 {
+                expired.push((line.clone(), entry.suppressed));
+            }
+            false
+        });
+        for (line, suppressed) in expired {
+            self.write_summary(&mut stderr, &line, suppressed)?;
+        }
+        match seen.get_mut(line) {
+            Some(entry) => {
+                entry.suppressed += 1;
+                Ok(())
+            }
+            None => {
+                seen.insert(
+                    line.to_vec(),
+                    Seen {
+                        since: now,
+                        suppressed: 0,
+                    },
+                );
+                stderr.write_all(line)
+            }
+        }
+    }
+
+    fn write_summary(&self, out: &mut impl Write, line: &[u8], suppressed: u64) -> io::Result<()> {
+        let times = if suppressed == 1 { "time" } else { "times" };
+        write!(
+            out,
+            "message repeated {suppressed} {times} in the last {}: ",
+            describe_window(self.window)
+        )?;
+        out.write_all(line.trim_ascii_start())
+    }
+}
+
+fn describe_window(window: Duration) -> String {
+    match window.as_secs() {
+        3600 => "hour".to_owned(),
+        60 => "minute".to_owned(),
+        secs if secs % 3600 == 0 => format!("{}h", secs / 3600),
+        secs if secs % 60 == 0 => format!("{}m", secs / 60),
+        secs => format!("{secs}s"),
+    }
+}
+
+pub(crate) struct DedupWriter<'a> {
+    dedup: &'a LogDedup,
+    buf: Vec<u8>,
+}
+
+impl Write for DedupWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for DedupWriter<'_> {
+    fn drop(&mut self) {
+        if !self.buf.is_empty() {
+            let _ = self.dedup.write_line(&self.buf);
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogDedup {
+    type Writer = DedupWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        DedupWriter {
+            dedup: self,
+            buf: Vec::new(),
+        }
+    }
+}
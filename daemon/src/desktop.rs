@@ -0,0 +1,59 @@
+use anyhow::Result;
+use std::process::Command;
+
+// Shows a notification on the desktop of the user running tdctld. The text is passed in the
+// environment rather than spliced into a script, so it needs no quoting.
+#[cfg(windows)]
+pub(crate) fn notify(summary: &str, body: &str) -> Result<()> {
+    // Toasts need the ID of an installed app, PowerShell's is always there.
+    const SCRIPT: &str = r"
+[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] | Out-Null
+$template = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)
+$text = $template.GetElementsByTagName('text')
+$text.Item(0).AppendChild($template.CreateTextNode($env:TDCTLD_SUMMARY)) | Out-Null
+$text.Item(1).AppendChild($template.CreateTextNode($env:TDCTLD_BODY)) | Out-Null
+$toast = [Windows.UI.Notifications.ToastNotification]::new($template)
+[Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\WindowsPowerShell\v1.0\powershell.exe').Show($toast)
+";
+    crate::notify::pipe(
+        Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", "-"])
+            .env("TDCTLD_SUMMARY", summary)
+            .env("TDCTLD_BODY", body),
+        SCRIPT,
+    )
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn notify(summary: &str, body: &str) -> Result<()> {
+    run(Command::new("osascript")
+        .args([
+            "-e",
+            "display notification (system attribute \"TDCTLD_BODY\") \
+             with title (system attribute \"TDCTLD_SUMMARY\")",
+        ])
+        .env("TDCTLD_SUMMARY", summary)
+        .env("TDCTLD_BODY", body))
+}
+
+// Anything else with a desktop speaks freedesktop.org notifications.
+#[cfg(not(any(windows, target_os = "macos")))]
+pub(crate) fn notify(summary: &str, body: &str) -> Result<()> {
+    run(Command::new("notify-send").args(["--app-name=tdctld", "--", summary, body]))
+}
+
+#[cfg(not(windows))]
+fn run(command: &mut Command) -> Result<()> {
+    use anyhow::{bail, Context};
+    use std::process::Stdio;
+
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .stdout(Stdio::null())
+        .status()
+        .context(format!("Unable to run {program}"))?;
+    if !status.success() {
+        bail!("{program} failed ({status})");
+    }
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use lunartick::NTPTimestamp;
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::Instant,
+};
+use tracing::{info, warn};
+
+const MAX_PACKET_LENGTH: usize = 1024;
+const HEADER_LENGTH: usize = 48;
+const MODE_MASK: u8 = 0b00_000_111;
+const VERSION_MASK: u8 = 0b00_111_000;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+// About a microsecond, as a power of two of seconds.
+const PRECISION: i8 = -20;
+const REFERENCE_ID: &[u8; 4] = b"FAKE";
+
+// A simulated timeline: `start` when the server starts, running `rate` times as fast as the
+// monotonic clock after that. A rate of 0 stops it at `start`.
+struct FakeTime {
+    anchor: Instant,
+    start: DateTime<Utc>,
+    rate: f64,
+}
+
+impl FakeTime {
+    fn now(&self) -> Option<DateTime<Utc>> {
+        let elapsed = self.anchor.elapsed().as_secs_f64() * self.rate * 1e6;
+        // Casting saturates, checked_add_signed catches the rest.
+        self.start
+            .checked_add_signed(ChronoDuration::microseconds(elapsed as i64))
+    }
+}
+
+// Answers NTP client queries with the simulated time instead of the real one, for test labs that
+// drive their machines to another date through their usual NTP configuration. Every client is
+// told the same time, so they stay in step with each other however far from the real time it is.
+pub(crate) fn serve(
+    listen: SocketAddr,
+    start: Option<DateTime<Utc>>,
+    rate: f64,
+    stratum: u8,
+) -> Result<()> {
+    let socket = UdpSocket::bind(listen).context(format!("Unable to listen on {listen}"))?;
+    let fake = FakeTime {
+        anchor: Instant::now(),
+        start: start.unwrap_or_else(Utc::now),
+        rate,
+    };
+    info!(
+        "serving fake time on {listen} from {} at {rate}x",
+        fake.start.to_rfc3339()
+    );
+    let mut packet = [0; MAX_PACKET_LENGTH];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut packet) {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("unable to receive query: {e}");
+                continue;
+            }
+        };
+        let received = fake.now();
+        if len < HEADER_LENGTH || packet[0] & MODE_MASK != MODE_CLIENT {
+            continue;
+        }
+        let Some((received, transmit)) = received.zip(fake.now()) else {
+            warn!("fake time is out of range, not answering {peer}");
+            continue;
+        };
+        let reply = response(&packet[..len], stratum, received, transmit);
+        if let Err(e) = socket.send_to(&reply, peer) {
+            warn!("unable to answer {peer}: {e}");
+        }
+    }
+}
+
+fn response(
+    query: &[u8],
+    stratum: u8,
+    received: DateTime<Utc>,
+    transmit: DateTime<Utc>,
+) -> [u8; HEADER_LENGTH] {
+    let mut reply = [0; HEADER_LENGTH];
+    // No leap second warning, the client's version, and the poll interval it asked for.
+    reply[0] = (query[0] & VERSION_MASK) | MODE_SERVER;
+    reply[1] = stratum;
+    reply[2] = query[2];
+    reply[3] = PRECISION as u8;
+    reply[12..16].copy_from_slice(REFERENCE_ID);
+    let received = NTPTimestamp::local(received).as_u64().to_be_bytes();
+    reply[16..24].copy_from_slice(&received);
+    reply[24..32].copy_from_slice(&query[40..48]);
+    reply[32..40].copy_from_slice(&received);
+    let transmit = NTPTimestamp::local(transmit).as_u64().to_be_bytes();
+    reply[40..48].copy_from_slice(&transmit);
+    reply
+}
@@ -0,0 +1,166 @@
+use crate::{control, StatusFormat};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+use tracing::{info, warn};
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+struct Member {
+    host: String,
+    // Remote clock minus local clock, halfway through the request.
+    skew_ms: f64,
+    rtt_ms: f64,
+    status: serde_json::Value,
+}
+
+fn query(host: &str) -> Result<Member> {
+    let target = if host
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+    {
+        host.to_owned()
+    } else {
+        format!("{host}:{}", control::DEFAULT_PORT)
+    };
+    let addr = target
+        .to_socket_addrs()
+        .context(format!("Unable to resolve {target}"))?
+        .next()
+        .context(format!("{target} did not resolve to an address"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)
+        .context(format!("Unable to connect to {target}"))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let sent = Utc::now();
+    writeln!(stream, "status")?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    let received = Utc::now();
+    let response: serde_json::Value = serde_json::from_str(&response)
+        .context(format!("{target} sent an invalid status response"))?;
+    let Some(time) = response["time"]
+        .as_str()
+        .and_then(|time| time.parse::<DateTime<Utc>>().ok())
+    else {
+        bail!("{target} did not report its time");
+    };
+    let rtt = received - sent;
+    let midpoint = sent + rtt / 2;
+    let millis = |d: chrono::Duration| d.num_microseconds().unwrap_or(i64::MAX) as f64 / 1e3;
+    Ok(Member {
+        host: host.to_owned(),
+        skew_ms: millis(time - midpoint),
+        rtt_ms: millis(rtt),
+        status: response["status"].clone(),
+    })
+}
+
+pub(crate) fn median(values: &mut [f64]) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    match values.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((values[mid - 1] + values[mid]) / 2.0),
+        _ => Some(values[mid]),
+    }
+}
+
+pub(crate) fn fleet(hosts: &[String], max_skew_ms: f64, format: &StatusFormat) -> Result<()> {
+    let handles: Vec<_> = hosts
+        .iter()
+        .cloned()
+        .map(|host| std::thread::spawn(move || (query(&host), host)))
+        .collect();
+    let mut members = Vec::new();
+    let mut failures = Vec::new();
+    for handle in handles {
+        match handle.join() {
+            Ok((Ok(member), _)) => members.push(member),
+            Ok((Err(e), host)) => failures.push((host, format!("{e:#}"))),
+            Err(_) => bail!("a fleet query panicked"),
+        }
+    }
+    let fleet_skew =
+        median(&mut members.iter().map(|m| m.skew_ms).collect::<Vec<_>>()).unwrap_or(0.0);
+    match format {
+        StatusFormat::Text => {
+            for member in &members {
+                let deviation = member.skew_ms - fleet_skew;
+                let drift = member.status["drift_ppm"]
+                    .as_f64()
+                    .map_or_else(|| "unknown".to_owned(), |ppm| format!("{ppm:+.3} ppm"));
+                let last_sync = member.status["last_sync"].as_str().unwrap_or("never");
+                let line = format!(
+                    "{} => {:+.3}ms from fleet median (rtt {:.3}ms, drift {drift}, last sync {last_sync})",
+                    member.host, deviation, member.rtt_ms
+                );
+                if deviation.abs() > max_skew_ms {
+                    warn!("{line}");
+                } else {
+                    info!("{line}");
+                }
+            }
+            for (host, error) in &failures {
+                warn!("{host} => ? [{error}]");
+            }
+            info!(
+                "fleet median => {fleet_skew:+.3}ms from this host ({} of {} reachable)",
+                members.len(),
+                hosts.len()
+            );
+        }
+        StatusFormat::Json => {
+            let members: Vec<_> = members
+                .iter()
+                .map(|member| {
+                    serde_json::json!({
+                        "host": member.host,
+                        "skew_ms": member.skew_ms,
+                        "deviation_ms": member.skew_ms - fleet_skew,
+                        "rtt_ms": member.rtt_ms,
+                        "status": member.status,
+                    })
+                })
+                .collect();
+            let failures: Vec<_> = failures
+                .iter()
+                .map(|(host, error)| serde_json::json!({ "host": host, "error": error }))
+                .collect();
+            let json = serde_json::json!({
+                "median_skew_ms": fleet_skew,
+                "members": members,
+                "unreachable": failures,
+            });
+            println!("{json}");
+        }
+        StatusFormat::Prometheus => {
+            let mut out = String::from(
+                "# HELP tdctld_fleet_skew_seconds Clock difference from the fleet median\n\
+                 # TYPE tdctld_fleet_skew_seconds gauge\n",
+            );
+            for member in &members {
+                out.push_str(&format!(
+                    "tdctld_fleet_skew_seconds{{host=\"{}\"}} {}\n",
+                    member.host,
+                    (member.skew_ms - fleet_skew) / 1e3
+                ));
+            }
+            out.push_str(
+                "# HELP tdctld_fleet_up Whether the host answered the status query\n\
+                 # TYPE tdctld_fleet_up gauge\n",
+            );
+            for member in &members {
+                out.push_str(&format!("tdctld_fleet_up{{host=\"{}\"}} 1\n", member.host));
+            }
+            for (host, _) in &failures {
+                out.push_str(&format!("tdctld_fleet_up{{host=\"{host}\"}} 0\n"));
+            }
+            print!("{out}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use lunartick::{Offset, TestResults};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+// Cycles older than this are dropped when the daemon starts.
+const RETENTION_DAYS: i64 = 90;
+
+// One line of the history file per sync cycle, for `tdctld report`.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Cycle {
+    pub(crate) time: DateTime<Utc>,
+    pub(crate) offset_ms: Option<f64>,
+    // Offset of each server queried, None if it did not answer.
+    #[serde(default)]
+    pub(crate) servers: BTreeMap<String, Option<f64>>,
+    pub(crate) correction_ms: Option<f64>,
+    #[serde(default)]
+    pub(crate) stepped: bool,
+    pub(crate) error: Option<String>,
+    // Jumps, stalls or pauses of the local clock since the previous cycle.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) anomalies: Vec<String>,
+}
+
+impl Cycle {
+    pub(crate) fn completed(results: &TestResults, applied: Option<Offset>, stepped: bool) -> Self {
+        Self {
+            time: Utc::now(),
+            offset_ms: results
+                .get_combined_offset()
+                .ok()
+                .map(|offset| offset.as_millis_f64()),
+            servers: results
+                .get_all_results()
+                .map(|(server, result)| {
+                    (
+                        server.to_owned(),
+                        result.as_ref().ok().map(Offset::as_millis_f64),
+                    )
+                })
+                .collect(),
+            correction_ms: applied.map(|applied| applied.as_millis_f64()),
+            stepped: stepped && applied.is_some(),
+            error: None,
+            anomalies: Vec::new(),
+        }
+    }
+
+    pub(crate) fn failed(error: String) -> Self {
+        Self {
+            time: Utc::now(),
+            offset_ms: None,
+            servers: BTreeMap::new(),
+            correction_ms: None,
+            stepped: false,
+            error: Some(error),
+            anomalies: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_anomalies(mut self, anomalies: Vec<String>) -> Self {
+        self.anomalies = anomalies;
+        self
+    }
+}
+
+pub(crate) fn default_path() -> PathBuf {
+    crate::state::default_path().with_file_name("history.jsonl")
+}
+
+pub(crate) struct History {
+    file: File,
+}
+
+impl History {
+    // Rewrites the file without the cycles that have aged out before appending to it.
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context(format!("Unable to create {}", dir.display()))?;
+        }
+        let kept = read(path, Utc::now() - chrono::Duration::days(RETENTION_DAYS))?;
+        let tmp = path.with_extension("jsonl.tmp");
+        let mut contents = String::new();
+        for cycle in &kept {
+            contents.push_str(&serde_json::to_string(cycle)?);
+            contents.push('\n');
+        }
+        std::fs::write(&tmp, contents).context(format!("Unable to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).context(format!("Unable to replace {}", path.display()))?;
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .context(format!("Unable to open {}", path.display()))?;
+        Ok(Self { file })
+    }
+
+    pub(crate) fn append(&mut self, cycle: &Cycle) -> Result<()> {
+        writeln!(self.file, "{}", serde_json::to_string(cycle)?)
+            .context("Unable to append to the history file")
+    }
+}
+
+// Cycles since `since`, oldest first. Lines that do not parse, such as one cut short by a crash,
+// are skipped.
+pub(crate) fn read(path: &Path, since: DateTime<Utc>) -> Result<Vec<Cycle>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(format!("Unable to read {}", path.display())),
+    };
+    let mut cycles = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.context(format!("Unable to read {}", path.display()))?;
+        if let Ok(cycle) = serde_json::from_str::<Cycle>(&line) {
+            if cycle.time >= since {
+                cycles.push(cycle);
+            }
+        }
+    }
+    Ok(cycles)
+}
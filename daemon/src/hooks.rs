@@ -0,0 +1,99 @@
+use crate::control::{Event, EventBus};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+// Hook protocol: every executable in the hooks directory is run once per hook event, with a JSON
+// object on stdin whose "event" field is sync_result, step_applied or source_failed.
+fn payload(event: &Event) -> Option<serde_json::Value> {
+    let time = Utc::now();
+    Some(match event {
+        Event::SyncCompleted { offset_ms } => serde_json::json!({
+            "event": "sync_result",
+            "time": time,
+            "ok": true,
+            "offset_ms": offset_ms,
+        }),
+        Event::SyncFailed { error } => serde_json::json!({
+            "event": "sync_result",
+            "time": time,
+            "ok": false,
+            "error": error,
+        }),
+        Event::ClockStepped { offset_ms } => serde_json::json!({
+            "event": "step_applied",
+            "time": time,
+            "offset_ms": offset_ms,
+        }),
+        Event::ServerRejected {
+            server,
+            reason,
+            falseticker: false,
+        } => serde_json::json!({
+            "event": "source_failed",
+            "time": time,
+            "server": server,
+            "reason": reason,
+        }),
+        _ => return None,
+    })
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    metadata.is_file()
+}
+
+fn hooks(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut hooks: Vec<_> = std::fs::read_dir(dir)
+        .context(format!("Unable to read hooks directory {}", dir.display()))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            !path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+                && is_executable(path)
+        })
+        .collect();
+    hooks.sort();
+    Ok(hooks)
+}
+
+pub(crate) fn watch(dir: PathBuf, events: &EventBus) -> Result<()> {
+    hooks(&dir)?;
+    events.subscribe(move |event| {
+        let Some(payload) = payload(event) else {
+            return;
+        };
+        // Rescanned for every event so hooks can be added and removed while the daemon runs.
+        let hooks = match hooks(&dir) {
+            Ok(hooks) => hooks,
+            Err(e) => {
+                warn!("{e:#}");
+                return;
+            }
+        };
+        let input = format!("{payload}\n");
+        for hook in hooks {
+            let input = input.clone();
+            std::thread::spawn(move || {
+                match crate::notify::pipe(&mut std::process::Command::new(&hook), &input) {
+                    Ok(()) => debug!("ran hook {}", hook.display()),
+                    Err(e) => warn!("hook {} failed: {e:#}", hook.display()),
+                }
+            });
+        }
+    });
+    Ok(())
+}
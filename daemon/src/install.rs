@@ -0,0 +1,267 @@
+use anyhow::Result;
+use tracing::info;
+
+#[cfg_attr(not(any(target_os = "linux", windows)), allow(dead_code))]
+pub(crate) struct InstallOptions {
+    pub(crate) servers: Option<Vec<String>>,
+    pub(crate) timeout: u64,
+    pub(crate) replace_w32time: bool,
+    pub(crate) replace_time_daemons: bool,
+}
+
+#[cfg(any(target_os = "linux", windows))]
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    use anyhow::{bail, Context};
+
+    let status = std::process::Command::new(program)
+        .args(args)
+        .status()
+        .context(format!("Unable to run {program}"))?;
+    if !status.success() {
+        bail!("{program} {} failed ({status})", args.join(" "));
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", windows))]
+fn daemon_command(options: &InstallOptions) -> Result<String> {
+    use anyhow::Context;
+
+    let exe = std::env::current_exe().context("Unable to locate the tdctld executable")?;
+    let mut args = vec![exe.display().to_string(), "daemon".to_owned()];
+    for server in options.servers.iter().flatten() {
+        args.push("--servers".to_owned());
+        args.push(server.clone());
+    }
+    args.push(options.timeout.to_string());
+    Ok(args
+        .iter()
+        .map(|arg| format!("\"{arg}\""))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{daemon_command, run, InstallOptions};
+    use anyhow::{bail, Context, Result};
+    use std::process::{Command, Stdio};
+    use tracing::{info, warn};
+
+    const UNIT_PATH: &str = "/etc/systemd/system/tdctld.service";
+    const STATE_DIR: &str = "/var/lib/tdctld";
+    const REPLACED_UNITS: &str = "/var/lib/tdctld/replaced-units";
+    const TIME_DAEMONS: &[&str] = &[
+        "systemd-timesyncd.service",
+        "chronyd.service",
+        "chrony.service",
+        "ntpd.service",
+        "ntp.service",
+    ];
+
+    fn systemctl_succeeds(args: &[&str]) -> bool {
+        Command::new("systemctl")
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    fn replace_time_daemons() -> Result<()> {
+        let running: Vec<_> = TIME_DAEMONS
+            .iter()
+            .copied()
+            .filter(|unit| {
+                systemctl_succeeds(&["is-enabled", "--quiet", unit])
+                    || systemctl_succeeds(&["is-active", "--quiet", unit])
+            })
+            .collect();
+        if running.is_empty() {
+            info!("no other time daemon is enabled");
+            return Ok(());
+        }
+        std::fs::create_dir_all(STATE_DIR).context(format!("Unable to create {STATE_DIR}"))?;
+        std::fs::write(REPLACED_UNITS, running.join("\n"))
+            .context(format!("Unable to write {REPLACED_UNITS}"))?;
+        for unit in &running {
+            run("systemctl", &["disable", "--now", unit])?;
+            info!("stopped and disabled {unit}");
+        }
+        Ok(())
+    }
+
+    fn restore_time_daemons() -> Result<()> {
+        let Ok(units) = std::fs::read_to_string(REPLACED_UNITS) else {
+            return Ok(());
+        };
+        for unit in units.lines().filter(|unit| !unit.is_empty()) {
+            match run("systemctl", &["enable", "--now", unit]) {
+                Ok(()) => info!("re-enabled {unit}"),
+                Err(e) => warn!("unable to re-enable {unit}: {e:#}"),
+            }
+        }
+        std::fs::remove_file(REPLACED_UNITS).context(format!("Unable to remove {REPLACED_UNITS}"))
+    }
+
+    pub(super) fn install(options: &InstallOptions) -> Result<()> {
+        if options.replace_w32time {
+            bail!("--replace-w32time is only available on Windows");
+        }
+        let exec_start = daemon_command(options)?;
+        if options.replace_time_daemons {
+            replace_time_daemons()?;
+        }
+        // The daemon reports ready after its first successful sync, so units ordered after
+        // time-sync.target wait for the clock to actually be right. Offline that can take forever.
+        let unit = format!(
+            "[Unit]\n\
+             Description=tdctld time synchronization daemon\n\
+             Wants=network-online.target time-sync.target\n\
+             After=network-online.target time-set.target\n\
+             Before=time-sync.target\n\
+             \n\
+             [Service]\n\
+             Type=notify\n\
+             TimeoutStartSec=infinity\n\
+             ExecStart={exec_start}\n\
+             Restart=on-failure\n\
+             \n\
+             [Install]\n\
+             WantedBy=multi-user.target\n"
+        );
+        std::fs::write(UNIT_PATH, unit).context(format!("Unable to write {UNIT_PATH}"))?;
+        run("systemctl", &["daemon-reload"])?;
+        run(
+            "systemctl",
+            &["enable", "--now", "--no-block", "tdctld.service"],
+        )?;
+        info!("installed and started tdctld.service");
+        Ok(())
+    }
+
+    pub(super) fn uninstall() -> Result<()> {
+        run("systemctl", &["disable", "--now", "tdctld.service"])?;
+        std::fs::remove_file(UNIT_PATH).context(format!("Unable to remove {UNIT_PATH}"))?;
+        run("systemctl", &["daemon-reload"])?;
+        info!("removed tdctld.service");
+        restore_time_daemons()
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::{daemon_command, run, InstallOptions};
+    use anyhow::{bail, Context, Result};
+    use std::{path::PathBuf, process::Command};
+    use tracing::{info, warn};
+
+    const TASK_NAME: &str = "tdctld";
+
+    fn state_dir() -> PathBuf {
+        std::env::var_os("ProgramData")
+            .map_or_else(|| PathBuf::from(r"C:\ProgramData"), PathBuf::from)
+            .join("tdctld")
+    }
+
+    fn w32time_state() -> PathBuf {
+        state_dir().join("w32time-start-type")
+    }
+
+    fn w32time_start_type() -> Result<&'static str> {
+        let output = Command::new("sc.exe")
+            .args(["qc", "w32time"])
+            .output()
+            .context("Unable to run sc.exe")?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        let line = output
+            .lines()
+            .find(|line| line.trim_start().starts_with("START_TYPE"))
+            .context("Unable to read the start type of the Windows Time service")?;
+        Ok(match line {
+            _ if line.contains("DELAYED") => "delayed-auto",
+            _ if line.contains("AUTO_START") => "auto",
+            _ if line.contains("DISABLED") => "disabled",
+            _ => "demand",
+        })
+    }
+
+    fn replace_w32time() -> Result<()> {
+        let start_type = w32time_start_type()?;
+        std::fs::create_dir_all(state_dir())?;
+        std::fs::write(w32time_state(), start_type)?;
+        if run("sc.exe", &["stop", "w32time"]).is_err() {
+            info!("Windows Time service was not running");
+        }
+        run("sc.exe", &["config", "w32time", "start=", "disabled"])?;
+        info!("stopped and disabled the Windows Time service (was {start_type})");
+        Ok(())
+    }
+
+    fn restore_w32time() -> Result<()> {
+        let path = w32time_state();
+        let Ok(start_type) = std::fs::read_to_string(&path) else {
+            return Ok(());
+        };
+        let start_type = start_type.trim();
+        run("sc.exe", &["config", "w32time", "start=", start_type])?;
+        if start_type != "disabled" && run("sc.exe", &["start", "w32time"]).is_err() {
+            warn!("unable to start the Windows Time service");
+        }
+        std::fs::remove_file(path)?;
+        info!("restored the Windows Time service ({start_type})");
+        Ok(())
+    }
+
+    pub(super) fn install(options: &InstallOptions) -> Result<()> {
+        if options.replace_time_daemons {
+            bail!("--replace-time-daemons is only available on Linux, use --replace-w32time");
+        }
+        let command = daemon_command(options)?;
+        if options.replace_w32time {
+            self::replace_w32time()?;
+        }
+        run(
+            "schtasks.exe",
+            &[
+                "/Create", "/TN", TASK_NAME, "/TR", &command, "/SC", "ONSTART", "/RU", "SYSTEM",
+                "/F",
+            ],
+        )?;
+        run("schtasks.exe", &["/Run", "/TN", TASK_NAME])?;
+        info!("installed and started the {TASK_NAME} scheduled task");
+        Ok(())
+    }
+
+    pub(super) fn uninstall() -> Result<()> {
+        if run("schtasks.exe", &["/End", "/TN", TASK_NAME]).is_err() {
+            info!("{TASK_NAME} was not running");
+        }
+        run("schtasks.exe", &["/Delete", "/TN", TASK_NAME, "/F"])?;
+        info!("removed the {TASK_NAME} scheduled task");
+        restore_w32time()
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    use super::InstallOptions;
+    use anyhow::{bail, Result};
+
+    pub(super) fn install(_options: &InstallOptions) -> Result<()> {
+        bail!("install is not supported on this platform")
+    }
+
+    pub(super) fn uninstall() -> Result<()> {
+        bail!("uninstall is not supported on this platform")
+    }
+}
+
+pub(crate) fn install(options: &InstallOptions) -> Result<()> {
+    info!("installing tdctld as a system service");
+    platform::install(options)
+}
+
+pub(crate) fn uninstall() -> Result<()> {
+    platform::uninstall()
+}
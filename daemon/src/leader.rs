@@ -0,0 +1,70 @@
+use anyhow::{Context, Result};
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::Write,
+    path::PathBuf,
+};
+use tracing::{info, warn};
+
+// Only the instance holding the lock file applies corrections. The lock is released by the OS
+// when the leader exits, so a standby takes over on its next cycle.
+pub(crate) struct Leadership {
+    path: PathBuf,
+    lock: File,
+    leading: bool,
+}
+
+impl Leadership {
+    pub(crate) fn new(path: PathBuf) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context(format!("Unable to create {}", dir.display()))?;
+        }
+        let lock = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .context(format!("Unable to open lock file {}", path.display()))?;
+        let mut leadership = Self {
+            path,
+            lock,
+            leading: false,
+        };
+        if !leadership.is_leader() {
+            info!(
+                "another instance holds {}, standing by in report-only mode",
+                leadership.path.display()
+            );
+        }
+        Ok(leadership)
+    }
+
+    pub(crate) fn is_leader(&mut self) -> bool {
+        if self.leading {
+            return true;
+        }
+        match self.lock.try_lock() {
+            Ok(()) => {
+                self.leading = true;
+                info!(
+                    "holding {}, this instance is the leader",
+                    self.path.display()
+                );
+                let pid = std::process::id().to_string();
+                if let Err(e) = self
+                    .lock
+                    .set_len(0)
+                    .and_then(|()| self.lock.write_all(pid.as_bytes()))
+                {
+                    warn!("unable to record pid in {}: {e}", self.path.display());
+                }
+            }
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(e)) => {
+                warn!("unable to lock {}: {e}", self.path.display());
+            }
+        }
+        self.leading
+    }
+}
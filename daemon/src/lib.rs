@@ -0,0 +1,2598 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Local, Utc};
+use clap::{clap_derive::ArgEnum, Parser, Subcommand};
+use control::{Event, EventBus};
+use lunartick::{
+    engine::{Correction, Explanation, SyncEngine},
+    Clock, ClockBackend, ClockWrite, FailureKind, KernelSyncStatus, LunartickError, NTPClient,
+    Offset, RecordingClock, ServerTransport, SystemClock, TestResults,
+};
+pub use scan::Subnet;
+pub use severity::OffsetLevels;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use state::DaemonState;
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{error, info, warn};
+
+mod control;
+mod dedup;
+mod desktop;
+mod fake;
+mod fleet;
+mod history;
+mod hooks;
+mod install;
+mod leader;
+mod monitor;
+mod networkd;
+mod notify;
+mod profile;
+mod relay;
+mod report;
+mod rpc;
+mod rtc;
+mod scan;
+mod schedule;
+mod servers;
+mod severity;
+mod shadow;
+mod state;
+mod stats;
+mod systemd;
+mod thermal;
+mod timens;
+mod tracking;
+mod watchdog;
+mod window;
+
+use install::InstallOptions;
+
+// Installs the log subscriber the tdctld binary uses, which only the daemon deduplicates. Callers
+// that embed the commands may install their own instead.
+pub fn init_logging(command: &Commands) {
+    let dedup_window = match command {
+        Commands::Daemon(daemon) => Duration::from_secs(daemon.log_dedup_window),
+        _ => Duration::ZERO,
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(dedup::LogDedup::new(dedup_window))
+        .without_time()
+        .init();
+}
+
+// Time kept back from a --max-runtime budget for applying the correction once the queries are done.
+const APPLY_RESERVE: Duration = Duration::from_millis(500);
+
+// Exits the process once --max-runtime has passed, should anything hang past the deadline run cuts
+// outstanding queries short at. Only the binary does this, callers embedding the commands decide
+// for themselves what to do about a command that hangs.
+pub fn exit_after_max_runtime(max_runtime: u64) {
+    let deadline = Instant::now() + Duration::from_secs(max_runtime);
+    std::thread::spawn(move || {
+        std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        error!("Giving up after --max-runtime {max_runtime}s");
+        std::process::exit(1);
+    });
+}
+
+// What a command produced, besides what it printed.
+#[derive(Debug)]
+pub enum Outcome {
+    Done,
+    // The time `get` read, or the one `convert` parsed.
+    Time(Clock),
+    // What `get --compare` measured.
+    Measured(TestResults),
+    Synced(SyncReport),
+    // What `sync --boot` did. Boot is held up until it succeeds, so it fails unless the clock was
+    // corrected, rather than letting time-sync.target be reached with the clock unset.
+    BootSynced(SyncReport),
+    // The process should exit with this code, as `ns-run` passes on that of its command.
+    Exit(i32),
+}
+
+impl Outcome {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::Exit(code) => *code,
+            Outcome::BootSynced(report) if report.applied.is_none() => 1,
+            _ => 0,
+        }
+    }
+}
+
+// Queries the servers, within what is left of the deadline if there is one.
+fn measure(
+    ntp_client: &NTPClient,
+    deadline: Option<Instant>,
+) -> Result<TestResults, LunartickError> {
+    match deadline {
+        Some(deadline) => ntp_client.test_within(
+            deadline
+                .saturating_duration_since(Instant::now())
+                .saturating_sub(APPLY_RESERVE),
+        ),
+        None => ntp_client.test(),
+    }
+}
+
+// Runs a command as the tdctld binary would, e.g. with
+// `run(Args::try_parse_from(["tdctld", "get", "-c"])?)`, and returns what it produced.
+pub fn run(args: Args) -> Result<Outcome> {
+    let deadline = args
+        .max_runtime
+        .map(|max_runtime| Instant::now() + Duration::from_secs(max_runtime));
+    match args.command {
+        Commands::Get {
+            std,
+            format,
+            sync_status: true,
+            ..
+        } => sync_status(format.or(std).unwrap_or(GetDTFormats::Debug))?,
+        Commands::Get {
+            std,
+            format,
+            compare: false,
+            ..
+        } => {
+            return Ok(Outcome::Time(get(format
+                .or(std)
+                .unwrap_or(GetDTFormats::Debug))))
+        }
+        Commands::Get {
+            std,
+            format,
+            servers,
+            query,
+            detail,
+            ..
+        } => {
+            return compare(
+                format.or(std).unwrap_or(GetDTFormats::Debug),
+                &ntp_client(servers, &query)?,
+                detail,
+                deadline,
+            )
+            .map(Outcome::Measured)
+        }
+        Commands::Set {
+            std,
+            datetime,
+            su,
+            local_time,
+        } => set(std, datetime, su, local_time)?,
+        Commands::Convert { from, to, value } => {
+            return convert(&from, &to, &value).map(Outcome::Time)
+        }
+        Commands::Status {
+            format,
+            detail,
+            samples,
+            explain,
+            control_socket,
+        } => status(
+            format,
+            &control_socket.unwrap_or_else(control::default_path),
+            detail,
+            samples,
+            explain,
+        )?,
+        Commands::Servers {
+            command,
+            control_socket,
+        } => {
+            let command = match command {
+                ServersCommand::List => "servers".to_owned(),
+                ServersCommand::Add { server, persist } => {
+                    format!(
+                        "servers add {server}{}",
+                        if persist { " persist" } else { "" }
+                    )
+                }
+                ServersCommand::Remove { server, persist } => {
+                    format!(
+                        "servers remove {server}{}",
+                        if persist { " persist" } else { "" }
+                    )
+                }
+            };
+            servers::request(
+                &control_socket.unwrap_or_else(control::default_path),
+                &command,
+            )?;
+        }
+        Commands::Pause {
+            duration,
+            control_socket,
+        } => {
+            let command = match duration {
+                Some(duration) => format!("pause {}", duration.num_seconds()),
+                None => "pause".to_owned(),
+            };
+            let response = control::command(
+                &control_socket.unwrap_or_else(control::default_path),
+                &command,
+            )?;
+            info!("{}", response["message"].as_str().unwrap_or_default());
+        }
+        Commands::Resume { control_socket } => {
+            let response = control::command(
+                &control_socket.unwrap_or_else(control::default_path),
+                "resume",
+            )?;
+            info!("{}", response["message"].as_str().unwrap_or_default());
+        }
+        Commands::Tracking {
+            csv,
+            control_socket,
+        } => tracking::tracking(&control_socket.unwrap_or_else(control::default_path), csv)?,
+        Commands::Stats {
+            format,
+            within_ms,
+            control_socket,
+        } => stats::stats(
+            format,
+            &control_socket.unwrap_or_else(control::default_path),
+            within_ms,
+        )?,
+        Commands::Report {
+            since,
+            format,
+            history_file,
+        } => report::report(
+            &history_file.unwrap_or_else(history::default_path),
+            since,
+            format,
+        )?,
+        Commands::Rtc {
+            command:
+                RtcCommand::Compare {
+                    device,
+                    format,
+                    state_file,
+                    timescale,
+                },
+        } => rtc::compare(
+            &device,
+            &state_file.unwrap_or_else(rtc::default_state_path),
+            timescale.map(Into::into),
+            format,
+        )?,
+        Commands::Install {
+            servers,
+            timeout,
+            replace_w32time,
+            replace_time_daemons,
+        } => install::install(&InstallOptions {
+            servers: servers.map(|servers| servers.iter().map(ToString::to_string).collect()),
+            timeout,
+            replace_w32time,
+            replace_time_daemons,
+        })?,
+        Commands::Uninstall => install::uninstall()?,
+        Commands::Events {
+            follow,
+            control_socket,
+        } => control::stream_events(
+            &control_socket.unwrap_or_else(control::default_path),
+            follow,
+        )?,
+        Commands::Fleet {
+            hosts,
+            max_skew_ms,
+            format,
+        } => fleet::fleet(&hosts, max_skew_ms, &format)?,
+        Commands::Relay { listen, upstream } => relay::relay(listen, &upstream)?,
+        Commands::FakeServer {
+            listen,
+            start,
+            rate,
+            stratum,
+        } => fake::serve(listen, start, rate, stratum)?,
+        Commands::NsRun { shift, command } => {
+            return timens::run(shift, &command).map(Outcome::Exit)
+        }
+        Commands::Scan {
+            subnet,
+            port,
+            concurrency,
+            timeout_ms,
+            threshold_ms,
+            format,
+        } => scan::scan(
+            subnet,
+            port,
+            concurrency,
+            Duration::from_millis(timeout_ms),
+            threshold_ms,
+            &format,
+        )?,
+        Commands::Monitor {
+            servers,
+            query,
+            interval,
+            threshold_ms,
+            notify,
+        } => monitor::monitor(
+            &ntp_client(servers, &query)?,
+            Duration::from_secs(interval),
+            threshold_ms,
+            notify,
+        )?,
+        Commands::ServeStdio { servers, query, su } => {
+            rpc::serve_stdio(ntp_client(servers, &query)?, &query, su)?;
+        }
+        Commands::Sync {
+            servers,
+            query,
+            su,
+            offset_levels,
+            detail,
+            wait_for_network,
+            boot,
+            min_networks,
+            attack_threshold_ms,
+            update_rtc,
+        } => {
+            let mut ntp_client = ntp_client(servers, &query)?;
+            if let Some(secs) = wait_for_network {
+                let mut timeout = Duration::from_secs(secs);
+                if let Some(deadline) = deadline {
+                    timeout = timeout.min(deadline.saturating_duration_since(Instant::now()));
+                }
+                if !ntp_client.wait_for_network(timeout) {
+                    warn!("no server was reachable after {secs}s, trying anyway");
+                }
+            }
+            if boot {
+                ntp_client = ntp_client.with_burst(BOOT_BURST);
+            }
+            let report = sync(
+                &ntp_client,
+                su,
+                &offset_levels.unwrap_or_default(),
+                &mut attack_guard(
+                    SyncEngine::new().with_min_networks(min_networks),
+                    attack_threshold_ms,
+                ),
+                detail,
+                deadline,
+                false,
+                None,
+            )?;
+            if (boot || update_rtc) && report.applied.is_some() {
+                write_rtc();
+            }
+            if !boot {
+                return Ok(Outcome::Synced(report));
+            }
+            if report.applied.is_none() {
+                error!("the clock was not corrected, failing the boot sync");
+            }
+            return Ok(Outcome::BootSynced(report));
+        }
+        Commands::Daemon(args) => daemon(*args)?,
+    }
+    Ok(Outcome::Done)
+}
+
+// Samples per server in a boot sync, enough to pick one that was not delayed by a congested link.
+const BOOT_BURST: u8 = 4;
+
+// Not every machine has an RTC, VMs and containers often do not, so failing to set it is only
+// worth a warning.
+fn write_rtc() {
+    let device = Path::new(lunartick::DEFAULT_RTC_DEVICE);
+    let written = lunartick::RtcTimescale::from_adjtime(Path::new(lunartick::ADJTIME_PATH))
+        .and_then(|timescale| lunartick::write_rtc(device, timescale));
+    match written {
+        Ok(time) => info!("hardware clock => set to {time}"),
+        Err(e) => warn!("unable to set the hardware clock {}: {e}", device.display()),
+    }
+}
+
+#[derive(Parser)]
+#[clap(version, about)]
+pub struct Args {
+    #[clap(subcommand)]
+    pub command: Commands,
+
+    /// Fail if the command has not finished within this many seconds, cutting outstanding server queries short
+    #[clap(long, global = true)]
+    pub max_runtime: Option<u64>,
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Get current time info
+    Get {
+        /// Date/time format [default: debug]
+        #[clap(arg_enum)]
+        std: Option<GetDTFormats>,
+
+        /// Date/time format, takes precedence over the positional argument
+        #[clap(arg_enum, short, long)]
+        format: Option<GetDTFormats>,
+
+        /// Show NTP-derived time and its difference from local time, without setting the clock
+        #[clap(short, long)]
+        compare: bool,
+
+        /// NTP servers to compare against, as host[:port][,weight=N][,prefer]
+        #[clap(short, long, requires = "compare")]
+        servers: Option<Vec<ServerSpec>>,
+
+        /// Show whether the kernel considers the clock synchronized and how it is being disciplined
+        #[clap(long, conflicts_with = "compare")]
+        sync_status: bool,
+
+        /// Also show each server's stratum, what its time ultimately comes from and the TTL its response arrived with
+        #[clap(long, requires = "compare")]
+        detail: bool,
+
+        #[clap(flatten)]
+        query: QueryArgs,
+    },
+
+    /// Set system time
+    Set {
+        /// Date/time format
+        #[clap(arg_enum, short, long)]
+        std: SetDTFormats,
+
+        /// Date/time to set to
+        datetime: String,
+
+        /// Set the clock through `su -c date`, for rooted Android devices
+        #[clap(long)]
+        su: bool,
+
+        /// Set the clock through SetLocalTime in the system's time zone instead of SetSystemTime in UTC (Windows only)
+        #[clap(long, conflicts_with = "su")]
+        local_time: bool,
+    },
+
+    /// Convert a date/time between formats without touching the system clock
+    Convert {
+        /// Format of the input: rfc2822, rfc3339, unix, human, or a strftime pattern
+        #[clap(long)]
+        from: ConvertFormat,
+
+        /// Format of the output: rfc2822, rfc3339, unix, human, or a strftime pattern
+        #[clap(long)]
+        to: ConvertFormat,
+
+        /// Date/time to convert
+        value: String,
+    },
+
+    /// Show how the kernel is currently disciplining the system clock
+    Status {
+        /// Output format
+        #[clap(arg_enum, short, long, default_value = "text")]
+        format: StatusFormat,
+
+        /// Also show the recent offsets of each server measured by the running daemon
+        #[clap(long)]
+        detail: bool,
+
+        /// Number of recent offsets to show per server
+        #[clap(long, default_value = "32", requires = "detail")]
+        samples: usize,
+
+        /// Also show why the running daemon accepted or rejected each server and stepped, slewed or held the clock in its last cycle
+        #[clap(long)]
+        explain: bool,
+
+        /// Control socket of the running daemon [default: /run/tdctld.sock]
+        #[clap(long)]
+        control_socket: Option<PathBuf>,
+    },
+
+    /// Synchronize system clock with NTP servers
+    Sync {
+        /// NTP servers to synchronize against, as host[:port][,weight=N][,prefer]
+        #[clap(short, long)]
+        servers: Option<Vec<ServerSpec>>,
+
+        #[clap(flatten)]
+        query: QueryArgs,
+
+        /// Set the clock through `su -c date`, for rooted Android devices
+        #[clap(long)]
+        su: bool,
+
+        /// Log levels by offset magnitude, e.g. 5ms:debug,100ms:info,1s:warn,error [default: info]
+        #[clap(long)]
+        offset_levels: Option<OffsetLevels>,
+
+        /// Also show each server's stratum, what its time ultimately comes from and the TTL its response arrived with
+        #[clap(long)]
+        detail: bool,
+
+        /// Wait up to SECS for a route to one of the servers before syncing [default: 30]
+        #[clap(
+            long,
+            value_name = "SECS",
+            min_values = 0,
+            require_equals = true,
+            default_missing_value = "30"
+        )]
+        wait_for_network: Option<u64>,
+
+        /// Sync once for early boot: sample each server in a burst, step by whatever it takes and write the result to the hardware clock
+        #[clap(long, conflicts_with = "su")]
+        boot: bool,
+
+        /// Leave the clock alone unless the accepted responses came from at least N distinct /24 (IPv6 /48) networks
+        #[clap(long, value_name = "N", default_value = "0")]
+        min_networks: usize,
+
+        /// Hold the clock when the untrusted servers call for a correction over MS milliseconds that the servers marked trusted do not confirm
+        #[clap(long, value_name = "MS")]
+        attack_threshold_ms: Option<u64>,
+
+        /// Also write the corrected time to the hardware clock, so it survives a power loss (Linux only)
+        #[clap(long, conflicts_with = "su")]
+        update_rtc: bool,
+    },
+
+    /// Run tdctld as a background process to synchronize the system clock in set intervals (only available on Linux, the BSDs and illumos)
+    Daemon(Box<DaemonArgs>),
+
+    /// Install tdctld as a system service that runs the daemon at boot
+    Install {
+        /// NTP servers for the installed daemon to synchronize against
+        #[clap(short, long)]
+        servers: Option<Vec<ServerSpec>>,
+
+        /// Duration between synchronizations (in seconds)
+        #[clap(default_value = "1800")]
+        timeout: u64,
+
+        /// Stop and disable the Windows Time service so it does not fight tdctld (Windows only)
+        #[clap(long)]
+        replace_w32time: bool,
+
+        /// Stop and disable systemd-timesyncd, chrony and ntpd so they do not fight tdctld (Linux only)
+        #[clap(long)]
+        replace_time_daemons: bool,
+    },
+
+    /// Remove the tdctld system service and restore any time service it replaced
+    Uninstall,
+
+    /// Print recent daemon events as JSON lines
+    Events {
+        /// Keep the connection open and print events as they happen
+        #[clap(short, long)]
+        follow: bool,
+
+        /// Control socket of the running daemon [default: /run/tdctld.sock]
+        #[clap(long)]
+        control_socket: Option<PathBuf>,
+    },
+
+    /// Show percentiles of the offsets measured and corrections applied by the running daemon
+    Stats {
+        /// Output format
+        #[clap(arg_enum, short, long, default_value = "text")]
+        format: StatusFormat,
+
+        /// Also show the share of samples within this bound (in milliseconds), e.g. to check an SLO
+        #[clap(long)]
+        within_ms: Option<f64>,
+
+        /// Control socket of the running daemon [default: /run/tdctld.sock]
+        #[clap(long)]
+        control_socket: Option<PathBuf>,
+    },
+
+    /// Show the running daemon's reference, offsets and frequency formatted like `chronyc tracking`
+    Tracking {
+        /// Print comma-separated values like `chronyc -c tracking`
+        #[clap(short, long)]
+        csv: bool,
+
+        /// Control socket of the running daemon [default: /run/tdctld.sock]
+        #[clap(long)]
+        control_socket: Option<PathBuf>,
+    },
+
+    /// Summarize offsets, server reachability and steps recorded by the daemon, e.g. for a weekly review
+    Report {
+        /// How far back to report, e.g. 12h, 7d or 4w
+        #[clap(long, default_value = "7d", value_parser = parse_since)]
+        since: chrono::Duration,
+
+        /// Output format
+        #[clap(arg_enum, short, long, default_value = "md")]
+        format: ReportFormat,
+
+        /// History file written by the daemon [default: /var/lib/tdctld/history.jsonl]
+        #[clap(long)]
+        history_file: Option<PathBuf>,
+    },
+
+    /// List, add or remove the running daemon's servers without restarting it
+    Servers {
+        #[clap(subcommand)]
+        command: ServersCommand,
+
+        /// Control socket of the running daemon [default: /run/tdctld.sock]
+        #[clap(long, global = true)]
+        control_socket: Option<PathBuf>,
+    },
+
+    /// Hold the running daemon's clock corrections for maintenance, while it keeps measuring
+    Pause {
+        /// Resume by itself after this long, e.g. 30m or 2h [default: until `tdctld resume`]
+        #[clap(long = "for", value_name = "DURATION", value_parser = parse_since)]
+        duration: Option<chrono::Duration>,
+
+        /// Control socket of the running daemon [default: /run/tdctld.sock]
+        #[clap(long)]
+        control_socket: Option<PathBuf>,
+    },
+
+    /// Let the running daemon correct the clock again after `tdctld pause`
+    Resume {
+        /// Control socket of the running daemon [default: /run/tdctld.sock]
+        #[clap(long)]
+        control_socket: Option<PathBuf>,
+    },
+
+    /// Inspect the hardware clock
+    Rtc {
+        #[clap(subcommand)]
+        command: RtcCommand,
+    },
+
+    /// Collect status from daemons started with --control-listen and report how far their clocks are apart
+    Fleet {
+        /// Daemons to query, as host or host:port [default port: 12323]
+        #[clap(required = true)]
+        hosts: Vec<String>,
+
+        /// Deviation from the fleet median to warn about (in milliseconds)
+        #[clap(long, default_value = "100")]
+        max_skew_ms: f64,
+
+        /// Output format
+        #[clap(arg_enum, short, long, default_value = "text")]
+        format: StatusFormat,
+    },
+
+    /// Probe every address of a network for NTP servers and list what answers, e.g. to audit which devices serve (possibly wrong) time
+    Scan {
+        /// Network to probe, e.g. 10.0.0.0/24, no larger than a /16
+        subnet: Subnet,
+
+        /// Port to send requests to
+        #[clap(long, default_value = "123")]
+        port: u16,
+
+        /// Requests in flight at once
+        #[clap(long, default_value = "256", value_parser = clap::value_parser!(u16).range(1..))]
+        concurrency: u16,
+
+        /// How long to wait for each address to answer (in milliseconds)
+        #[clap(long, default_value = "1000")]
+        timeout_ms: u64,
+
+        /// Offset from this host's clock to warn about (in milliseconds)
+        #[clap(long, default_value = "100")]
+        threshold_ms: f64,
+
+        /// Output format
+        #[clap(arg_enum, short, long, default_value = "text")]
+        format: StatusFormat,
+    },
+
+    /// Watch the clock without setting it, reporting when it drifts or is stepped by more than a threshold
+    Monitor {
+        /// NTP servers to measure against, as host[:port][,weight=N][,prefer]
+        #[clap(short, long)]
+        servers: Option<Vec<ServerSpec>>,
+
+        #[clap(flatten)]
+        query: QueryArgs,
+
+        /// Duration between measurements (in seconds)
+        #[clap(long, default_value = "600")]
+        interval: u64,
+
+        /// Offset or step to report (in milliseconds)
+        #[clap(long, default_value = "100")]
+        threshold_ms: u64,
+
+        /// Also show reports as desktop notifications (notify-send, macOS notifications or Windows toasts)
+        #[clap(long)]
+        notify: bool,
+    },
+
+    /// Serve JSON-RPC requests (getTime, measureOffset, applySync) line by line on stdin and stdout, for front-ends driving tdctld as a subprocess
+    ServeStdio {
+        /// NTP servers to query when a request names none, as host[:port][,weight=N][,prefer]
+        #[clap(short, long)]
+        servers: Option<Vec<ServerSpec>>,
+
+        #[clap(flatten)]
+        query: QueryArgs,
+
+        /// Set the clock through `su -c date`, for rooted Android devices
+        #[clap(long)]
+        su: bool,
+    },
+
+    /// Forward NTP over TCP from clients behind firewalls that block UDP to an NTP server (use with the tcp server option)
+    Relay {
+        /// Address to accept relayed queries on
+        #[clap(default_value = "0.0.0.0:123")]
+        listen: std::net::SocketAddr,
+
+        /// NTP server to forward queries to, as host or host:port
+        #[clap(short, long, default_value = "time.google.com")]
+        upstream: String,
+    },
+
+    /// Answer NTP queries with a simulated time instead of the real one, to move test machines to another date
+    FakeServer {
+        /// Address to answer queries on
+        #[clap(default_value = "0.0.0.0:123")]
+        listen: std::net::SocketAddr,
+
+        /// Simulated time to start from, as RFC 3339 (defaults to the current time)
+        #[clap(long, value_parser = parse_rfc3339)]
+        start: Option<DateTime<Utc>>,
+
+        /// How many times as fast as real time the simulated time runs, 0 to keep it fixed at --start
+        #[clap(long, default_value = "1", value_parser = parse_rate)]
+        rate: f64,
+
+        /// Stratum to claim
+        #[clap(long, default_value = "1", value_parser = clap::value_parser!(u8).range(1..16))]
+        stratum: u8,
+    },
+
+    /// Run a command in a Linux time namespace with its monotonic and boot-time clocks shifted, e.g. to test timeouts and uptime handling (the date is not shifted)
+    NsRun {
+        /// How far to shift the clocks, as a time relative to now, e.g. "in 30 days" or "1 hour ago"
+        #[clap(long, allow_hyphen_values = true, value_parser = timens::parse_shift)]
+        shift: chrono::Duration,
+
+        /// Command to run, after --
+        #[clap(required = true, last = true)]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RtcCommand {
+    /// Report how far the hardware clock is from the system clock and how fast it drifts (Linux only)
+    Compare {
+        /// RTC device to read
+        #[clap(long, default_value = lunartick::DEFAULT_RTC_DEVICE)]
+        device: PathBuf,
+
+        /// Output format
+        #[clap(arg_enum, short, long, default_value = "text")]
+        format: StatusFormat,
+
+        /// File to learn the RTC's drift rate in across runs [default: /var/lib/tdctld/rtc.json]
+        #[clap(long)]
+        state_file: Option<PathBuf>,
+
+        /// Whether the hardware clock is kept in UTC or local time [default: as set in /etc/adjtime]
+        #[clap(arg_enum, long)]
+        timescale: Option<RtcZone>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ServersCommand {
+    /// List the servers the daemon was configured with
+    List,
+
+    /// Start querying a server, given as for --servers
+    Add {
+        server: ServerSpec,
+
+        /// Also save the change to the daemon's --servers-file
+        #[clap(long)]
+        persist: bool,
+    },
+
+    /// Stop querying a server, given as host[:port] or as it was added
+    Remove {
+        server: String,
+
+        /// Also save the change to the daemon's --servers-file
+        #[clap(long)]
+        persist: bool,
+    },
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+pub enum RtcZone {
+    Utc,
+    Local,
+}
+
+impl From<RtcZone> for lunartick::RtcTimescale {
+    fn from(zone: RtcZone) -> Self {
+        match zone {
+            RtcZone::Utc => lunartick::RtcTimescale::Utc,
+            RtcZone::Local => lunartick::RtcTimescale::Local,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub struct DaemonArgs {
+    /// NTP servers to synchronize against, as host[:port][,weight=N][,prefer]
+    #[clap(short, long)]
+    servers: Option<Vec<ServerSpec>>,
+
+    /// File of servers to also query, one per line as for --servers, which `tdctld servers add/remove --persist` saves changes to
+    #[clap(long, value_name = "PATH")]
+    servers_file: Option<PathBuf>,
+
+    /// Duration between synchronizations (in seconds)
+    #[clap(default_value = "1800")]
+    timeout: u64,
+
+    #[clap(flatten)]
+    query: QueryArgs,
+
+    #[clap(flatten)]
+    backoff: BackoffArgs,
+
+    /// Set the clock through `su -c date`, for rooted Android devices
+    #[clap(long)]
+    su: bool,
+
+    /// Log levels by offset magnitude, e.g. 5ms:debug,100ms:info,1s:warn,error [default: info]
+    #[clap(long)]
+    offset_levels: Option<OffsetLevels>,
+
+    /// Step the clock only when the offset exceeds THRESHOLD seconds during the first LIMIT corrections
+    /// (-1 for no limit), and slew it otherwise
+    #[clap(
+        long,
+        number_of_values = 2,
+        value_names = &["THRESHOLD", "LIMIT"],
+        allow_hyphen_values = true,
+        conflicts_with = "su"
+    )]
+    makestep: Option<Vec<f64>>,
+
+    /// Leave the clock alone unless the accepted responses came from at least N distinct /24 (IPv6 /48) networks
+    #[clap(long, value_name = "N", default_value = "0")]
+    min_networks: usize,
+
+    /// Hold the clock when the untrusted servers call for a correction over MS milliseconds that the servers marked trusted do not confirm
+    #[clap(long, value_name = "MS")]
+    attack_threshold_ms: Option<u64>,
+
+    /// Correct the clock by at most this many milliseconds per cycle, steps included, applying a larger offset over several cycles
+    #[clap(long, value_name = "MS", value_parser = parse_max_correction)]
+    max_correction_per_cycle: Option<f64>,
+
+    /// Correct offsets measured while the link is congested against the shortest delay each server was measured with over the last SECS seconds (ntpd's huff-n-puff filter)
+    #[clap(long, value_name = "SECS")]
+    huff_puff: Option<u64>,
+
+    /// Discipline the clock with a phase/frequency-locked loop of this time constant in seconds, slewing offsets away gradually and adjusting the clock frequency, instead of correcting each offset in full (Linux and illumos only)
+    #[clap(long, value_name = "SECS", conflicts_with = "su")]
+    time_constant: Option<u64>,
+
+    /// Learn how drift follows the temperature this sysfs sensor reports in millidegrees, for better holdover estimates on devices without a temperature-compensated crystal [default: /sys/class/thermal/thermal_zone0/temp]
+    #[clap(
+        long,
+        value_name = "PATH",
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = thermal::DEFAULT_SENSOR
+    )]
+    temperature_sensor: Option<PathBuf>,
+
+    /// Also query the NTP servers systemd-networkd learned for each link, following them as links come and go (Linux only) [default: /run/systemd/netif/links]
+    #[clap(
+        long,
+        value_name = "DIR",
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = networkd::LINKS_DIR
+    )]
+    networkd: Option<PathBuf>,
+
+    /// Only correct the clock between these local times, e.g. 02:00-04:00, measuring the rest of the day (repeatable)
+    #[clap(long, value_name = "HH:MM-HH:MM")]
+    correction_window: Vec<window::CorrectionWindow>,
+
+    /// Also write the time to the hardware clock after each correction, so it survives a power loss (Linux only)
+    #[clap(long, conflicts_with = "su")]
+    update_rtc: bool,
+
+    /// Run the whole measurement, selection and discipline pipeline and log what would be done to the clock, without touching it
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Compare each cycle's combined offset with what chrony or ntpd running alongside reports, through `chronyc -c tracking` or `ntpq`
+    #[clap(long, value_name = "DAEMON")]
+    shadow: Option<shadow::ShadowSource>,
+
+    /// Warn when the offset differs from the one --shadow reports by more than this many milliseconds
+    #[clap(long, value_name = "MS", default_value = "5", requires = "shadow")]
+    shadow_threshold_ms: f64,
+
+    /// Lock file shared with redundant instances, only the instance holding it sets the clock while the others report
+    #[clap(long)]
+    leader_lock: Option<PathBuf>,
+
+    /// Keep setting the clock even when the hypervisor is also synchronizing it
+    #[clap(long)]
+    allow_hypervisor: bool,
+
+    /// File to persist drift, server scores and the last sync in across restarts
+    #[clap(long)]
+    state_file: Option<PathBuf>,
+
+    /// File to append a record of every sync cycle to, for `tdctld report` [default: history.jsonl next to the state file]
+    #[clap(long)]
+    history_file: Option<PathBuf>,
+
+    /// Unix socket to serve status and events on [default: /run/tdctld.sock]
+    #[clap(long)]
+    control_socket: Option<PathBuf>,
+
+    /// Also serve status and events over TCP for `tdctld fleet`, e.g. 0.0.0.0:12323
+    #[clap(long)]
+    control_listen: Option<std::net::SocketAddr>,
+
+    /// TOML file configuring Slack, Telegram or email notifications for large steps, holdover and falsetickers
+    #[clap(long)]
+    notify_config: Option<PathBuf>,
+
+    /// TOML file of an offset profile keeping the clock ahead of (or behind) true time by a fixed or ramping offset, for staging systems that test certificate expiry and scheduled jobs
+    #[clap(long)]
+    offset_profile: Option<PathBuf>,
+
+    /// Directory of executables to run with a JSON event on stdin after each sync, step and source failure
+    #[clap(long)]
+    hooks_dir: Option<PathBuf>,
+
+    /// Seconds to suppress repeats of an identical log message for, 0 to log every repeat
+    #[clap(long, default_value = "3600")]
+    log_dedup_window: u64,
+
+    /// Report the daemon as degraded once the clock may have drifted this far since the last successful sync (in milliseconds)
+    #[clap(long, default_value = "100")]
+    holdover_error_bound: f64,
+
+    /// Re-sync right away when the system clock jumps, stands still or the system was suspended for more than this many milliseconds between checks
+    #[clap(long)]
+    clock_watchdog: Option<u64>,
+}
+
+#[derive(clap::Args)]
+pub struct QueryArgs {
+    /// DSCP value to mark outgoing NTP packets with (e.g. 46 for expedited forwarding)
+    #[clap(long, value_parser = clap::value_parser!(u8).range(..64))]
+    dscp: Option<u8>,
+
+    /// Network interface to send NTP queries from (e.g. eth1)
+    #[clap(long)]
+    interface: Option<String>,
+
+    /// Send NTP queries from this UDP port instead of a random one per query (e.g. for a firewall that only allows a fixed port)
+    #[clap(long)]
+    source_port: Option<u16>,
+
+    /// Reject responses from servers below this stratum
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u8).range(1..))]
+    min_stratum: u8,
+
+    /// Reject responses from servers above this stratum (e.g. 4 to ignore misconfigured upstreams)
+    #[clap(long, default_value = "15")]
+    max_stratum: u8,
+
+    /// Add the servers that answer a manycast request to this multicast group on the local network [default group: 224.0.1.1]
+    #[clap(
+        long,
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "224.0.1.1"
+    )]
+    manycast: Option<std::net::Ipv4Addr>,
+
+    /// Query servers in NTPv4 interleaved mode for more accurate transmit timestamps, falling back to basic mode for servers without support
+    #[clap(long)]
+    interleaved: bool,
+
+    /// Give up on resolving a server's name after this many milliseconds, reporting it apart from servers that do not answer
+    #[clap(long, default_value = "2000")]
+    dns_timeout_ms: u64,
+
+    /// Query the four servers of this NTP pool zone, such as a vendor zone, when no servers are given
+    #[clap(long, value_name = "ZONE")]
+    pool_zone: Option<String>,
+
+    /// Query up to this many UDP servers at once from one shared socket, for checking hundreds of servers quickly
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: u16,
+}
+
+#[derive(clap::Args)]
+struct BackoffArgs {
+    /// Seconds to wait before retrying after the first failed sync
+    #[clap(long, default_value = "30")]
+    backoff_initial: u64,
+
+    /// Factor the retry delay grows by after each further failed sync
+    #[clap(long, default_value = "2", value_parser = parse_multiplier)]
+    backoff_multiplier: f64,
+
+    /// Longest delay between retries, in seconds [default: the sync interval]
+    #[clap(long)]
+    backoff_max: Option<u64>,
+}
+
+impl BackoffArgs {
+    fn delay(&self, failures: u32, poll_interval: Duration) -> Duration {
+        let cap = self
+            .backoff_max
+            .map_or(poll_interval, Duration::from_secs)
+            .as_secs_f64();
+        let exponent = i32::try_from(failures.saturating_sub(1)).unwrap_or(i32::MAX);
+        let delay = self.backoff_initial as f64 * self.backoff_multiplier.powi(exponent);
+        Duration::from_secs_f64(delay.min(cap))
+    }
+}
+
+// --makestep THRESHOLD LIMIT, with every correction stepped when it is not given.
+fn sync_engine(makestep: Option<&[f64]>) -> Result<SyncEngine> {
+    let Some(values) = makestep else {
+        return Ok(SyncEngine::new());
+    };
+    let &[threshold, limit] = values else {
+        bail!("--makestep takes a threshold and a limit");
+    };
+    if !threshold.is_finite() || threshold < 0.0 {
+        bail!("the makestep threshold must be a non-negative number of seconds");
+    }
+    if limit.fract() != 0.0 || limit < -1.0 {
+        bail!("the makestep limit must be a whole number, or -1 for no limit");
+    }
+    Ok(SyncEngine::new().with_makestep(
+        chrono::Duration::microseconds((threshold * 1e6) as i64),
+        (limit >= 0.0).then_some(limit as u64),
+    ))
+}
+
+// --attack-threshold-ms MS, which only has an effect with servers marked trusted.
+fn attack_guard(engine: SyncEngine, threshold_ms: Option<u64>) -> SyncEngine {
+    match threshold_ms {
+        Some(ms) => engine.with_attack_threshold(chrono::Duration::milliseconds(
+            i64::try_from(ms).unwrap_or(i64::MAX),
+        )),
+        None => engine,
+    }
+}
+
+// A span like 30m, 12h, 7d or 2w.
+fn parse_since(value: &str) -> Result<chrono::Duration, String> {
+    let split = value.len() - value.chars().last().map_or(0, char::len_utf8);
+    let (count, unit) = value.split_at(split);
+    let count = count
+        .parse::<u32>()
+        .map_err(|_| format!("expected a number followed by m, h, d or w, got '{value}'"))?;
+    let count = i64::from(count);
+    match unit {
+        "m" => Ok(chrono::Duration::minutes(count)),
+        "h" => Ok(chrono::Duration::hours(count)),
+        "d" => Ok(chrono::Duration::days(count)),
+        "w" => Ok(chrono::Duration::weeks(count)),
+        _ => Err(format!("unknown unit '{unit}', expected m, h, d or w")),
+    }
+}
+
+fn parse_max_correction(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(ms) if ms.is_finite() && ms > 0.0 => Ok(ms),
+        Ok(_) => Err("the limit must be a positive number of milliseconds".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_multiplier(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(multiplier) if multiplier.is_finite() && multiplier >= 1.0 => Ok(multiplier),
+        Ok(_) => Err("the multiplier must be at least 1".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_rfc3339(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| format!("expected an RFC 3339 time like 2038-01-19T03:14:07Z: {e}"))
+}
+
+fn parse_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate >= 0.0 => Ok(rate),
+        Ok(_) => Err("the rate must not be negative".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(ArgEnum, Clone)]
+pub enum GetDTFormats {
+    Debug,
+    Timestamp,
+    RFC2822,
+    RFC3339,
+    Tai,
+    Gps,
+    Julian,
+    Mjd,
+    IsoWeek,
+    Ordinal,
+    Json,
+}
+
+#[derive(ArgEnum, Clone)]
+pub enum ReportFormat {
+    Html,
+    Md,
+}
+
+#[derive(ArgEnum, Clone)]
+pub enum StatusFormat {
+    Text,
+    Json,
+    Prometheus,
+}
+
+#[derive(ArgEnum, Clone, Debug)]
+pub enum SetDTFormats {
+    RFC2822,
+    RFC3339,
+    Human,
+}
+
+impl From<SetDTFormats> for GetDTFormats {
+    fn from(value: SetDTFormats) -> Self {
+        match value {
+            SetDTFormats::RFC2822 => GetDTFormats::RFC2822,
+            SetDTFormats::RFC3339 => GetDTFormats::RFC3339,
+            SetDTFormats::Human => GetDTFormats::Debug,
+        }
+    }
+}
+
+fn get(std: GetDTFormats) -> Clock {
+    let now = Clock::now();
+    match std {
+        GetDTFormats::Json => println!("{}", clock_json(&now)),
+        std => info!("{}", describe(&now, &std)),
+    }
+    now
+}
+
+fn describe(now: &Clock, std: &GetDTFormats) -> String {
+    match std {
+        GetDTFormats::Debug => format!("{now:?}"),
+        GetDTFormats::Timestamp => now.get_timestamp().to_string(),
+        GetDTFormats::RFC2822 => now.get_rfc2822(),
+        GetDTFormats::RFC3339 => now.get_rfc3339(),
+        GetDTFormats::Tai => format!("{} TAI", now.to_tai()),
+        GetDTFormats::Gps => now.to_gps_time().to_string(),
+        GetDTFormats::Julian => now.to_julian_date().to_string(),
+        GetDTFormats::Mjd => now.to_modified_julian_date().to_string(),
+        GetDTFormats::IsoWeek => now.get_iso_week_date(),
+        GetDTFormats::Ordinal => now.get_ordinal_date(),
+        GetDTFormats::Json => clock_json(now).to_string(),
+    }
+}
+
+fn clock_json(now: &Clock) -> serde_json::Value {
+    let gps = now.to_gps_time();
+    serde_json::json!({
+        "debug": now.to_string(),
+        "timestamp": now.get_timestamp(),
+        "rfc2822": now.get_rfc2822(),
+        "rfc3339": now.get_rfc3339(),
+        "tai": now.to_tai().to_string(),
+        "gps": {
+            "week": gps.week,
+            "seconds": gps.seconds_since_epoch(),
+        },
+        "julian_date": now.to_julian_date(),
+        "modified_julian_date": now.to_modified_julian_date(),
+        "iso_week_date": now.get_iso_week_date(),
+        "ordinal_date": now.get_ordinal_date(),
+    })
+}
+
+fn compare(
+    std: GetDTFormats,
+    ntp_client: &NTPClient,
+    detail: bool,
+    deadline: Option<Instant>,
+) -> Result<TestResults> {
+    let results = measure(ntp_client, deadline)?;
+    report_results(&results, &OffsetLevels::default());
+    if detail && !matches!(std, GetDTFormats::Json) {
+        report_sources(&results);
+    }
+    let correction = results.applied_correction()?;
+    let local = Clock::now();
+    let ntp = Clock::new(DateTime::<Local>::from(local) + correction);
+    let delta = Offset::from(correction);
+    match std {
+        GetDTFormats::Json => {
+            let json = serde_json::json!({
+                "local": clock_json(&local),
+                "ntp": clock_json(&ntp),
+                "delta_ms": delta.as_millis_f64(),
+                "uncertainty_ms": results
+                    .get_uncertainty()
+                    .map(|uncertainty| Offset::from(uncertainty).as_millis_f64()),
+                "best_server": results.best_server(),
+                "sources": detail.then(|| sources_json(&results)),
+            });
+            println!("{json}");
+        }
+        std => {
+            info!("local => {}", describe(&local, &std));
+            info!("ntp   => {}", describe(&ntp, &std));
+            match results.get_uncertainty() {
+                Some(uncertainty) => info!("delta => {delta} ±{}", Offset::from(uncertainty)),
+                None => info!("delta => {delta}"),
+            }
+        }
+    }
+    Ok(results)
+}
+
+fn sync_status(std: GetDTFormats) -> Result<()> {
+    let status = lunartick::kernel_sync_status()?;
+    let now = Clock::now();
+    match std {
+        GetDTFormats::Json => {
+            let json = serde_json::json!({
+                "time": clock_json(&now),
+                "kernel": kernel_json(&status),
+            });
+            println!("{json}");
+        }
+        std => {
+            info!("{}", describe(&now, &std));
+            report_kernel(&status);
+        }
+    }
+    Ok(())
+}
+
+fn report_kernel(status: &KernelSyncStatus) {
+    match status.synchronized {
+        Some(true) => info!("kernel clock => synchronized"),
+        Some(false) => warn!("kernel clock => unsynchronized"),
+        None => info!("kernel clock => synchronization state unknown"),
+    }
+    let fields = [
+        ("offset", status.offset),
+        ("max error", status.max_error),
+        ("estimated error", status.estimated_error),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            info!("{name} => {}", Offset::from(value));
+        }
+    }
+    match status.frequency_ppm {
+        Some(ppm) => info!("frequency => {ppm:+.3} ppm"),
+        None => info!("frequency => not disciplined"),
+    }
+    if let Some(tick) = status.tick {
+        let micros = tick.num_nanoseconds().unwrap_or(i64::MAX) as f64 / 1e3;
+        match status.tick_ppm {
+            Some(ppm) => info!("tick => {micros}us ({ppm:+.3} ppm)"),
+            None => info!("tick => {micros}us"),
+        }
+    }
+    if let Some(total) = status.total_frequency_ppm() {
+        info!("total correction => {total:+.3} ppm");
+    }
+}
+
+// The daemon's health is included whenever it is reachable, the offset history only with --detail.
+fn status(
+    format: StatusFormat,
+    control_socket: &Path,
+    detail: bool,
+    samples: usize,
+    explain: bool,
+) -> Result<()> {
+    let status = lunartick::kernel_sync_status()?;
+    let hypervisor = lunartick::detect_hypervisor_time_sync();
+    let daemon = match control::query_status(control_socket) {
+        Ok(daemon) => Some(daemon),
+        Err(e) if detail || explain => return Err(e),
+        Err(_) => None,
+    };
+    let history = daemon
+        .as_ref()
+        .filter(|_| detail)
+        .map(|daemon| server_history(daemon, samples))
+        .transpose()?;
+    let explanation = daemon
+        .as_ref()
+        .filter(|_| explain)
+        .map(|daemon| daemon["explain"].clone())
+        .filter(|explanation| !explanation.is_null());
+    if explain && explanation.is_none() {
+        bail!("The daemon has not finished a cycle yet");
+    }
+    let shadow = daemon
+        .as_ref()
+        .map(|daemon| daemon["shadow"].clone())
+        .filter(|shadow| !shadow.is_null());
+    let paused = daemon
+        .as_ref()
+        .map(|daemon| daemon["paused"].clone())
+        .filter(|paused| !paused.is_null());
+    let health = daemon.as_ref().and_then(|daemon| daemon["health"].as_str());
+    let holdover_error_ms = daemon
+        .as_ref()
+        .and_then(|daemon| daemon["holdover_error_ms"].as_f64());
+    match format {
+        StatusFormat::Text => {
+            report_kernel(&status);
+            if let Some(hypervisor) = hypervisor {
+                warn!("hypervisor => {hypervisor} is also disciplining this clock");
+            }
+            let error = holdover_error_ms.map_or_else(String::new, |ms| {
+                format!(", holdover error estimate {ms:.3}ms")
+            });
+            match health {
+                Some("ok") => info!("daemon => ok{error}"),
+                Some(health) => warn!("daemon => {health}{error}"),
+                None => (),
+            }
+            if let Some(paused) = &paused {
+                match paused["until"].as_str() {
+                    Some(until) => warn!("daemon => corrections paused until {until}"),
+                    None => warn!("daemon => corrections paused until resumed"),
+                }
+            }
+            for (server, offsets) in history.iter().flatten() {
+                let measured: Vec<_> = offsets.iter().flatten().copied().collect();
+                match measured.last() {
+                    Some(last) => {
+                        let min = measured.iter().copied().fold(f64::INFINITY, f64::min);
+                        let max = measured.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                        info!(
+                            "{server} => {} last {last:.3}ms, range {min:.3}ms..{max:.3}ms",
+                            sparkline(offsets)
+                        );
+                    }
+                    None => warn!(
+                        "{server} => no answers in the last {} cycles",
+                        offsets.len()
+                    ),
+                }
+            }
+            if let Some(divergence) = shadow
+                .as_ref()
+                .and_then(|shadow| shadow["divergence_ms"].as_f64())
+            {
+                info!(
+                    "shadow => {divergence:+.3}ms from {} in the last cycle",
+                    shadow
+                        .as_ref()
+                        .and_then(|shadow| shadow["daemon"].as_str())
+                        .unwrap_or_default()
+                );
+            }
+            if let Some(explanation) = &explanation {
+                report_explanation(explanation);
+            }
+        }
+        StatusFormat::Json => {
+            let json = serde_json::json!({
+                "kernel": kernel_json(&status),
+                "hypervisor_time_sync": hypervisor.map(|h| h.to_string()),
+                "daemon_health": health,
+                "holdover_error_ms": holdover_error_ms,
+                "server_offsets_ms": history,
+                "explain": explanation,
+                "shadow": shadow,
+                "paused": paused,
+            });
+            println!("{json}");
+        }
+        StatusFormat::Prometheus => {
+            print!("{}", kernel_metrics(&status));
+            print!("{}", health_metrics(health, holdover_error_ms));
+            if let Some(daemon) = &daemon {
+                print!("{}", stats::metrics(daemon)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn server_history(
+    status: &serde_json::Value,
+    samples: usize,
+) -> Result<BTreeMap<String, Vec<Option<f64>>>> {
+    let servers: BTreeMap<String, state::ServerScore> =
+        serde_json::from_value(status["servers"].clone())
+            .context("The daemon sent an invalid server list")?;
+    Ok(servers
+        .into_iter()
+        .map(|(server, score)| {
+            let skip = score.history.len().saturating_sub(samples);
+            (server, score.history.into_iter().skip(skip).collect())
+        })
+        .collect())
+}
+
+fn report_explanation(explanation: &serde_json::Value) {
+    for verdict in explanation["servers"].as_array().into_iter().flatten() {
+        let server = verdict["server"].as_str().unwrap_or_default();
+        let reason = verdict["reason"].as_str().unwrap_or_default();
+        if verdict["accepted"].as_bool() == Some(true) {
+            info!("{server} => accepted, {reason}");
+        } else {
+            warn!("{server} => rejected, {reason}");
+        }
+    }
+    if let Some(combine) = explanation["combine"].as_str() {
+        info!("combined => {combine}");
+    }
+    if let Some(correction) = explanation["correction"].as_str() {
+        info!("last cycle => {correction}");
+    }
+}
+
+// Scales offsets between their minimum and maximum, leaving a gap for cycles without an answer.
+fn sparkline(offsets: &[Option<f64>]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let measured = offsets.iter().flatten().copied();
+    let min = measured.clone().fold(f64::INFINITY, f64::min);
+    let max = measured.fold(f64::NEG_INFINITY, f64::max);
+    offsets
+        .iter()
+        .map(|offset| match offset {
+            Some(offset) if max > min => {
+                BARS[(((offset - min) / (max - min)) * 7.0).round() as usize]
+            }
+            Some(_) => BARS[3],
+            None => ' ',
+        })
+        .collect()
+}
+
+fn gauges<const N: usize>(metrics: [(&str, &str, Option<f64>); N]) -> String {
+    metrics
+        .into_iter()
+        .filter_map(|(name, help, value)| {
+            value
+                .map(|value| format!("# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n"))
+        })
+        .collect()
+}
+
+fn health_metrics(health: Option<&str>, holdover_error_ms: Option<f64>) -> String {
+    let metrics = [
+        (
+            "tdctld_degraded",
+            "Whether the daemon's estimated holdover error exceeds its bound",
+            health.map(|health| f64::from(u8::from(health != "ok"))),
+        ),
+        (
+            "tdctld_holdover_error_seconds",
+            "Estimated error accumulated since the last successful sync",
+            holdover_error_ms.map(|ms| ms / 1e3),
+        ),
+    ];
+    gauges(metrics)
+}
+
+fn kernel_metrics(status: &KernelSyncStatus) -> String {
+    let seconds = |d: chrono::Duration| d.num_nanoseconds().unwrap_or(i64::MAX) as f64 / 1e9;
+    let metrics = [
+        (
+            "tdctld_kernel_synchronized",
+            "Whether the kernel considers the clock synchronized",
+            status.synchronized.map(|s| f64::from(u8::from(s))),
+        ),
+        (
+            "tdctld_kernel_offset_seconds",
+            "Remaining offset the kernel is slewing out",
+            status.offset.map(seconds),
+        ),
+        (
+            "tdctld_kernel_max_error_seconds",
+            "Maximum error reported by the kernel",
+            status.max_error.map(seconds),
+        ),
+        (
+            "tdctld_kernel_estimated_error_seconds",
+            "Estimated error reported by the kernel",
+            status.estimated_error.map(seconds),
+        ),
+        (
+            "tdctld_kernel_frequency_ppm",
+            "Kernel frequency offset in parts per million",
+            status.frequency_ppm,
+        ),
+        (
+            "tdctld_kernel_tick_seconds",
+            "Length of a kernel clock tick",
+            status.tick.map(seconds),
+        ),
+        (
+            "tdctld_kernel_tick_ppm",
+            "Deviation of the tick length from nominal in parts per million",
+            status.tick_ppm,
+        ),
+    ];
+    gauges(metrics)
+}
+
+fn kernel_json(status: &KernelSyncStatus) -> serde_json::Value {
+    let millis = |d: Option<chrono::Duration>| d.map(|d| Offset::from(d).as_millis_f64());
+    serde_json::json!({
+        "synchronized": status.synchronized,
+        "offset_ms": millis(status.offset),
+        "max_error_ms": millis(status.max_error),
+        "estimated_error_ms": millis(status.estimated_error),
+        "frequency_ppm": status.frequency_ppm,
+        "tick_us": status.tick.map(|t| t.num_nanoseconds().unwrap_or(i64::MAX) as f64 / 1e3),
+        "tick_ppm": status.tick_ppm,
+        "total_frequency_ppm": status.total_frequency_ppm(),
+    })
+}
+
+#[derive(Debug, Clone)]
+pub enum ConvertFormat {
+    RFC2822,
+    RFC3339,
+    Unix,
+    Human,
+    Strftime(String),
+}
+
+impl FromStr for ConvertFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rfc2822" => Ok(ConvertFormat::RFC2822),
+            "rfc3339" => Ok(ConvertFormat::RFC3339),
+            "unix" | "timestamp" => Ok(ConvertFormat::Unix),
+            "human" => Ok(ConvertFormat::Human),
+            _ if s.contains('%') => Ok(ConvertFormat::Strftime(s.to_owned())),
+            _ => Err(format!(
+                "unknown format '{s}', expected rfc2822, rfc3339, unix, human, or a strftime pattern"
+            )),
+        }
+    }
+}
+
+// A server given as `host[:port][,weight=N][,prefer][,tcp][,proxy=socks5://host:port]` followed
+// by any of `[,pin=IP]...[,trusted][,asymmetry=MS]`.
+#[derive(Debug, Clone)]
+pub struct ServerSpec {
+    spec: String,
+    server: String,
+    weight: f64,
+    transport: ServerTransport,
+    pins: Vec<IpAddr>,
+    trusted: bool,
+    // Milliseconds the path to the server takes longer than the path back.
+    asymmetry_ms: f64,
+}
+
+// Weight given to servers marked `prefer`.
+const PREFER_WEIGHT: f64 = 10.0;
+
+// No path is that lopsided, a larger asymmetry is more likely a typo.
+const MAX_ASYMMETRY_MS: f64 = 10_000.0;
+
+impl FromStr for ServerSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = s.split(',').map(str::trim);
+        let server = options.next().unwrap_or_default();
+        if server.is_empty() {
+            return Err("missing server name".to_owned());
+        }
+        let mut weight = 1.0;
+        let mut transport = ServerTransport::Udp;
+        let mut pins = Vec::new();
+        let mut trusted = false;
+        let mut asymmetry_ms = 0.0;
+        for option in options {
+            match option.split_once('=') {
+                None if option == "prefer" => weight = PREFER_WEIGHT,
+                None if option == "trusted" => trusted = true,
+                None if option == "tcp" => {
+                    if transport == ServerTransport::Udp {
+                        transport = ServerTransport::Tcp;
+                    }
+                }
+                Some(("weight", value)) => {
+                    weight = match value.parse::<f64>() {
+                        Ok(weight) if weight.is_finite() && weight > 0.0 => weight,
+                        _ => {
+                            return Err(format!(
+                                "invalid weight '{value}', expected a positive number"
+                            ))
+                        }
+                    }
+                }
+                Some(("asymmetry", value)) => {
+                    asymmetry_ms = match value.parse::<f64>() {
+                        Ok(asymmetry) if asymmetry.abs() <= MAX_ASYMMETRY_MS => asymmetry,
+                        _ => {
+                            return Err(format!(
+                                "invalid asymmetry '{value}', expected at most {MAX_ASYMMETRY_MS} milliseconds either way"
+                            ))
+                        }
+                    }
+                }
+                Some(("proxy", url)) => {
+                    transport = ServerTransport::Socks5(url.parse().map_err(|e| format!("{e}"))?)
+                }
+                Some(("pin", address)) => pins.push(
+                    address
+                        .parse::<IpAddr>()
+                        .map_err(|_| format!("invalid pinned address '{address}'"))?,
+                ),
+                _ => {
+                    return Err(format!(
+                    "unknown server option '{option}', expected weight=N, prefer, tcp, proxy=URL, pin=IP, trusted or asymmetry=MS"
+                ))
+                }
+            }
+        }
+        if !pins.is_empty() && matches!(transport, ServerTransport::Socks5(_)) {
+            return Err("servers reached through a proxy cannot be pinned".to_owned());
+        }
+        Ok(Self {
+            spec: s.to_owned(),
+            server: server.to_owned(),
+            weight,
+            transport,
+            pins,
+            trusted,
+            asymmetry_ms,
+        })
+    }
+}
+
+impl std::fmt::Display for ServerSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+fn parse_unix(value: &str) -> Option<Clock> {
+    let (secs, frac) = value.split_once('.').unwrap_or((value, ""));
+    let secs = secs.parse().ok()?;
+    let nanos = if frac.is_empty() {
+        0
+    } else if frac.len() <= 9 && frac.bytes().all(|b| b.is_ascii_digit()) {
+        format!("{frac:0<9}").parse().ok()?
+    } else {
+        return None;
+    };
+    Clock::from_timestamp(secs, nanos).ok()
+}
+
+fn convert(from: &ConvertFormat, to: &ConvertFormat, value: &str) -> Result<Clock> {
+    let dt = match from {
+        ConvertFormat::RFC2822 => Clock::from_rfc2822(value.to_owned())?,
+        ConvertFormat::RFC3339 => Clock::from_rfc3339(value.to_owned())?,
+        ConvertFormat::Unix => {
+            parse_unix(value).context(format!("Unable to parse {value} as a Unix timestamp"))?
+        }
+        ConvertFormat::Human => Clock::from_human(value)?,
+        ConvertFormat::Strftime(fmt) => Clock::from_format(value, fmt)?,
+    };
+    // Formatted before logging, as arguments are not evaluated when nothing logs at that level.
+    let converted = match to {
+        ConvertFormat::RFC2822 => dt.get_rfc2822(),
+        ConvertFormat::RFC3339 => dt.get_rfc3339(),
+        ConvertFormat::Unix => match dt.get_subsec_nanos() {
+            0 => dt.get_timestamp().to_string(),
+            nanos => format!(
+                "{}.{}",
+                dt.get_timestamp(),
+                format!("{nanos:09}").trim_end_matches('0')
+            ),
+        },
+        ConvertFormat::Human => dt.to_string(),
+        ConvertFormat::Strftime(fmt) => dt.format(fmt)?,
+    };
+    info!("{converted}");
+    Ok(dt)
+}
+
+fn set(std: SetDTFormats, datetime: String, su: bool, local_time: bool) -> Result<()> {
+    let dt = match std {
+        SetDTFormats::RFC2822 => Clock::from_rfc2822(datetime.clone()),
+        SetDTFormats::RFC3339 => Clock::from_rfc3339(datetime.clone()),
+        SetDTFormats::Human => Clock::from_human(&datetime),
+    }
+    .context(format!("Unable to parse {datetime} according to {std:?}"))?;
+    if local_time {
+        #[cfg(windows)]
+        dt.set_local_time()?;
+        #[cfg(not(windows))]
+        bail!("--local-time is only available on Windows");
+    } else {
+        apply(&dt, su)?;
+    }
+    get(std.into());
+    Ok(())
+}
+
+fn apply(dt: &Clock, su: bool) -> Result<bool> {
+    if su {
+        return set_with_su(dt).map(|()| true);
+    }
+    match dt.set() {
+        Ok(()) => Ok(true),
+        Err(e) => set_failed(e, dt),
+    }
+}
+
+// Reports a clock that could not be set to `dt` and returns false, or the error if it is not about
+// setting the clock.
+fn set_failed(e: LunartickError, dt: &Clock) -> Result<bool> {
+    match e {
+        LunartickError::SetError(e) => error!(e),
+        e @ LunartickError::ClockSetDenied(_) if cfg!(target_os = "android") => {
+            error!(
+                "{e} (Android only lets root set the clock, retry with --su on a rooted device)"
+            );
+            info!(
+                "from a computer with adb: adb shell su -c 'date -u {}'",
+                toybox_date(dt)
+            );
+        }
+        e @ LunartickError::ClockSetDenied(_) => error!("{e} (try running as root)"),
+        e => return Err(e.into()),
+    }
+    Ok(false)
+}
+
+// Steps the clock with `su -c date` for rooted Android devices, where only root can set it, and
+// slews it and sets its frequency as SystemClock does.
+struct SuClock;
+
+impl ClockBackend for SuClock {
+    fn set_time(&self, time: DateTime<Utc>) -> Result<(), LunartickError> {
+        set_with_su(&Clock::from(time.with_timezone(&Local)))
+            .map_err(|e| LunartickError::SetError(format!("{e:#}")))
+    }
+
+    fn slew(&self, delta: ChronoDuration) -> Result<(), LunartickError> {
+        SystemClock.slew(delta)
+    }
+
+    fn set_frequency(&self, ppm: f64) -> Result<(), LunartickError> {
+        SystemClock.set_frequency(ppm)
+    }
+}
+
+// `date` on Android (toybox) and busybox only take whole seconds, in MMDDhhmmCCYY.ss form.
+fn toybox_date(dt: &Clock) -> String {
+    DateTime::<Local>::from(*dt)
+        .with_timezone(&Utc)
+        .format("%m%d%H%M%Y.%S")
+        .to_string()
+}
+
+fn set_with_su(dt: &Clock) -> Result<()> {
+    let wait = 1_000_000_000 - dt.get_subsec_nanos().min(999_999_999);
+    let target = Clock::from_timestamp(dt.get_timestamp() + 1, 0)?;
+    std::thread::sleep(Duration::from_nanos(wait.into()));
+    let status = Command::new("su")
+        .arg("-c")
+        .arg(format!("date -u {}", toybox_date(&target)))
+        .stdout(Stdio::null())
+        .status()
+        .context("Unable to run su")?;
+    if !status.success() {
+        bail!("su -c date failed ({status})");
+    }
+    Ok(())
+}
+
+// How long to collect replies to a manycast request.
+const MANYCAST_WAIT: Duration = Duration::from_secs(1);
+
+fn ntp_client(mut servers: Option<Vec<ServerSpec>>, query: &QueryArgs) -> Result<NTPClient> {
+    if let Some(group) = query.manycast {
+        let responders = lunartick::manycast_discover(group, MANYCAST_WAIT)
+            .context(format!("Unable to send a manycast request to {group}"))?;
+        if responders.is_empty() && servers.is_none() {
+            bail!("No server answered the manycast request to {group}");
+        }
+        info!(
+            "{} server(s) answered the manycast request to {group}",
+            responders.len()
+        );
+        let discovered = servers.get_or_insert_with(Vec::new);
+        for responder in responders {
+            let server = responder.to_string();
+            if !discovered.iter().any(|spec| spec.server == server) {
+                discovered.push(server.parse().map_err(anyhow::Error::msg)?);
+            }
+        }
+    }
+    let mut ntp_client = if let Some(servers) = &servers {
+        NTPClient::new_with_multiple_servers(servers.iter().map(|spec| spec.server.as_str()))
+    } else if let Some(zone) = &query.pool_zone {
+        NTPClient::new_with_pool_zone(zone)
+    } else {
+        NTPClient::new()
+    };
+    for spec in servers.iter().flatten() {
+        ntp_client = ntp_client
+            .with_server_weight(&spec.server, spec.weight)
+            .with_server_transport(&spec.server, spec.transport.clone())
+            .with_server_pins(&spec.server, &spec.pins)
+            .with_server_trust(&spec.server, spec.trusted)
+            .with_server_asymmetry(
+                &spec.server,
+                chrono::Duration::microseconds((spec.asymmetry_ms * 1e3) as i64),
+            );
+    }
+    if let Some(dscp) = query.dscp {
+        ntp_client = ntp_client.with_dscp(dscp);
+    }
+    if let Some(interface) = &query.interface {
+        ntp_client = ntp_client.with_interface(interface.as_str());
+    }
+    if let Some(port) = query.source_port {
+        ntp_client = ntp_client.with_source_port(port);
+    }
+    Ok(ntp_client
+        .with_stratum_range(query.min_stratum..=query.max_stratum)
+        .with_interleaved(query.interleaved)
+        .with_concurrency(usize::from(query.concurrency))
+        .with_dns_timeout(Duration::from_millis(query.dns_timeout_ms)))
+}
+
+fn report_results(results: &TestResults, levels: &OffsetLevels) {
+    results
+        .get_all_results()
+        .for_each(|(server, timing)| match timing {
+            Ok(offset) => levels.log(
+                offset,
+                &format!("{server} => {offset} away from local system time"),
+            ),
+            Err(failure) => warn!("{server} => ? [{failure}]"),
+        });
+}
+
+// Senders start from a TTL of 64, 128 or 255, so the distance is roughly what was used up since the
+// nearest one above what arrived.
+fn hops(ttl: u8) -> u8 {
+    let initial = [64, 128, 255]
+        .into_iter()
+        .find(|&initial| ttl <= initial)
+        .unwrap_or(u8::MAX);
+    initial - ttl
+}
+
+fn report_sources(results: &TestResults) {
+    for (i, (server, _)) in results.get_all_results().enumerate() {
+        if let Some(reference) = results.get_reference(i) {
+            let distance = results
+                .get_ttl(i)
+                .map(|ttl| format!(", ttl {ttl} (~{} hops)", hops(ttl)))
+                .unwrap_or_default();
+            info!(
+                "{server} => stratum {}, reference {reference}{distance}",
+                reference.stratum()
+            );
+        }
+    }
+}
+
+fn sources_json(results: &TestResults) -> serde_json::Value {
+    results
+        .get_all_results()
+        .enumerate()
+        .filter_map(|(i, (server, _))| {
+            let reference = results.get_reference(i)?;
+            Some(serde_json::json!({
+                "server": server,
+                "stratum": reference.stratum(),
+                "reference_id": reference.code().map_or_else(
+                    || std::net::Ipv4Addr::from(reference.as_bytes()).to_string(),
+                    str::to_owned,
+                ),
+                "reference": reference.to_string(),
+                "ttl": results.get_ttl(i),
+                "hops": results.get_ttl(i).map(hops),
+            }))
+        })
+        .collect()
+}
+
+// Moves the measured offsets towards where the profile wants the clock, so the clock is corrected
+// to that and the offsets reported from here on are from it rather than from true time.
+fn apply_profile(results: &mut TestResults, profile: Option<&profile::OffsetProfile>) {
+    let Some(profile) = profile else {
+        return;
+    };
+    let Ok(offset) = results.get_combined_offset() else {
+        return;
+    };
+    let target = profile.offset_at(Utc::now() + offset.as_duration());
+    info!(
+        "offset profile keeps the clock {} from true time",
+        Offset::from(target)
+    );
+    results.shift(target);
+}
+
+#[derive(Debug)]
+pub struct SyncReport {
+    pub results: TestResults,
+    // The correction made, None when the clock was left alone.
+    pub applied: Option<Offset>,
+    pub stepped: bool,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sync(
+    ntp_client: &NTPClient,
+    su: bool,
+    levels: &OffsetLevels,
+    engine: &mut SyncEngine,
+    detail: bool,
+    deadline: Option<Instant>,
+    dry_run: bool,
+    profile: Option<&profile::OffsetProfile>,
+) -> Result<SyncReport> {
+    if let Some(hypervisor) = lunartick::detect_hypervisor_time_sync() {
+        warn!("{hypervisor} is also disciplining this clock, the correction may be undone");
+    }
+    let mut results = measure(ntp_client, deadline)?;
+    report_results(&results, levels);
+    if detail {
+        report_sources(&results);
+    }
+    apply_profile(&mut results, profile);
+    let correction = engine.decide(&results)?;
+    let rejected: Vec<_> = results.rejected_servers().collect();
+    if !rejected.is_empty() {
+        warn!("excluded from combined offset: {}", rejected.join(", "));
+    }
+    let stepped = correction.is_step();
+    if let Some(best) = results.best_server() {
+        let offset = Offset::from(correction.offset());
+        let how = match (stepped, dry_run) {
+            (true, false) => "stepping",
+            (false, false) => "slewing",
+            (true, true) => "dry run, would step",
+            (false, true) => "dry run, would slew",
+        };
+        levels.log(
+            offset,
+            &format!(
+                "{how} {offset}{} correction, dominated by {best}",
+                results
+                    .get_uncertainty()
+                    .map(|uncertainty| format!(" ±{}", Offset::from(uncertainty)))
+                    .unwrap_or_default()
+            ),
+        );
+    }
+    if dry_run {
+        // The engine writes to a clock that only records, so what is logged is exactly what the
+        // system clock would have been given.
+        let recorder = RecordingClock::new();
+        engine.apply_with(correction, &recorder)?;
+        for write in recorder.take_writes() {
+            match write {
+                ClockWrite::Set(time) => info!("dry run, would set the clock to {time}"),
+                ClockWrite::Slew(delta) => {
+                    info!("dry run, would slew the clock by {}", Offset::from(delta));
+                }
+                ClockWrite::Frequency(ppm) => {
+                    info!("dry run, would set the clock frequency to {ppm:+.3} ppm");
+                }
+            }
+        }
+        return Ok(SyncReport {
+            results,
+            applied: None,
+            stepped,
+        });
+    }
+    let backend: &dyn ClockBackend = if su { &SuClock } else { &SystemClock };
+    let applied = match (engine.apply_with(correction, backend), correction) {
+        (Ok(()), _) => true,
+        (Err(e), Correction::Step(offset)) => {
+            set_failed(e, &Clock::now_with_offset_duration(offset))?
+        }
+        (Err(e @ LunartickError::ClockSetDenied(_)), Correction::Slew(_)) => {
+            error!("{e} (try running as root)");
+            false
+        }
+        (Err(e), Correction::Slew(_)) => {
+            return Err(e).context("Unable to slew the clock, refusing to step it")
+        }
+    }
+    .then(|| Offset::from(correction.offset()));
+    get(GetDTFormats::Debug);
+    Ok(SyncReport {
+        results,
+        applied,
+        stepped,
+    })
+}
+
+// How often to look for links that came or went.
+const NETWORKD_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// The configured servers followed by those systemd-networkd has that are not among them, or the
+// default servers when there are neither.
+fn networkd_client(
+    servers: &[ServerSpec],
+    networkd: &[String],
+    query: &QueryArgs,
+) -> Result<NTPClient> {
+    let mut servers = servers.to_vec();
+    for server in networkd {
+        if !servers.iter().any(|spec| &spec.server == server) {
+            servers.push(server.parse().map_err(anyhow::Error::msg)?);
+        }
+    }
+    ntp_client((!servers.is_empty()).then_some(servers), query)
+}
+
+fn record(history: Option<&mut history::History>, cycle: &history::Cycle) {
+    if let Some(Err(e)) = history.map(|history| history.append(cycle)) {
+        warn!("{e:#}");
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn daemon(args: DaemonArgs) -> Result<()> {
+    if !cfg!(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly",
+        target_os = "illumos"
+    )) {
+        bail!("The daemon is only available on Linux, the BSDs and illumos");
+    }
+    info!("starting daemon service");
+    if timens::is_shifted() {
+        warn!("running in a time namespace, the wall clock set is still the host's");
+    }
+    let mut networkd_servers = Vec::new();
+    if let Some(dir) = &args.networkd {
+        networkd_servers = networkd::link_servers(dir)?;
+        info!(
+            "{} server(s) from systemd-networkd: {}",
+            networkd_servers.len(),
+            networkd_servers.join(", ")
+        );
+    }
+    let mut configured = args.servers.clone().unwrap_or_default();
+    if let Some(path) = &args.servers_file {
+        for spec in servers::load(path)? {
+            if !configured.iter().any(|known| known.server == spec.server) {
+                configured.push(spec);
+            }
+        }
+    }
+    let mut ntp_client = networkd_client(&configured, &networkd_servers, &args.query)?;
+    let offset_levels = args.offset_levels.unwrap_or_default();
+    let mut engine = attack_guard(
+        sync_engine(args.makestep.as_deref())?.with_min_networks(args.min_networks),
+        args.attack_threshold_ms,
+    );
+    if let Some(window) = args.huff_puff {
+        engine = engine.with_huff_puff(Duration::from_secs(window));
+    }
+    if let Some(time_constant) = args.time_constant {
+        engine = engine.with_discipline(Duration::from_secs(time_constant));
+    }
+    if let Some(max) = args.max_correction_per_cycle {
+        engine = engine.with_max_correction(chrono::Duration::microseconds((max * 1e3) as i64));
+    }
+    let hypervisor_report_only = match lunartick::detect_hypervisor_time_sync() {
+        Some(hypervisor) if !args.allow_hypervisor => {
+            warn!(
+                "{hypervisor} is already disciplining this clock, only reporting offsets \
+                 (pass --allow-hypervisor to set the clock anyway)"
+            );
+            true
+        }
+        Some(hypervisor) => {
+            warn!("{hypervisor} is also disciplining this clock, expect sawtooth offsets");
+            false
+        }
+        None => false,
+    };
+    let state_path = args.state_file.unwrap_or_else(state::default_path);
+    let mut state = DaemonState::load(&state_path).unwrap_or_else(|e| {
+        warn!("{e:#}, starting without saved state");
+        DaemonState::default()
+    });
+    if let Some(last_sync) = state.last_sync {
+        match state.drift_ppm {
+            Some(drift) => info!("resuming from last sync at {last_sync}, drift {drift:+.3} ppm"),
+            None => info!("resuming from last sync at {last_sync}"),
+        }
+    }
+    let control_path = args.control_socket.unwrap_or_else(control::default_path);
+    let events = EventBus::default();
+    events.set_servers(configured.clone(), args.servers_file.clone());
+    let mut servers_generation = 0;
+    if let Err(e) = control::listen(&control_path, &events) {
+        warn!("{e:#}, continuing without a control socket");
+    }
+    if let Some(addr) = args.control_listen {
+        control::listen_tcp(addr, &events)?;
+    }
+    let history_path = args
+        .history_file
+        .unwrap_or_else(|| state_path.with_file_name("history.jsonl"));
+    let mut history = match history::History::open(&history_path) {
+        Ok(history) => Some(history),
+        Err(e) => {
+            warn!("{e:#}, continuing without a history file");
+            None
+        }
+    };
+    events.set_status(state.status_json(args.holdover_error_bound)?);
+    // Starting from ok means resuming with a stale state is logged after the first cycle.
+    let mut health = state::Health::Ok;
+    if let Some(path) = &args.notify_config {
+        notify::NotifyConfig::load(path)?.watch(&events);
+    }
+    let offset_profile = args
+        .offset_profile
+        .as_deref()
+        .map(profile::OffsetProfile::load)
+        .transpose()?;
+    if let Some(dir) = args.hooks_dir {
+        hooks::watch(dir, &events)?;
+    }
+    let mut leadership = args.leader_lock.map(leader::Leadership::new).transpose()?;
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, Arc::clone(&shutdown))?;
+    }
+    let dump = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGUSR1, Arc::clone(&dump))?;
+    let poll_interval = Duration::from_secs(args.timeout);
+    let mut schedule = schedule::PollSchedule::default();
+    let mut next_sync = Instant::now();
+    let mut failures = 0;
+    let mut synchronized = false;
+    let mut watchdog = args
+        .clock_watchdog
+        .map(|ms| watchdog::ClockWatchdog::new(Duration::from_millis(ms)));
+    // Clock anomalies seen since the last cycle, an interval the drift model must not learn from.
+    let mut anomalies = Vec::new();
+    // The last comparison with the --shadow daemon.
+    let mut shadow = serde_json::Value::Null;
+    let mut tracker = tracking::Tracker::default();
+    let mut tracking = serde_json::Value::Null;
+    let mut networkd_checked = Instant::now();
+    let mut was_in_window = true;
+    let mut was_limited = false;
+    systemd::notify("STATUS=Waiting for the first sync");
+    while !shutdown.load(Ordering::Relaxed) {
+        if dump.swap(false, Ordering::Relaxed) {
+            state.log_report(poll_interval);
+        }
+        if let Some(anomaly) = watchdog.as_mut().and_then(watchdog::ClockWatchdog::check) {
+            warn!("{anomaly}, re-syncing now");
+            events.publish(Event::ClockAnomaly {
+                kind: anomaly.kind().to_owned(),
+                detail: anomaly.to_string(),
+            });
+            anomalies.push(anomaly.to_string());
+            schedule.expedite();
+            next_sync = Instant::now();
+        }
+        let mut networkd_changed = None;
+        if let Some(dir) = args
+            .networkd
+            .as_ref()
+            .filter(|_| networkd_checked.elapsed() >= NETWORKD_CHECK_INTERVAL)
+        {
+            networkd_checked = Instant::now();
+            match networkd::link_servers(dir) {
+                Ok(servers) if servers != networkd_servers => {
+                    info!(
+                        "systemd-networkd servers changed to [{}]",
+                        servers.join(", ")
+                    );
+                    networkd_changed = Some(servers);
+                }
+                Ok(_) => (),
+                Err(e) => warn!("{e:#}, keeping the previous systemd-networkd servers"),
+            }
+        }
+        let configured_changed = events.changed_servers(&mut servers_generation);
+        if networkd_changed.is_some() || configured_changed.is_some() {
+            let specs = configured_changed.unwrap_or_else(|| configured.clone());
+            let networkd = networkd_changed.unwrap_or_else(|| networkd_servers.clone());
+            match networkd_client(&specs, &networkd, &args.query) {
+                Ok(client) => {
+                    // Servers that are new to the schedule are due right away.
+                    let known = ntp_client.get_servers();
+                    if client
+                        .get_servers()
+                        .iter()
+                        .any(|server| !known.contains(server))
+                    {
+                        next_sync = Instant::now();
+                    }
+                    info!(
+                        "now querying {}",
+                        client
+                            .get_servers()
+                            .iter()
+                            .map(AsRef::as_ref)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                    ntp_client = client;
+                    configured = specs;
+                    networkd_servers = networkd;
+                }
+                Err(e) => warn!("{e:#}, keeping the previous servers"),
+            }
+        }
+        let now = Local::now().time();
+        let in_window = args.correction_window.is_empty()
+            || args
+                .correction_window
+                .iter()
+                .any(|window| window.contains(now));
+        if in_window != was_in_window {
+            if in_window {
+                // Corrections wait for the window, so it is worth starting it with one.
+                info!("correction window open, re-syncing now");
+                next_sync = Instant::now();
+            } else {
+                info!("correction window closed, only measuring until the next one");
+            }
+            was_in_window = in_window;
+        }
+        let remaining = next_sync.saturating_duration_since(Instant::now());
+        if !remaining.is_zero() {
+            std::thread::sleep(remaining.min(Duration::from_millis(250)));
+            continue;
+        }
+        if let Some(sensor) = &args.temperature_sensor {
+            state.temperature_c = thermal::read(sensor)
+                .map_err(|e| warn!("{e:#}, not correlating drift with temperature"))
+                .ok();
+        }
+        let due = schedule.due(&ntp_client, Instant::now());
+        events.publish(Event::SyncStarted {
+            servers: due.get_servers().len(),
+        });
+        let paused = events.paused();
+        let report_only = paused
+            || !in_window
+            || hypervisor_report_only
+            || leadership
+                .as_mut()
+                .is_some_and(|leadership| !leadership.is_leader());
+        let res = if report_only {
+            due.test().map_err(Into::into).map(|mut results| {
+                report_results(&results, &offset_levels);
+                apply_profile(&mut results, offset_profile.as_ref());
+                SyncReport {
+                    results,
+                    applied: None,
+                    stepped: false,
+                }
+            })
+        } else {
+            sync(
+                &due,
+                args.su,
+                &offset_levels,
+                &mut engine,
+                false,
+                None,
+                args.dry_run,
+                offset_profile.as_ref(),
+            )
+        };
+        state.counters.cycles += 1;
+        let mut explanation = engine.take_explanation().unwrap_or_else(|| Explanation {
+            correction: match &res {
+                _ if paused => "left the clock alone, corrections are paused".to_owned(),
+                _ if !in_window => format!(
+                    "left the clock alone outside the correction windows {}",
+                    args.correction_window
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                _ if report_only => {
+                    "left the clock alone, only reporting while something else sets it".to_owned()
+                }
+                Ok(_) => "left the clock alone".to_owned(),
+                Err(e) => format!("held the clock: {e:#}"),
+            },
+            ..Explanation::default()
+        });
+        if args.dry_run && !report_only {
+            explanation.correction = format!("dry run, {}", explanation.correction);
+        }
+        let explanation = explanation_json(&explanation);
+        let delay = match res {
+            Ok(report) => {
+                failures = 0;
+                publish_rejections(&events, &report.results);
+                events.publish(Event::SyncCompleted {
+                    offset_ms: report
+                        .results
+                        .applied_correction()
+                        .ok()
+                        .map(|correction| Offset::from(correction).as_millis_f64()),
+                });
+                anomalies.extend(sample_anomalies(&report.results));
+                if let (Some(source), Ok(offset)) =
+                    (args.shadow, report.results.get_combined_offset())
+                {
+                    shadow = shadow_compare(source, offset, args.shadow_threshold_ms);
+                }
+                // A correction cut short leaves an offset that is not drift, in that cycle's
+                // correction and in the next.
+                let anomalous = !anomalies.is_empty();
+                let limited = engine.is_limited();
+                let learn_drift = !anomalous && !limited && !was_limited;
+                was_limited = limited;
+                state.record_results(&report.results);
+                record(
+                    history.as_mut(),
+                    &history::Cycle::completed(&report.results, report.applied, report.stepped)
+                        .with_anomalies(std::mem::take(&mut anomalies)),
+                );
+                schedule.record(&report.results, poll_interval, Instant::now());
+                if let Some(applied) = report.applied {
+                    let offset_ms = applied.as_millis_f64();
+                    events.publish(if report.stepped {
+                        Event::ClockStepped { offset_ms }
+                    } else {
+                        Event::ClockSlewed { offset_ms }
+                    });
+                    state.counters.corrections += 1;
+                    if args.update_rtc {
+                        write_rtc();
+                    }
+                    if anomalous {
+                        info!("not learning drift across a clock anomaly");
+                    }
+                    match engine.get_frequency_ppm() {
+                        // The loop has taken the drift out of the corrections, and knows it
+                        // better.
+                        Some(frequency) => {
+                            state.record_correction(applied, Utc::now(), false);
+                            state.drift_ppm = Some(-frequency);
+                            info!("clock frequency corrected by {frequency:+.3} ppm");
+                        }
+                        None => state.record_correction(applied, Utc::now(), learn_drift),
+                    }
+                }
+                if let Some(update) = tracker.update(&report.results, report.applied, &state) {
+                    tracking = serde_json::to_value(update)?;
+                }
+                if let Err(e) = state.save(&state_path) {
+                    warn!("{e:#}");
+                }
+                // Only a clock that was actually corrected, or is kept right by someone else as
+                // during a dry run, lets time-sync.target through.
+                let synced = report.applied.is_some()
+                    || (((report_only && !paused && in_window) || args.dry_run)
+                        && report.results.get_combined_offset().is_ok());
+                if synced {
+                    let ready = if synchronized { "" } else { "READY=1\n" };
+                    synchronized = true;
+                    systemd::notify(&format!(
+                        "{ready}STATUS=Synchronized, last offset {}",
+                        report
+                            .results
+                            .get_combined_offset()
+                            .map_or_else(|_| "unknown".to_owned(), |offset| offset.to_string())
+                    ));
+                }
+                schedule
+                    .next_due(&ntp_client, Instant::now())
+                    .saturating_duration_since(Instant::now())
+            }
+            Err(e) => {
+                failures += 1;
+                state.counters.failed_cycles += 1;
+                record(
+                    history.as_mut(),
+                    &history::Cycle::failed(format!("{e:#}"))
+                        .with_anomalies(std::mem::take(&mut anomalies)),
+                );
+                if let Some(LunartickError::SuspectedAttack { offset, trusted }) = e.downcast_ref()
+                {
+                    events.publish(Event::SuspectedAttack {
+                        offset_ms: offset.as_millis_f64(),
+                        trusted_offset_ms: trusted.map(|trusted| trusted.as_millis_f64()),
+                    });
+                }
+                events.publish(Event::SyncFailed {
+                    error: format!("{e:#}"),
+                });
+                error!("{e:#}");
+                systemd::notify(&format!("STATUS=Last sync failed: {e:#}"));
+                let delay = args.backoff.delay(failures, poll_interval);
+                info!("retrying in {}s", delay.as_secs());
+                delay
+            }
+        };
+        let mut status = state.status_json(args.holdover_error_bound)?;
+        status["explain"] = explanation;
+        status["shadow"] = shadow.clone();
+        status["tracking"] = tracking.clone();
+        events.set_status(status);
+        let now = Utc::now();
+        match (health, state.health(now, args.holdover_error_bound)) {
+            (state::Health::Ok, state::Health::Degraded) => {
+                let error = state
+                    .holdover_error_ms(now)
+                    .map_or_else(|| "unknown".to_owned(), |ms| format!("{ms:.1}ms"));
+                warn!(
+                    "estimated holdover error {error} exceeds {}ms, reporting degraded",
+                    args.holdover_error_bound
+                );
+                health = state::Health::Degraded;
+            }
+            (state::Health::Degraded, state::Health::Ok) => {
+                info!("holdover error is back within bounds, reporting ok");
+                health = state::Health::Ok;
+            }
+            _ => (),
+        }
+        next_sync = Instant::now() + delay;
+        if let Some(watchdog) = &mut watchdog {
+            watchdog.reset();
+        }
+    }
+    info!("shutting down");
+    systemd::notify("STOPPING=1");
+    control::close(&control_path);
+    state.save(&state_path)
+}
+
+// Samples thrown away because the local clock jumped while they were taken.
+fn sample_anomalies(results: &TestResults) -> Vec<String> {
+    results
+        .get_all_results()
+        .filter_map(|(server, result)| match result {
+            Err(kind @ FailureKind::ClockAnomaly(_)) => {
+                Some(format!("{kind} while querying {server}"))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Compares the combined offset with what the other daemon measures, for status as JSON.
+fn shadow_compare(
+    source: shadow::ShadowSource,
+    offset: Offset,
+    threshold_ms: f64,
+) -> serde_json::Value {
+    let tracking = match shadow::query(source) {
+        Ok(tracking) => tracking,
+        Err(e) => {
+            warn!("{e:#}, not comparing with {source}");
+            return serde_json::json!({"daemon": source.to_string(), "error": format!("{e:#}")});
+        }
+    };
+    let ours = offset.as_millis_f64();
+    let divergence = ours - tracking.offset_ms;
+    let line = format!(
+        "{source} => {:.3}ms from {}, tdctld measures {ours:.3}ms, {divergence:+.3}ms apart",
+        tracking.offset_ms, tracking.reference
+    );
+    if divergence.abs() > threshold_ms {
+        warn!("{line}");
+    } else {
+        info!("{line}");
+    }
+    serde_json::json!({
+        "daemon": source.to_string(),
+        "reference": tracking.reference,
+        "offset_ms": tracking.offset_ms,
+        "tdctld_offset_ms": ours,
+        "divergence_ms": divergence,
+    })
+}
+
+// Why the last cycle did what it did, for `tdctld status --explain`.
+fn explanation_json(explanation: &Explanation) -> serde_json::Value {
+    let servers: Vec<_> = explanation
+        .servers
+        .iter()
+        .map(|verdict| {
+            serde_json::json!({
+                "server": verdict.server,
+                "accepted": verdict.accepted,
+                "reason": verdict.reason,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "servers": servers,
+        "combine": (!explanation.combine.is_empty()).then_some(&explanation.combine),
+        "correction": explanation.correction,
+    })
+}
+
+fn publish_rejections(events: &EventBus, results: &TestResults) {
+    let accepted: Vec<_> = results.get_all_results().collect();
+    for server in results.rejected_servers() {
+        let failure = accepted
+            .iter()
+            .find(|(name, _)| *name == server)
+            .and_then(|(_, result)| result.as_ref().err());
+        events.publish(Event::ServerRejected {
+            server: server.to_owned(),
+            reason: failure.map_or_else(
+                || "excluded from combined offset".to_owned(),
+                ToString::to_string,
+            ),
+            falseticker: failure.is_none(),
+        });
+        if let Some(FailureKind::AddressMismatch(address)) = failure {
+            warn!("{server} resolved to {address}, not one of its pinned addresses, DNS may have been tampered with");
+            events.publish(Event::AddressMismatch {
+                server: server.to_owned(),
+                address: address.to_string(),
+            });
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{
+    desktop,
+    watchdog::{Anomaly, ClockWatchdog},
+};
+use anyhow::Result;
+use lunartick::{NTPClient, Offset};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+fn alert(notify: bool, summary: &str, body: &str) {
+    warn!("{summary}: {body}");
+    if notify {
+        if let Err(e) = desktop::notify(summary, body) {
+            warn!("unable to show a desktop notification: {e:#}");
+        }
+    }
+}
+
+fn describe(offset: Offset) -> String {
+    let ms = offset.as_millis_f64();
+    let how = if ms > 0.0 { "behind" } else { "ahead" };
+    format!("the system clock is {:.3}s {how}", ms.abs() / 1e3)
+}
+
+// Watches the clock without setting it: the offset is measured every `interval` and reported once it
+// exceeds the threshold, and steps of the clock by more than the threshold are reported as they
+// happen. Waking from suspend measures right away, as the clock is most likely to be off then.
+pub(crate) fn monitor(
+    ntp_client: &NTPClient,
+    interval: Duration,
+    threshold_ms: u64,
+    notify: bool,
+) -> Result<()> {
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, Arc::clone(&shutdown))?;
+    }
+    let threshold = Duration::from_millis(threshold_ms);
+    let mut watchdog = ClockWatchdog::new(threshold);
+    let mut next_check = Instant::now();
+    let mut drifted = false;
+    info!(
+        "monitoring the clock every {}s, reporting offsets and steps over {threshold_ms}ms",
+        interval.as_secs()
+    );
+    while !shutdown.load(Ordering::Relaxed) {
+        match watchdog.check() {
+            Some(Anomaly::Suspended(_)) => next_check = Instant::now(),
+            Some(anomaly @ Anomaly::Jumped(_)) => {
+                alert(notify, "Clock stepped", &anomaly.to_string());
+                next_check = Instant::now();
+            }
+            Some(anomaly @ Anomaly::Frozen(_)) => {
+                alert(notify, "Clock stuck", &anomaly.to_string())
+            }
+            None => (),
+        }
+        if Instant::now() < next_check {
+            std::thread::sleep(CHECK_INTERVAL);
+            continue;
+        }
+        next_check = Instant::now() + interval;
+        let offset = match ntp_client
+            .test()
+            .and_then(|results| results.get_combined_offset())
+        {
+            Ok(offset) => offset,
+            Err(e) => {
+                warn!("unable to measure the offset: {e}");
+                continue;
+            }
+        };
+        if offset.as_millis_f64().abs() <= threshold_ms as f64 {
+            info!("{}", describe(offset));
+            if std::mem::take(&mut drifted) {
+                info!("the offset is back within {threshold_ms}ms");
+            }
+        } else if !std::mem::replace(&mut drifted, true) {
+            alert(notify, "Clock drifted", &describe(offset));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use anyhow::{Context, Result};
+use std::{io::ErrorKind, path::Path};
+
+// Where systemd-networkd keeps the state of each link, one file per interface index.
+pub(crate) const LINKS_DIR: &str = "/run/systemd/netif/links";
+
+// The NTP servers systemd-networkd has for its links, from DHCP, router advertisements or its own
+// configuration, in interface order and without duplicates. A link that goes away takes its file,
+// and so its servers, with it. Nothing is found where networkd does not run.
+pub(crate) fn link_servers(dir: &Path) -> Result<Vec<String>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(format!("Unable to read {}", dir.display())),
+    };
+    let mut links = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(index) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        // The link may go away between listing and reading it.
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => links.push((index, contents)),
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => return Err(e).context(format!("Unable to read {}", path.display())),
+        }
+    }
+    links.sort_unstable_by_key(|(index, _)| *index);
+    let mut servers: Vec<String> = Vec::new();
+    for (_, contents) in &links {
+        let listed = contents
+            .lines()
+            .filter_map(|line| line.strip_prefix("NTP="))
+            .flat_map(str::split_whitespace);
+        for server in listed {
+            if !servers.iter().any(|known| known == server) {
+                servers.push(server.to_owned());
+            }
+        }
+    }
+    Ok(servers)
+}
@@ -0,0 +1,220 @@
+use crate::control::{Event, EventBus};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct NotifyConfig {
+    // Clock steps at least this large are reported.
+    #[serde(default = "default_step_threshold_ms")]
+    step_threshold_ms: f64,
+    // Going this long without a successful sync is reported as prolonged holdover.
+    #[serde(default = "default_holdover_secs")]
+    holdover_secs: u64,
+    #[serde(default = "default_true")]
+    falsetickers: bool,
+    #[serde(default = "default_true")]
+    clock_anomalies: bool,
+    #[serde(default = "default_true")]
+    address_mismatches: bool,
+    #[serde(default = "default_true")]
+    suspected_attacks: bool,
+    #[serde(default, rename = "notifier")]
+    notifiers: Vec<Notifier>,
+}
+
+fn default_step_threshold_ms() -> f64 {
+    100.0
+}
+
+fn default_holdover_secs() -> u64 {
+    3600
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+enum Notifier {
+    Slack {
+        webhook_url: String,
+    },
+    Telegram {
+        bot_token: String,
+        chat_id: String,
+    },
+    Email {
+        to: String,
+        from: Option<String>,
+        #[serde(default = "default_sendmail")]
+        sendmail: String,
+    },
+}
+
+fn default_sendmail() -> String {
+    "sendmail".to_owned()
+}
+
+impl NotifyConfig {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).context(format!(
+            "Unable to read notification config {}",
+            path.display()
+        ))?;
+        let config: Self = toml::from_str(&contents).context(format!(
+            "Unable to parse notification config {}",
+            path.display()
+        ))?;
+        if config.notifiers.is_empty() {
+            warn!("{} does not configure any notifier", path.display());
+        }
+        Ok(config)
+    }
+
+    // Sends a notification for large steps, prolonged holdover, falsetickers, clock anomalies,
+    // pinned servers resolving elsewhere and corrections the trusted servers do not confirm
+    // published on the bus.
+    pub(crate) fn watch(self, events: &EventBus) {
+        let holdover = Duration::from_secs(self.holdover_secs);
+        let mut last_success = Instant::now();
+        let mut in_holdover = false;
+        events.subscribe(move |event| {
+            let message = match event {
+                Event::ClockStepped { offset_ms } if offset_ms.abs() >= self.step_threshold_ms => {
+                    format!("stepped the clock by {offset_ms:.3}ms")
+                }
+                Event::ServerRejected {
+                    server,
+                    falseticker: true,
+                    ..
+                } if self.falsetickers => {
+                    format!("{server} disagrees with the other servers and was rejected as a falseticker")
+                }
+                Event::SyncCompleted { .. } => {
+                    last_success = Instant::now();
+                    if !std::mem::take(&mut in_holdover) {
+                        return;
+                    }
+                    "synchronized again, holdover is over".to_owned()
+                }
+                Event::SyncFailed { error } if !in_holdover && last_success.elapsed() >= holdover => {
+                    in_holdover = true;
+                    format!(
+                        "no successful sync for {}s, holding over on the local clock ({error})",
+                        last_success.elapsed().as_secs()
+                    )
+                }
+                Event::ClockAnomaly { detail, .. } if self.clock_anomalies => format!("{detail}, re-syncing now"),
+                Event::AddressMismatch { server, address } if self.address_mismatches => {
+                    format!("{server} resolved to {address} instead of a pinned address, DNS may have been tampered with")
+                }
+                Event::SuspectedAttack { offset_ms, .. } if self.suspected_attacks => format!(
+                    "untrusted servers call for a {offset_ms:+.3}ms correction the trusted servers do not confirm, \
+                     holding the clock (their responses may be forged or delayed)"
+                ),
+                _ => return,
+            };
+            let message = format!("tdctld on {}: {message}", hostname());
+            for notifier in &self.notifiers {
+                let notifier = notifier.clone();
+                let message = message.clone();
+                std::thread::spawn(move || match notifier.send(&message) {
+                    Ok(()) => info!("sent {} notification", notifier.name()),
+                    Err(e) => warn!("unable to send {} notification: {e:#}", notifier.name()),
+                });
+            }
+        });
+    }
+}
+
+fn hostname() -> String {
+    let name = if cfg!(windows) {
+        std::env::var("COMPUTERNAME").ok()
+    } else {
+        std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .ok()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+    };
+    name.map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown host".to_owned())
+}
+
+// Quotes a value for a curl config file, which keeps webhook URLs and bot tokens out of argv.
+fn curl_quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+pub(crate) fn pipe(command: &mut Command, input: &str) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .context(format!("Unable to run {program}"))?;
+    child
+        .stdin
+        .take()
+        .context("stdin is not piped")?
+        .write_all(input.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{program} failed ({status})");
+    }
+    Ok(())
+}
+
+impl Notifier {
+    fn name(&self) -> &'static str {
+        match self {
+            Notifier::Slack { .. } => "Slack",
+            Notifier::Telegram { .. } => "Telegram",
+            Notifier::Email { .. } => "email",
+        }
+    }
+
+    fn send(&self, message: &str) -> Result<()> {
+        match self {
+            Notifier::Slack { webhook_url } => {
+                let body = serde_json::json!({ "text": message }).to_string();
+                let config = format!(
+                    "url = {}\nheader = \"Content-Type: application/json\"\ndata = {}\n",
+                    curl_quote(webhook_url),
+                    curl_quote(&body)
+                );
+                pipe(Command::new("curl").args(["-fsS", "-K", "-"]), &config)
+            }
+            Notifier::Telegram { bot_token, chat_id } => {
+                let config = format!(
+                    "url = {}\ndata-urlencode = {}\ndata-urlencode = {}\n",
+                    curl_quote(&format!(
+                        "https://api.telegram.org/bot{bot_token}/sendMessage"
+                    )),
+                    curl_quote(&format!("chat_id={chat_id}")),
+                    curl_quote(&format!("text={message}"))
+                );
+                pipe(Command::new("curl").args(["-fsS", "-K", "-"]), &config)
+            }
+            Notifier::Email { to, from, sendmail } => {
+                let from = from
+                    .as_ref()
+                    .map_or_else(String::new, |from| format!("From: {from}\n"));
+                let mail = format!("To: {to}\n{from}Subject: {message}\n\n{message}\n");
+                pipe(Command::new(sendmail).arg("-t"), &mail)
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Deserialize;
+use std::path::Path;
+
+// How far ahead of true time the clock is kept, for staging systems that run in the future to
+// test certificate expiry and scheduled jobs. The offset starts at `offset_secs` and, from `start`
+// on, grows by `ramp_secs_per_hour` until it reaches `max_offset_secs`. Negative values keep the
+// clock behind instead.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct OffsetProfile {
+    #[serde(default)]
+    offset_secs: f64,
+    #[serde(default)]
+    ramp_secs_per_hour: f64,
+    // When the ramp starts, when the daemon starts if not given.
+    start: Option<toml::value::Datetime>,
+    max_offset_secs: Option<f64>,
+    #[serde(skip)]
+    ramp_start: Option<DateTime<Utc>>,
+}
+
+impl OffsetProfile {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .context(format!("Unable to read offset profile {}", path.display()))?;
+        let mut profile: Self = toml::from_str(&contents)
+            .context(format!("Unable to parse offset profile {}", path.display()))?;
+        let values = [
+            Some(profile.offset_secs),
+            Some(profile.ramp_secs_per_hour),
+            profile.max_offset_secs,
+        ];
+        if values.into_iter().flatten().any(|value| !value.is_finite()) {
+            bail!("Offsets in {} must be finite", path.display());
+        }
+        if profile.max_offset_secs.is_some_and(|max| max < 0.0) {
+            bail!(
+                "max_offset_secs in {} is a magnitude and must not be negative",
+                path.display()
+            );
+        }
+        profile.ramp_start = Some(match &profile.start {
+            Some(start) => DateTime::parse_from_rfc3339(&start.to_string())
+                .context(format!(
+                    "start in {} must be a date and time with an offset",
+                    path.display()
+                ))?
+                .with_timezone(&Utc),
+            None => Utc::now(),
+        });
+        Ok(profile)
+    }
+
+    // The offset from true time the clock should have at `now`, itself true time.
+    pub(crate) fn offset_at(&self, now: DateTime<Utc>) -> ChronoDuration {
+        let ramping = self
+            .ramp_start
+            .map_or(ChronoDuration::zero(), |start| now - start)
+            .max(ChronoDuration::zero());
+        let hours = ramping.num_milliseconds() as f64 / 3_600_000.0;
+        let mut offset = self.offset_secs + self.ramp_secs_per_hour * hours;
+        if let Some(max) = self.max_offset_secs {
+            offset = offset.clamp(-max, max);
+        }
+        ChronoDuration::microseconds((offset * 1e6) as i64)
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, UdpSocket},
+    time::Duration,
+};
+use tracing::{info, warn};
+
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(2);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_PACKET_LENGTH: usize = 1024;
+const NTP_PORT: u16 = 123;
+
+// Accepts NTP over TCP (each packet prefixed with its 16-bit length) from clients whose network
+// blocks UDP, and forwards every packet to the upstream server over UDP.
+pub(crate) fn relay(listen: SocketAddr, upstream: &str) -> Result<()> {
+    let upstream = if upstream
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+    {
+        upstream.to_owned()
+    } else {
+        format!("{upstream}:{NTP_PORT}")
+    };
+    let listener = TcpListener::bind(listen).context(format!("Unable to listen on {listen}"))?;
+    info!("relaying NTP over TCP on {listen} to {upstream}");
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("unable to accept relay client: {e}");
+                continue;
+            }
+        };
+        let upstream = upstream.to_owned();
+        std::thread::spawn(move || {
+            let peer = stream
+                .peer_addr()
+                .map_or_else(|_| "unknown client".to_owned(), |addr| addr.to_string());
+            if let Err(e) = serve(stream, &upstream) {
+                warn!("relay for {peer} stopped: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn serve(mut stream: TcpStream, upstream: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    stream.set_write_timeout(Some(UPSTREAM_TIMEOUT))?;
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.set_read_timeout(Some(UPSTREAM_TIMEOUT))?;
+    socket.connect(upstream)?;
+    let mut packet = [0; MAX_PACKET_LENGTH];
+    loop {
+        let mut len = [0; 2];
+        match stream.read_exact(&mut len) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+        let len = usize::from(u16::from_be_bytes(len));
+        if len > MAX_PACKET_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("packet of {len} bytes is too large"),
+            ));
+        }
+        stream.read_exact(&mut packet[..len])?;
+        socket.send(&packet[..len])?;
+        let received = socket.recv(&mut packet)?;
+        let mut frame = (received as u16).to_be_bytes().to_vec();
+        frame.extend_from_slice(&packet[..received]);
+        stream.write_all(&frame)?;
+    }
+}
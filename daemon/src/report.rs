@@ -0,0 +1,279 @@
+use crate::{
+    fleet::median,
+    history::{self, Cycle},
+    ReportFormat,
+};
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 200.0;
+
+struct Day {
+    date: NaiveDate,
+    samples: usize,
+    min_ms: f64,
+    median_ms: f64,
+    max_ms: f64,
+}
+
+fn days(cycles: &[Cycle]) -> Vec<Day> {
+    let mut offsets: BTreeMap<NaiveDate, Vec<f64>> = BTreeMap::new();
+    for cycle in cycles {
+        if let Some(offset) = cycle.offset_ms {
+            offsets
+                .entry(cycle.time.date_naive())
+                .or_default()
+                .push(offset);
+        }
+    }
+    offsets
+        .into_iter()
+        .filter_map(|(date, mut offsets)| {
+            Some(Day {
+                date,
+                samples: offsets.len(),
+                min_ms: offsets.iter().copied().fold(f64::INFINITY, f64::min),
+                max_ms: offsets.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                median_ms: median(&mut offsets)?,
+            })
+        })
+        .collect()
+}
+
+struct Reachability {
+    server: String,
+    answered: usize,
+    queried: usize,
+    median_ms: Option<f64>,
+}
+
+fn reachability(cycles: &[Cycle]) -> Vec<Reachability> {
+    let mut servers: BTreeMap<&str, (usize, Vec<f64>)> = BTreeMap::new();
+    for cycle in cycles {
+        for (server, offset) in &cycle.servers {
+            let (queried, offsets) = servers.entry(server).or_default();
+            *queried += 1;
+            offsets.extend(offset);
+        }
+    }
+    servers
+        .into_iter()
+        .map(|(server, (queried, mut offsets))| Reachability {
+            server: server.to_owned(),
+            answered: offsets.len(),
+            queried,
+            median_ms: median(&mut offsets),
+        })
+        .collect()
+}
+
+fn percent(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Combined offsets over time as an SVG polyline, with a line at zero when it is in range.
+fn chart(cycles: &[Cycle], from: DateTime<Utc>, to: DateTime<Utc>) -> String {
+    let points: Vec<_> = cycles
+        .iter()
+        .filter_map(|cycle| Some((cycle.time, cycle.offset_ms?)))
+        .collect();
+    let min = points.iter().map(|p| p.1).fold(0.0, f64::min);
+    let max = points.iter().map(|p| p.1).fold(0.0, f64::max);
+    let span_ms = (to - from).num_milliseconds().max(1) as f64;
+    let range = if max > min { max - min } else { 1.0 };
+    let x = |time: DateTime<Utc>| (time - from).num_milliseconds() as f64 / span_ms * CHART_WIDTH;
+    let y = |offset: f64| (max - offset) / range * CHART_HEIGHT;
+    let polyline: Vec<_> = points
+        .iter()
+        .map(|(time, offset)| format!("{:.1},{:.1}", x(*time), y(*offset)))
+        .collect();
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{CHART_WIDTH}\" height=\"{CHART_HEIGHT}\" \
+         viewBox=\"0 0 {CHART_WIDTH} {CHART_HEIGHT}\">\n\
+         <line x1=\"0\" y1=\"{zero:.1}\" x2=\"{CHART_WIDTH}\" y2=\"{zero:.1}\" stroke=\"#ccc\"/>\n\
+         <polyline fill=\"none\" stroke=\"#36c\" points=\"{}\"/>\n\
+         </svg>\n\
+         <p>{max:+.3} ms at the top, {min:+.3} ms at the bottom</p>\n",
+        polyline.join(" "),
+        zero = y(0.0),
+    )
+}
+
+pub(crate) fn report(
+    history_file: &Path,
+    since: chrono::Duration,
+    format: ReportFormat,
+) -> Result<()> {
+    let to = Utc::now();
+    let from = to - since;
+    let cycles = history::read(history_file, from)?;
+    let failed = cycles.iter().filter(|cycle| cycle.error.is_some()).count();
+    let steps: Vec<_> = cycles
+        .iter()
+        .filter(|cycle| cycle.stepped)
+        .filter_map(|cycle| Some((cycle.time, cycle.correction_ms?)))
+        .collect();
+    let slews = cycles
+        .iter()
+        .filter(|cycle| !cycle.stepped && cycle.correction_ms.is_some())
+        .count();
+    let anomalies: Vec<_> = cycles
+        .iter()
+        .flat_map(|cycle| {
+            cycle
+                .anomalies
+                .iter()
+                .map(move |anomaly| (cycle.time, anomaly))
+        })
+        .collect();
+    let days = days(&cycles);
+    let servers = reachability(&cycles);
+    let mut out = String::new();
+    let period = format!("{} to {}", from.format("%F %R UTC"), to.format("%F %R UTC"));
+    let summary = format!(
+        "{} sync cycles, {failed} failed; {} steps and {slews} slews applied; {} clock anomalies.",
+        cycles.len(),
+        steps.len(),
+        anomalies.len()
+    );
+    match format {
+        ReportFormat::Md => {
+            writeln!(out, "# Time source report\n\n{period}\n\n{summary}\n")?;
+            writeln!(out, "## Offsets\n")?;
+            writeln!(out, "| Day | Samples | Min (ms) | Median (ms) | Max (ms) |")?;
+            writeln!(out, "| --- | ---: | ---: | ---: | ---: |")?;
+            for day in &days {
+                writeln!(
+                    out,
+                    "| {} | {} | {:+.3} | {:+.3} | {:+.3} |",
+                    day.date, day.samples, day.min_ms, day.median_ms, day.max_ms
+                )?;
+            }
+            writeln!(out, "\n## Server reachability\n")?;
+            writeln!(out, "| Server | Answered | Median offset (ms) |")?;
+            writeln!(out, "| --- | ---: | ---: |")?;
+            for server in &servers {
+                let median = server
+                    .median_ms
+                    .map_or_else(|| "-".to_owned(), |ms| format!("{ms:+.3}"));
+                writeln!(
+                    out,
+                    "| {} | {}/{} ({:.1}%) | {median} |",
+                    server.server.replace('|', "\\|"),
+                    server.answered,
+                    server.queried,
+                    percent(server.answered, server.queried)
+                )?;
+            }
+            writeln!(out, "\n## Steps applied\n")?;
+            if steps.is_empty() {
+                writeln!(out, "None.")?;
+            } else {
+                writeln!(out, "| Time | Step (ms) |")?;
+                writeln!(out, "| --- | ---: |")?;
+                for (time, ms) in &steps {
+                    writeln!(out, "| {} | {ms:+.3} |", time.format("%F %T UTC"))?;
+                }
+            }
+            writeln!(out, "\n## Clock anomalies\n")?;
+            if anomalies.is_empty() {
+                writeln!(out, "None.")?;
+            } else {
+                writeln!(out, "| Cycle | Anomaly |")?;
+                writeln!(out, "| --- | --- |")?;
+                for (time, anomaly) in &anomalies {
+                    writeln!(
+                        out,
+                        "| {} | {} |",
+                        time.format("%F %T UTC"),
+                        anomaly.replace('|', "\\|")
+                    )?;
+                }
+            }
+        }
+        ReportFormat::Html => {
+            writeln!(
+                out,
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                 <title>Time source report</title>\n</head>\n<body>\n\
+                 <h1>Time source report</h1>\n<p>{period}</p>\n<p>{summary}</p>"
+            )?;
+            writeln!(out, "<h2>Offsets</h2>\n{}", chart(&cycles, from, to))?;
+            writeln!(
+                out,
+                "<table>\n<tr><th>Day</th><th>Samples</th><th>Min (ms)</th>\
+                 <th>Median (ms)</th><th>Max (ms)</th></tr>"
+            )?;
+            for day in &days {
+                writeln!(
+                    out,
+                    "<tr><td>{}</td><td>{}</td><td>{:+.3}</td><td>{:+.3}</td><td>{:+.3}</td></tr>",
+                    day.date, day.samples, day.min_ms, day.median_ms, day.max_ms
+                )?;
+            }
+            writeln!(out, "</table>\n<h2>Server reachability</h2>")?;
+            writeln!(
+                out,
+                "<table>\n<tr><th>Server</th><th>Answered</th><th>Median offset (ms)</th></tr>"
+            )?;
+            for server in &servers {
+                let median = server
+                    .median_ms
+                    .map_or_else(|| "-".to_owned(), |ms| format!("{ms:+.3}"));
+                writeln!(
+                    out,
+                    "<tr><td>{}</td><td>{}/{} ({:.1}%)</td><td>{median}</td></tr>",
+                    escape(&server.server),
+                    server.answered,
+                    server.queried,
+                    percent(server.answered, server.queried)
+                )?;
+            }
+            writeln!(out, "</table>\n<h2>Steps applied</h2>")?;
+            if steps.is_empty() {
+                writeln!(out, "<p>None.</p>")?;
+            } else {
+                writeln!(out, "<table>\n<tr><th>Time</th><th>Step (ms)</th></tr>")?;
+                for (time, ms) in &steps {
+                    writeln!(
+                        out,
+                        "<tr><td>{}</td><td>{ms:+.3}</td></tr>",
+                        time.format("%F %T UTC")
+                    )?;
+                }
+                writeln!(out, "</table>")?;
+            }
+            writeln!(out, "<h2>Clock anomalies</h2>")?;
+            if anomalies.is_empty() {
+                writeln!(out, "<p>None.</p>")?;
+            } else {
+                writeln!(out, "<table>\n<tr><th>Cycle</th><th>Anomaly</th></tr>")?;
+                for (time, anomaly) in &anomalies {
+                    writeln!(
+                        out,
+                        "<tr><td>{}</td><td>{}</td></tr>",
+                        time.format("%F %T UTC"),
+                        escape(anomaly)
+                    )?;
+                }
+                writeln!(out, "</table>")?;
+            }
+            writeln!(out, "</body>\n</html>")?;
+        }
+    }
+    print!("{out}");
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use crate::{clock_json, ntp_client, sync, OffsetLevels, QueryArgs, ServerSpec};
+use anyhow::Result;
+use lunartick::{engine::SyncEngine, Clock, NTPClient, Offset, TestResults};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use tracing::{debug, info};
+
+// JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// Start of the range reserved for implementation-defined server errors.
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Option<Value>,
+}
+
+// measureOffset and applySync query the configured servers unless the request names its own.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServerParams {
+    servers: Option<Vec<String>>,
+}
+
+struct Error {
+    code: i64,
+    message: String,
+}
+
+impl Error {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+fn results_json(results: &TestResults) -> Value {
+    let rejected: Vec<_> = results.rejected_servers().collect();
+    let servers: Vec<_> = results
+        .get_all_results()
+        .map(|(server, result)| match result {
+            Ok(offset) => json!({
+                "server": server,
+                "offset_ms": offset.as_millis_f64(),
+                "rejected": rejected.contains(&server),
+            }),
+            Err(failure) => json!({
+                "server": server,
+                "error": failure.to_string(),
+            }),
+        })
+        .collect();
+    json!({
+        "offset_ms": results.get_combined_offset().ok().map(|offset| offset.as_millis_f64()),
+        "uncertainty_ms": results
+            .get_uncertainty()
+            .map(|uncertainty| Offset::from(uncertainty).as_millis_f64()),
+        "best_server": results.best_server(),
+        "servers": servers,
+    })
+}
+
+struct Server<'a> {
+    ntp_client: NTPClient,
+    query: &'a QueryArgs,
+    su: bool,
+    engine: SyncEngine,
+}
+
+impl Server<'_> {
+    fn client(&self, params: Option<Value>) -> Result<Option<NTPClient>, Error> {
+        let params: ServerParams = match params {
+            Some(params) => serde_json::from_value(params)
+                .map_err(|e| Error::new(INVALID_PARAMS, e.to_string()))?,
+            None => ServerParams::default(),
+        };
+        let Some(servers) = params.servers else {
+            return Ok(None);
+        };
+        let servers = servers
+            .iter()
+            .map(|server| server.parse::<ServerSpec>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::new(INVALID_PARAMS, e))?;
+        ntp_client(Some(servers), self.query)
+            .map(Some)
+            .map_err(|e| Error::new(SERVER_ERROR, format!("{e:#}")))
+    }
+
+    fn call(&mut self, method: &str, params: Option<Value>) -> Result<Value, Error> {
+        let failed = |e: anyhow::Error| Error::new(SERVER_ERROR, format!("{e:#}"));
+        match method {
+            "getTime" => Ok(clock_json(&Clock::now())),
+            "measureOffset" => {
+                let client = self.client(params)?;
+                let results = client
+                    .as_ref()
+                    .unwrap_or(&self.ntp_client)
+                    .test()
+                    .map_err(|e| failed(e.into()))?;
+                Ok(results_json(&results))
+            }
+            "applySync" => {
+                let client = self.client(params)?;
+                let report = sync(
+                    client.as_ref().unwrap_or(&self.ntp_client),
+                    self.su,
+                    &OffsetLevels::default(),
+                    &mut self.engine,
+                    false,
+                    None,
+                    false,
+                    None,
+                )
+                .map_err(failed)?;
+                let mut result = results_json(&report.results);
+                result["applied_ms"] = json!(report.applied.map(|applied| applied.as_millis_f64()));
+                result["stepped"] = json!(report.stepped && report.applied.is_some());
+                Ok(result)
+            }
+            method => Err(Error::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method {method}"),
+            )),
+        }
+    }
+
+    // Returns the response to a line, None for a notification.
+    fn handle(&mut self, line: &str) -> Option<Value> {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => {
+                return Some(error_response(
+                    Value::Null,
+                    Error::new(PARSE_ERROR, e.to_string()),
+                ))
+            }
+        };
+        // Absent for notifications, which get no response.
+        let id = request.get("id").cloned();
+        let request = match serde_json::from_value::<Request>(request) {
+            Ok(request) if request.jsonrpc == "2.0" => request,
+            Ok(_) => {
+                return Some(error_response(
+                    id.unwrap_or(Value::Null),
+                    Error::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""),
+                ))
+            }
+            Err(e) => {
+                return Some(error_response(
+                    id.unwrap_or(Value::Null),
+                    Error::new(INVALID_REQUEST, e.to_string()),
+                ))
+            }
+        };
+        debug!("rpc call {}", request.method);
+        let result = self.call(&request.method, request.params);
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(e) => error_response(id, e),
+        })
+    }
+}
+
+fn error_response(id: Value, error: Error) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": error.code, "message": error.message},
+    })
+}
+
+// One request per line on stdin and one response per line on stdout, until stdin is closed. Logs
+// go to stderr as usual.
+pub(crate) fn serve_stdio(ntp_client: NTPClient, query: &QueryArgs, su: bool) -> Result<()> {
+    info!("serving JSON-RPC on stdio");
+    let mut server = Server {
+        ntp_client,
+        query,
+        su,
+        engine: SyncEngine::new(),
+    };
+    let mut stdout = io::stdout();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = server.handle(&line) {
+            writeln!(stdout, "{response}")?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
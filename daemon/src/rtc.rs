@@ -0,0 +1,195 @@
+use crate::{gauges, StatusFormat};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Offset, TimeZone, Utc};
+use lunartick::RtcTimescale;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+// Weight given to the newest drift measurement.
+const SMOOTHING: f64 = 0.3;
+// Comparisons closer together than this are too short to measure drift with a clock that only
+// counts whole seconds.
+const MIN_ELAPSED_SECS: i64 = 3600;
+// Far outside what a healthy crystal does, typical of a failing battery or oscillator.
+const DRIFT_WARNING_PPM: f64 = 100.0;
+// An RTC this far off was most likely reset to its epoch when it lost power.
+const RESET_WARNING_SECS: i64 = 365 * 86400;
+// How close the offset has to be to the UTC offset of the local time zone to suggest the RTC is kept
+// in the other timescale.
+const TIMESCALE_TOLERANCE_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Comparison {
+    time: DateTime<Utc>,
+    offset_ms: f64,
+}
+
+// What previous comparisons learned about the RTC, kept apart from the daemon's state.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RtcState {
+    baseline: Option<Comparison>,
+    drift_ppm: Option<f64>,
+    #[serde(default)]
+    measurements: u64,
+}
+
+pub(crate) fn default_state_path() -> PathBuf {
+    crate::state::default_path().with_file_name("rtc.json")
+}
+
+impl RtcState {
+    fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .context(format!("Unable to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context(format!("Unable to read {}", path.display())),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context(format!("Unable to create {}", dir.display()))?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)
+            .context(format!("Unable to write {}", path.display()))
+    }
+
+    // Measures drift against the baseline once enough time has passed, then moves the baseline.
+    fn record(&mut self, comparison: Comparison) {
+        let Some(baseline) = self.baseline else {
+            self.baseline = Some(comparison);
+            return;
+        };
+        let elapsed = comparison.time - baseline.time;
+        if elapsed.num_seconds() < MIN_ELAPSED_SECS {
+            return;
+        }
+        // Positive when the RTC gains time on the system clock.
+        let drift =
+            (comparison.offset_ms - baseline.offset_ms) / elapsed.num_milliseconds() as f64 * 1e6;
+        self.drift_ppm = Some(match self.drift_ppm {
+            Some(previous) => previous + SMOOTHING * (drift - previous),
+            None => drift,
+        });
+        self.measurements += 1;
+        self.baseline = Some(comparison);
+    }
+}
+
+// An RTC read in the wrong timescale is off by exactly the local UTC offset, give or take drift.
+fn check_timescale(timescale: RtcTimescale, offset: chrono::Duration, at: DateTime<Utc>) {
+    let utc_offset = i64::from(
+        Local
+            .offset_from_utc_datetime(&at.naive_utc())
+            .fix()
+            .local_minus_utc(),
+    );
+    if utc_offset == 0 {
+        return;
+    }
+    let (expected, other) = match timescale {
+        RtcTimescale::Utc => (utc_offset, "local time"),
+        RtcTimescale::Local => (-utc_offset, "UTC"),
+    };
+    if (offset.num_seconds() - expected).abs() <= TIMESCALE_TOLERANCE_SECS {
+        warn!(
+            "the hardware clock looks like it is kept in {other}, pass --timescale or fix {}",
+            lunartick::ADJTIME_PATH
+        );
+    }
+}
+
+pub(crate) fn compare(
+    device: &Path,
+    state_path: &Path,
+    timescale: Option<RtcTimescale>,
+    format: StatusFormat,
+) -> Result<()> {
+    let timescale = match timescale {
+        Some(timescale) => timescale,
+        None => RtcTimescale::from_adjtime(Path::new(lunartick::ADJTIME_PATH))
+            .context(format!("Unable to read {}", lunartick::ADJTIME_PATH))?,
+    };
+    let reading = lunartick::read_rtc(device).context(format!(
+        "Unable to read the hardware clock {}",
+        device.display()
+    ))?;
+    let rtc = timescale.to_utc(reading.rtc).context(format!(
+        "The hardware clock reads {}, which does not exist in the local time zone",
+        reading.rtc
+    ))?;
+    let offset = rtc - reading.system;
+    let offset_ms = offset.num_milliseconds() as f64;
+    let mut state = RtcState::load(state_path)?;
+    state.record(Comparison {
+        time: reading.system,
+        offset_ms,
+    });
+    if let Err(e) = state.save(state_path) {
+        warn!("{e:#}, the drift rate will not be learned");
+    }
+    match format {
+        StatusFormat::Text => {
+            info!(
+                "hardware clock => {rtc} ({:+.3}s from the system clock, kept in {})",
+                offset_ms / 1e3,
+                match timescale {
+                    RtcTimescale::Utc => "UTC",
+                    RtcTimescale::Local => "local time",
+                }
+            );
+            if offset.num_seconds().abs() > RESET_WARNING_SECS {
+                warn!("the hardware clock looks reset, its battery may be dead");
+            } else {
+                check_timescale(timescale, offset, reading.system);
+            }
+            match state.drift_ppm {
+                Some(drift) if drift.abs() > DRIFT_WARNING_PPM => warn!(
+                    "rtc drift => {drift:+.3} ppm ({:+.3}s/day), far beyond a healthy crystal, \
+                     check its battery",
+                    drift * 86400.0 / 1e6
+                ),
+                Some(drift) => info!(
+                    "rtc drift => {drift:+.3} ppm ({:+.3}s/day) over {} measurements",
+                    drift * 86400.0 / 1e6,
+                    state.measurements
+                ),
+                None => {
+                    info!("rtc drift => unknown, compare again in an hour or more to measure it")
+                }
+            }
+        }
+        StatusFormat::Json => {
+            let json = serde_json::json!({
+                "rtc": rtc,
+                "timescale": match timescale {
+                    RtcTimescale::Utc => "utc",
+                    RtcTimescale::Local => "local",
+                },
+                "system": reading.system,
+                "offset_ms": offset_ms,
+                "drift_ppm": state.drift_ppm,
+                "measurements": state.measurements,
+            });
+            println!("{json}");
+        }
+        StatusFormat::Prometheus => {
+            let metrics = [
+                (
+                    "tdctld_rtc_offset_seconds",
+                    "Hardware clock minus system clock",
+                    Some(offset_ms / 1e3),
+                ),
+                (
+                    "tdctld_rtc_drift_ppm",
+                    "Rate the hardware clock gains on the system clock in parts per million",
+                    state.drift_ppm,
+                ),
+            ];
+            print!("{}", gauges(metrics));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use crate::StatusFormat;
+use anyhow::Result;
+use lunartick::{FailureKind, NTPClient, Offset};
+use std::{fmt, net::Ipv4Addr, str::FromStr, time::Duration};
+use tracing::{info, warn};
+
+// Scanning a /16 already takes 65536 requests, anything larger is more likely a typo.
+const MIN_PREFIX: u8 = 16;
+
+// An IPv4 network such as `10.0.0.0/24`. A bare address is a /32.
+#[derive(Debug, Clone, Copy)]
+pub struct Subnet {
+    network: Ipv4Addr,
+    prefix: u8,
+}
+
+impl Subnet {
+    // The network and broadcast addresses are skipped, except in /31 and /32 networks which have
+    // neither.
+    fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let first = u32::from(self.network);
+        let last = first | u32::MAX.checked_shr(u32::from(self.prefix)).unwrap_or(0);
+        let (first, last) = if self.prefix < 31 {
+            (first + 1, last - 1)
+        } else {
+            (first, last)
+        };
+        (first..=last).map(Ipv4Addr::from)
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = s.split_once('/').unwrap_or((s, "32"));
+        let address: Ipv4Addr = address
+            .parse()
+            .map_err(|_| format!("invalid IPv4 address '{address}'"))?;
+        let prefix: u8 = prefix
+            .parse()
+            .ok()
+            .filter(|prefix| *prefix <= 32)
+            .ok_or_else(|| format!("invalid prefix length '{prefix}'"))?;
+        if prefix < MIN_PREFIX {
+            return Err(format!(
+                "refusing to scan a /{prefix}, networks larger than a /{MIN_PREFIX} are not supported"
+            ));
+        }
+        let mask = u32::MAX.checked_shl(u32::from(32 - prefix)).unwrap_or(0);
+        Ok(Self {
+            network: Ipv4Addr::from(u32::from(address) & mask),
+            prefix,
+        })
+    }
+}
+
+enum Answer {
+    Time {
+        offset: Offset,
+        stratum: u8,
+        reference: String,
+    },
+    // Answered, but not with usable time: a kiss-o'-death, an unsynchronized clock or a malformed
+    // packet. Worth knowing about all the same.
+    Unusable(String),
+}
+
+struct Responder {
+    address: Ipv4Addr,
+    answer: Answer,
+}
+
+// Sends one client request to every host of the network and lists those that answer. Offsets are
+// from this host's clock, so a responder far off from the others is the suspect one.
+pub(crate) fn scan(
+    subnet: Subnet,
+    port: u16,
+    concurrency: u16,
+    timeout: Duration,
+    threshold_ms: f64,
+    format: &StatusFormat,
+) -> Result<()> {
+    let hosts: Vec<_> = subnet.hosts().collect();
+    info!(
+        "probing {} addresses in {subnet} on port {port}",
+        hosts.len()
+    );
+    let results =
+        NTPClient::new_with_multiple_servers(hosts.iter().map(|host| format!("{host}:{port}")))
+            .with_concurrency(usize::from(concurrency))
+            .with_timeout(timeout)
+            .test()?;
+    let responders: Vec<_> = results
+        .get_all_results()
+        .enumerate()
+        .filter_map(|(i, (_, result))| {
+            let answer = match result {
+                Ok(offset) => {
+                    let reference = results.get_reference(i)?;
+                    Answer::Time {
+                        offset,
+                        stratum: reference.stratum(),
+                        reference: reference.to_string(),
+                    }
+                }
+                // Nothing came back, or nothing that could be told apart from silence.
+                Err(FailureKind::Timeout | FailureKind::Network(_)) => return None,
+                Err(failure) => Answer::Unusable(failure.to_string()),
+            };
+            Some(Responder {
+                address: hosts[i],
+                answer,
+            })
+        })
+        .collect();
+    match format {
+        StatusFormat::Text => {
+            for responder in &responders {
+                match &responder.answer {
+                    Answer::Time {
+                        offset,
+                        stratum,
+                        reference,
+                    } => {
+                        let line = format!(
+                            "{} => stratum {stratum}, reference {reference}, {offset} from this host",
+                            responder.address
+                        );
+                        if offset.as_millis_f64().abs() > threshold_ms {
+                            warn!("{line}");
+                        } else {
+                            info!("{line}");
+                        }
+                    }
+                    Answer::Unusable(reason) => warn!("{} => ? [{reason}]", responder.address),
+                }
+            }
+            info!(
+                "{} of {} addresses in {subnet} answered",
+                responders.len(),
+                hosts.len()
+            );
+        }
+        StatusFormat::Json => {
+            let responders: Vec<_> = responders
+                .iter()
+                .map(|responder| match &responder.answer {
+                    Answer::Time {
+                        offset,
+                        stratum,
+                        reference,
+                    } => serde_json::json!({
+                        "address": responder.address.to_string(),
+                        "stratum": stratum,
+                        "reference": reference,
+                        "offset_ms": offset.as_millis_f64(),
+                    }),
+                    Answer::Unusable(reason) => serde_json::json!({
+                        "address": responder.address.to_string(),
+                        "error": reason,
+                    }),
+                })
+                .collect();
+            let json = serde_json::json!({
+                "network": subnet.to_string(),
+                "scanned": hosts.len(),
+                "responders": responders,
+            });
+            println!("{json}");
+        }
+        StatusFormat::Prometheus => {
+            let mut out = String::from(
+                "# HELP tdctld_scan_offset_seconds Responder clock minus the local clock\n\
+                 # TYPE tdctld_scan_offset_seconds gauge\n",
+            );
+            for responder in &responders {
+                if let Answer::Time { offset, .. } = responder.answer {
+                    out.push_str(&format!(
+                        "tdctld_scan_offset_seconds{{address=\"{}\"}} {}\n",
+                        responder.address,
+                        offset.as_millis_f64() / 1e3
+                    ));
+                }
+            }
+            out.push_str(
+                "# HELP tdctld_scan_stratum Stratum the responder claims\n\
+                 # TYPE tdctld_scan_stratum gauge\n",
+            );
+            for responder in &responders {
+                if let Answer::Time { stratum, .. } = responder.answer {
+                    out.push_str(&format!(
+                        "tdctld_scan_stratum{{address=\"{}\"}} {stratum}\n",
+                        responder.address
+                    ));
+                }
+            }
+            print!("{out}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use lunartick::{NTPClient, TestResults};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+const COALESCE: Duration = Duration::from_secs(1);
+
+// When each server may be polled next. A server is never polled more often than the poll
+// interval it advertises, even if the configured interval is shorter.
+#[derive(Default)]
+pub(crate) struct PollSchedule {
+    next: HashMap<String, Instant>,
+    hints: HashMap<String, Duration>,
+}
+
+impl PollSchedule {
+    // Servers without a hint that are due within a second join the cycle, rather than being polled
+    // in a cycle of their own right after it.
+    pub(crate) fn due(&self, ntp_client: &NTPClient, now: Instant) -> NTPClient {
+        ntp_client.filter_servers(|server| {
+            let slack = if self.hints.contains_key(server) {
+                Duration::ZERO
+            } else {
+                COALESCE
+            };
+            self.next
+                .get(server)
+                .is_none_or(|next| *next <= now + slack)
+        })
+    }
+
+    pub(crate) fn record(&mut self, results: &TestResults, poll_interval: Duration, now: Instant) {
+        for i in 0.. {
+            let Some(server) = results.get_server(i) else {
+                break;
+            };
+            let hint = results.get_poll_interval(i);
+            if let Some(hint) = hint.filter(|hint| *hint > poll_interval) {
+                if self.hints.get(server) != Some(&hint) {
+                    info!(
+                        "{server} asks to be polled at most every {}s, polling it less often",
+                        hint.as_secs()
+                    );
+                }
+                self.hints.insert(server.to_owned(), hint);
+            } else {
+                self.hints.remove(server);
+            }
+            let interval = hint.map_or(poll_interval, |hint| hint.max(poll_interval));
+            self.next.insert(server.to_owned(), now + interval);
+        }
+    }
+
+    // Makes every server due now, keeping the hints for the cycles after.
+    pub(crate) fn expedite(&mut self) {
+        self.next.clear();
+    }
+
+    // The earliest time any server of the client is due.
+    pub(crate) fn next_due(&self, ntp_client: &NTPClient, now: Instant) -> Instant {
+        ntp_client
+            .get_servers()
+            .iter()
+            .map(|server| self.next.get(server.as_ref()).copied().unwrap_or(now))
+            .min()
+            .unwrap_or(now)
+    }
+}
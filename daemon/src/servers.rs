@@ -0,0 +1,50 @@
+use crate::ServerSpec;
+use anyhow::{Context, Result};
+use std::{io::ErrorKind, path::Path};
+
+// A servers file lists one server per line as given to --servers, with `#` comments. A file that
+// does not exist yet lists none.
+pub(crate) fn load(path: &Path) -> Result<Vec<ServerSpec>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(format!("Unable to read {}", path.display())),
+    };
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.parse()
+                .map_err(|e| anyhow::anyhow!("Invalid server '{line}' in {}: {e}", path.display()))
+        })
+        .collect()
+}
+
+// Replaces the file through a rename, so the daemon never reads half of it on start.
+pub(crate) fn save(path: &Path, servers: &[ServerSpec]) -> Result<()> {
+    let mut contents = String::from("# Written by tdctld servers add/remove --persist\n");
+    for server in servers {
+        contents.push_str(&format!("{server}\n"));
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, contents).context(format!("Unable to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).context(format!("Unable to replace {}", path.display()))
+}
+
+// Sends one `servers` command to the running daemon and prints its answer.
+pub(crate) fn request(control_socket: &Path, command: &str) -> Result<()> {
+    let response = crate::control::command(control_socket, command)?;
+    if let Some(message) = response["message"].as_str() {
+        println!("{message}");
+    }
+    if let Some(servers) = response["servers"].as_array() {
+        if servers.is_empty() {
+            println!("no servers configured");
+        }
+        for server in servers.iter().filter_map(serde_json::Value::as_str) {
+            println!("{server}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use lunartick::Offset;
+use std::str::FromStr;
+use tracing::{debug, error, info, trace, warn, Level};
+
+// Maps the magnitude of an offset to the level it is logged at, e.g. `5ms:debug,100ms:info,1s:warn,error`
+// logs offsets below 5ms at debug, below 100ms at info, below 1s at warn and anything larger at error.
+#[derive(Debug, Clone)]
+pub struct OffsetLevels {
+    thresholds: Vec<(f64, Level)>,
+    otherwise: Level,
+}
+
+impl Default for OffsetLevels {
+    fn default() -> Self {
+        Self {
+            thresholds: Vec::new(),
+            otherwise: Level::INFO,
+        }
+    }
+}
+
+impl OffsetLevels {
+    pub(crate) fn level(&self, offset: Offset) -> Level {
+        let millis = offset.as_millis_f64().abs();
+        self.thresholds
+            .iter()
+            .find(|(below_ms, _)| millis < *below_ms)
+            .map_or(self.otherwise, |(_, level)| *level)
+    }
+
+    pub(crate) fn log(&self, offset: Offset, message: &str) {
+        match self.level(offset) {
+            Level::ERROR => error!(target: "tdctld", "{message}"),
+            Level::WARN => warn!(target: "tdctld", "{message}"),
+            Level::INFO => info!(target: "tdctld", "{message}"),
+            Level::DEBUG => debug!(target: "tdctld", "{message}"),
+            _ => trace!(target: "tdctld", "{message}"),
+        }
+    }
+}
+
+fn parse_millis(threshold: &str) -> Option<f64> {
+    let (value, scale) = if let Some(value) = threshold.strip_suffix("ms") {
+        (value, 1.0)
+    } else if let Some(value) = threshold.strip_suffix("us") {
+        (value, 1e-3)
+    } else if let Some(value) = threshold.strip_suffix('s') {
+        (value, 1e3)
+    } else {
+        (threshold, 1.0)
+    };
+    let millis = value.trim().parse::<f64>().ok()? * scale;
+    (millis.is_finite() && millis >= 0.0).then_some(millis)
+}
+
+impl FromStr for OffsetLevels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut levels = Self::default();
+        let mut entries = s.split(',').map(str::trim).peekable();
+        while let Some(entry) = entries.next() {
+            let parse_level = |level: &str| {
+                Level::from_str(level.trim())
+                    .map_err(|_| format!("unknown log level '{}'", level.trim()))
+            };
+            match entry.split_once(':') {
+                Some((threshold, level)) => {
+                    let below_ms = parse_millis(threshold)
+                        .ok_or_else(|| format!("invalid threshold '{threshold}'"))?;
+                    if levels.thresholds.last().is_some_and(|(last, _)| *last >= below_ms) {
+                        return Err("thresholds must be in increasing order".to_owned());
+                    }
+                    levels.thresholds.push((below_ms, parse_level(level)?));
+                }
+                None if entries.peek().is_none() => levels.otherwise = parse_level(entry)?,
+                None => {
+                    return Err(format!(
+                        "expected <threshold>:<level> in '{entry}', only the last entry may be a bare level"
+                    ))
+                }
+            }
+        }
+        Ok(levels)
+    }
+}
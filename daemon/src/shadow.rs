@@ -0,0 +1,105 @@
+use anyhow::{bail, Context, Result};
+use std::{fmt, process::Command, str::FromStr};
+
+// Another time daemon to compare offsets with, when trialling tdctld next to it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ShadowSource {
+    Chrony,
+    Ntpd,
+}
+
+impl FromStr for ShadowSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "chrony" | "chronyd" => Ok(Self::Chrony),
+            "ntp" | "ntpd" => Ok(Self::Ntpd),
+            _ => Err(format!("unknown daemon '{s}', expected chrony or ntpd")),
+        }
+    }
+}
+
+impl fmt::Display for ShadowSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Chrony => "chrony",
+            Self::Ntpd => "ntpd",
+        })
+    }
+}
+
+// What the other daemon makes of the clock, with the offset signed as tdctld signs it: positive
+// when NTP time is ahead of the system clock.
+pub(crate) struct Tracking {
+    pub(crate) offset_ms: f64,
+    pub(crate) reference: String,
+}
+
+fn run(command: &mut Command) -> Result<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .context(format!("Unable to run {program}"))?;
+    if !output.status.success() {
+        bail!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub(crate) fn query(source: ShadowSource) -> Result<Tracking> {
+    match source {
+        ShadowSource::Chrony => {
+            let output = run(Command::new("chronyc").args(["-c", "tracking"]))?;
+            parse_chrony(&output)
+        }
+        ShadowSource::Ntpd => {
+            let output = run(Command::new("ntpq").args(["-c", "rv 0 offset,refid"]))?;
+            parse_ntpq(&output)
+        }
+    }
+}
+
+// `chronyc -c tracking` prints one line of comma-separated fields: the reference ID, its name,
+// the stratum, the reference time, then how far the system clock is slow of NTP time in seconds,
+// which chrony is still correcting.
+fn parse_chrony(output: &str) -> Result<Tracking> {
+    let fields: Vec<_> = output.trim().split(',').collect();
+    let (Some(name), Some(correction)) = (fields.get(1), fields.get(4)) else {
+        bail!(
+            "Unable to parse chronyc tracking output '{}'",
+            output.trim()
+        );
+    };
+    let seconds: f64 = correction.parse().context(format!(
+        "Unable to parse chrony's system time '{correction}'"
+    ))?;
+    Ok(Tracking {
+        offset_ms: seconds * 1e3,
+        reference: (*name).to_owned(),
+    })
+}
+
+// `ntpq -c 'rv 0 offset,refid'` prints `offset=-0.123, refid=192.0.2.1`, the offset in
+// milliseconds and signed as tdctld signs it.
+fn parse_ntpq(output: &str) -> Result<Tracking> {
+    let mut offset = None;
+    let mut reference = String::new();
+    for variable in output.split([',', '\n']) {
+        match variable.trim().split_once('=') {
+            Some(("offset", value)) => offset = value.trim().parse::<f64>().ok(),
+            Some(("refid", value)) => reference = value.trim().to_owned(),
+            _ => (),
+        }
+    }
+    let Some(offset_ms) = offset else {
+        bail!("Unable to parse ntpq output '{}'", output.trim());
+    };
+    Ok(Tracking {
+        offset_ms,
+        reference,
+    })
+}
@@ -0,0 +1,249 @@
+use crate::{stats::Rolling, thermal::TemperatureModel};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use lunartick::{Offset, TestResults};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tracing::info;
+
+// Weight given to the newest sample when smoothing drift and server scores.
+const SMOOTHING: f64 = 0.3;
+// Number of offsets kept per server for `tdctld status --detail`.
+const HISTORY: usize = 64;
+// Drift uncertainty assumed until it has been measured, typical of an undisciplined crystal.
+const UNKNOWN_DRIFT_PPM: f64 = 50.0;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct DaemonState {
+    pub(crate) drift_ppm: Option<f64>,
+    #[serde(default)]
+    pub(crate) drift_variance_ppm2: Option<f64>,
+    pub(crate) last_offset_ms: Option<f64>,
+    pub(crate) last_sync: Option<DateTime<Utc>>,
+    // Last cycle that produced a combined offset, whether or not the clock was corrected.
+    #[serde(default)]
+    pub(crate) last_success: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub(crate) servers: BTreeMap<String, ServerScore>,
+    #[serde(default)]
+    pub(crate) counters: Counters,
+    // Combined offsets of recent cycles and the corrections applied, for `tdctld stats`.
+    #[serde(default)]
+    pub(crate) offset_histogram: Rolling,
+    #[serde(default)]
+    pub(crate) correction_histogram: Rolling,
+    // The last reading of --temperature-sensor, and how drift has followed it.
+    #[serde(default)]
+    pub(crate) temperature_c: Option<f64>,
+    #[serde(default)]
+    pub(crate) temperature_model: TemperatureModel,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Counters {
+    pub(crate) cycles: u64,
+    pub(crate) corrections: u64,
+    pub(crate) failed_cycles: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ServerScore {
+    pub(crate) score: f64,
+    pub(crate) successes: u64,
+    pub(crate) failures: u64,
+    pub(crate) last_offset_ms: Option<f64>,
+    // Oldest first, with None for cycles where the server did not answer.
+    #[serde(default)]
+    pub(crate) history: VecDeque<Option<f64>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Health {
+    Ok,
+    Degraded,
+}
+
+impl Default for ServerScore {
+    fn default() -> Self {
+        Self {
+            score: 1.0,
+            successes: 0,
+            failures: 0,
+            last_offset_ms: None,
+            history: VecDeque::new(),
+        }
+    }
+}
+
+pub(crate) fn default_path() -> PathBuf {
+    if cfg!(windows) {
+        std::env::var_os("ProgramData")
+            .map_or_else(|| PathBuf::from(r"C:\ProgramData"), PathBuf::from)
+            .join("tdctld")
+            .join("state.json")
+    } else {
+        PathBuf::from("/var/lib/tdctld/state.json")
+    }
+}
+
+impl DaemonState {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .context(format!("Unable to parse state file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context(format!("Unable to read state file {}", path.display())),
+        }
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context(format!("Unable to create {}", dir.display()))?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .context(format!("Unable to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).context(format!("Unable to replace {}", path.display()))
+    }
+
+    pub(crate) fn log_report(&self, poll_interval: Duration) {
+        info!("status report:");
+        info!("  poll interval => {}s", poll_interval.as_secs());
+        match self.last_sync {
+            Some(last_sync) => info!("  last sync => {last_sync}"),
+            None => info!("  last sync => never"),
+        }
+        if let Some(offset) = self.last_offset_ms {
+            info!("  last correction => {offset:.3}ms");
+        }
+        match self.drift_ppm {
+            Some(drift) => info!("  drift => {drift:+.3} ppm"),
+            None => info!("  drift => unknown"),
+        }
+        if let Some(temperature) = self.temperature_c {
+            match self.temperature_model.coefficient_ppm_per_c() {
+                Some(coefficient) => {
+                    info!("  temperature => {temperature:.1}°C, drift {coefficient:+.3} ppm/°C")
+                }
+                None => info!("  temperature => {temperature:.1}°C, drift dependence unknown"),
+            }
+        }
+        let Counters {
+            cycles,
+            corrections,
+            failed_cycles,
+        } = self.counters;
+        info!("  cycles => {cycles} ({corrections} corrections, {failed_cycles} failed)");
+        for (server, score) in &self.servers {
+            let offset = score
+                .last_offset_ms
+                .map_or_else(|| "?".to_owned(), |ms| format!("{ms:.3}ms"));
+            info!(
+                "  {server} => score {:.2}, {} ok, {} failed, last offset {offset}",
+                score.score, score.successes, score.failures
+            );
+        }
+    }
+
+    pub(crate) fn record_results(&mut self, results: &TestResults) {
+        for (server, result) in results.get_all_results() {
+            let score = self.servers.entry(server.to_owned()).or_default();
+            let sample = match result {
+                Ok(offset) => {
+                    score.successes += 1;
+                    score.last_offset_ms = Some(offset.as_millis_f64());
+                    1.0
+                }
+                Err(_) => {
+                    score.failures += 1;
+                    0.0
+                }
+            };
+            score.score += SMOOTHING * (sample - score.score);
+            if score.history.len() == HISTORY {
+                score.history.pop_front();
+            }
+            score
+                .history
+                .push_back(result.as_ref().ok().map(Offset::as_millis_f64));
+        }
+        if let Ok(offset) = results.get_combined_offset() {
+            self.last_success = Some(Utc::now());
+            self.offset_histogram.push(offset.as_millis_f64());
+        }
+    }
+
+    // How far the clock may have wandered since the last successful sync, given how much the
+    // measured drift varies, or how it follows the temperature once that is known.
+    pub(crate) fn holdover_error_ms(&self, now: DateTime<Utc>) -> Option<f64> {
+        let since = self.last_success.or(self.last_sync)?;
+        let elapsed_secs = (now - since).num_milliseconds().max(0) as f64 / 1e3;
+        let uncertainty_ppm = self
+            .drift_ppm
+            .zip(self.temperature_c)
+            .and_then(|(drift, temperature)| {
+                self.temperature_model
+                    .drift_uncertainty_ppm(drift, temperature)
+            })
+            .or_else(|| self.drift_variance_ppm2.map(f64::sqrt))
+            .unwrap_or(UNKNOWN_DRIFT_PPM);
+        Some(uncertainty_ppm * elapsed_secs / 1e3)
+    }
+
+    pub(crate) fn health(&self, now: DateTime<Utc>, bound_ms: f64) -> Health {
+        match self.holdover_error_ms(now) {
+            Some(error_ms) if error_ms <= bound_ms => Health::Ok,
+            _ => Health::Degraded,
+        }
+    }
+
+    // The state as served over the control socket, with the health derived from it.
+    pub(crate) fn status_json(&self, bound_ms: f64) -> Result<serde_json::Value> {
+        let now = Utc::now();
+        let mut status = serde_json::to_value(self)?;
+        status["health"] = serde_json::to_value(self.health(now, bound_ms))?;
+        status["holdover_error_ms"] = serde_json::to_value(self.holdover_error_ms(now))?;
+        Ok(status)
+    }
+
+    // A correction spanning a step or pause of the clock by someone else says nothing about drift.
+    pub(crate) fn record_correction(
+        &mut self,
+        correction: Offset,
+        at: DateTime<Utc>,
+        learn_drift: bool,
+    ) {
+        let offset_ms = correction.as_millis_f64();
+        if let Some(last_sync) = self.last_sync.filter(|_| learn_drift) {
+            let elapsed_ms = (at - last_sync).num_milliseconds() as f64;
+            if elapsed_ms > 0.0 {
+                // Positive when the local clock runs fast, i.e. had to be set back.
+                let drift = -offset_ms / elapsed_ms * 1e6;
+                // Temperature changes slowly next to the polling interval, the reading at the end
+                // of the interval stands for all of it.
+                if let Some(temperature) = self.temperature_c {
+                    self.temperature_model.record(temperature, drift);
+                }
+                self.drift_ppm = Some(match self.drift_ppm {
+                    Some(previous) => {
+                        let deviation = (drift - previous).powi(2);
+                        self.drift_variance_ppm2 = Some(match self.drift_variance_ppm2 {
+                            Some(variance) => variance + SMOOTHING * (deviation - variance),
+                            None => deviation,
+                        });
+                        previous + SMOOTHING * (drift - previous)
+                    }
+                    None => drift,
+                });
+            }
+        }
+        self.last_offset_ms = Some(offset_ms);
+        self.last_sync = Some(at);
+        self.correction_histogram.push(offset_ms);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{control, StatusFormat};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, path::Path};
+use tracing::{info, warn};
+
+// Number of samples each rolling histogram covers.
+const WINDOW: usize = 1024;
+// Upper bounds of the histogram buckets, in milliseconds.
+const BUCKETS_MS: [f64; 9] = [0.1, 0.5, 1.0, 5.0, 10.0, 50.0, 100.0, 500.0, 1000.0];
+const PERCENTILES: [f64; 3] = [50.0, 95.0, 99.0];
+
+// The magnitudes of the most recent offsets or corrections, in milliseconds, oldest first.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Rolling {
+    samples: VecDeque<f64>,
+}
+
+impl Rolling {
+    pub(crate) fn push(&mut self, ms: f64) {
+        if self.samples.len() == WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(ms.abs());
+    }
+
+    // Nearest-rank percentile, so the result is always a value that was actually seen.
+    fn percentile(&self, p: f64) -> Option<f64> {
+        let mut sorted: Vec<_> = self.samples.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted.get(rank.saturating_sub(1)).copied()
+    }
+
+    fn within(&self, bound_ms: f64) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let within = self.samples.iter().filter(|ms| **ms <= bound_ms).count();
+        Some(within as f64 / self.samples.len() as f64)
+    }
+
+    // Cumulative counts per bucket, like a Prometheus histogram.
+    fn buckets(&self) -> impl Iterator<Item = (f64, usize)> + '_ {
+        BUCKETS_MS.into_iter().map(|le| {
+            let count = self.samples.iter().filter(|ms| **ms <= le).count();
+            (le, count)
+        })
+    }
+
+    fn json(&self, within_ms: Option<f64>) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "samples": self.samples.len(),
+            "buckets": self
+                .buckets()
+                .map(|(le_ms, count)| serde_json::json!({ "le_ms": le_ms, "count": count }))
+                .collect::<Vec<_>>(),
+        });
+        for p in PERCENTILES {
+            json[format!("p{p}_ms")] = self.percentile(p).into();
+        }
+        if let Some(bound_ms) = within_ms {
+            json["within"] = self.within(bound_ms).into();
+        }
+        json
+    }
+
+    // Exposed as a summary, whose quantiles are conventionally taken over a sliding window.
+    fn metrics(&self, name: &str, help: &str) -> String {
+        if self.samples.is_empty() {
+            return String::new();
+        }
+        let mut out = format!("# HELP {name} {help}\n# TYPE {name} summary\n");
+        for p in PERCENTILES {
+            if let Some(ms) = self.percentile(p) {
+                out.push_str(&format!(
+                    "{name}{{quantile=\"{}\"}} {}\n",
+                    p / 100.0,
+                    ms / 1e3
+                ));
+            }
+        }
+        let sum: f64 = self.samples.iter().sum();
+        out.push_str(&format!("{name}_sum {}\n", sum / 1e3));
+        out.push_str(&format!("{name}_count {}\n", self.samples.len()));
+        out
+    }
+
+    fn report(&self, what: &str, within_ms: Option<f64>) {
+        let percentiles: Vec<_> = PERCENTILES
+            .into_iter()
+            .filter_map(|p| Some(format!("p{p} {:.3}ms", self.percentile(p)?)))
+            .collect();
+        if percentiles.is_empty() {
+            warn!("{what} => no samples yet");
+            return;
+        }
+        info!(
+            "{what} => {} ({} samples)",
+            percentiles.join(", "),
+            self.samples.len()
+        );
+        if let Some((bound_ms, within)) =
+            within_ms.and_then(|bound_ms| Some((bound_ms, self.within(bound_ms)?)))
+        {
+            info!("  within {bound_ms}ms => {:.2}%", within * 100.0);
+        }
+    }
+}
+
+fn histograms(status: &serde_json::Value) -> Result<(Rolling, Rolling)> {
+    let rolling = |field: &str| -> Result<Rolling> {
+        Ok(
+            serde_json::from_value::<Option<Rolling>>(status[field].clone())
+                .context("The daemon sent an invalid histogram")?
+                .unwrap_or_default(),
+        )
+    };
+    Ok((
+        rolling("offset_histogram")?,
+        rolling("correction_histogram")?,
+    ))
+}
+
+// The percentile metrics for a daemon status, for `tdctld status --format prometheus`.
+pub(crate) fn metrics(status: &serde_json::Value) -> Result<String> {
+    let (offsets, corrections) = histograms(status)?;
+    Ok(offsets.metrics(
+        "tdctld_offset_abs_seconds",
+        "Magnitude of the offsets measured in recent cycles",
+    ) + &corrections.metrics(
+        "tdctld_correction_abs_seconds",
+        "Magnitude of the corrections applied in recent cycles",
+    ))
+}
+
+pub(crate) fn stats(
+    format: StatusFormat,
+    control_socket: &Path,
+    within_ms: Option<f64>,
+) -> Result<()> {
+    let status = control::query_status(control_socket)?;
+    let (offsets, corrections) = histograms(&status)?;
+    match format {
+        StatusFormat::Text => {
+            offsets.report("measured offsets", within_ms);
+            corrections.report("applied corrections", within_ms);
+        }
+        StatusFormat::Json => {
+            let json = serde_json::json!({
+                "offsets": offsets.json(within_ms),
+                "corrections": corrections.json(within_ms),
+            });
+            println!("{json}");
+        }
+        StatusFormat::Prometheus => print!("{}", metrics(&status)?),
+    }
+    Ok(())
+}
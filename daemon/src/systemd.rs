@@ -0,0 +1,30 @@
+// Reports the daemon's state to systemd when it runs as a Type=notify unit, and does nothing
+// otherwise. The unit only becomes ready once the clock has been synchronized, which is what holds
+// back time-sync.target and everything ordered after it.
+#[cfg(target_os = "linux")]
+pub(crate) fn notify(state: &str) {
+    use std::os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    };
+    use tracing::debug;
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let path = path.to_string_lossy();
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name),
+        None => SocketAddr::from_pathname(path.as_ref()),
+    };
+    let sent = addr.and_then(|addr| {
+        let socket = UnixDatagram::unbound()?;
+        socket.send_to_addr(state.as_bytes(), &addr)
+    });
+    if let Err(e) = sent {
+        debug!("unable to notify systemd: {e}");
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn notify(_state: &str) {}
@@ -0,0 +1,79 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub(crate) const DEFAULT_SENSOR: &str = "/sys/class/thermal/thermal_zone0/temp";
+
+// Weight given to the newest sample, small enough that the fit spans days of temperature swings.
+const SMOOTHING: f64 = 0.05;
+// Fewer samples than this, or temperatures spread less than this (in squared degrees), say nothing
+// about how drift depends on temperature.
+const MIN_SAMPLES: u64 = 8;
+const MIN_VARIANCE: f64 = 0.25;
+
+// Reads a sysfs thermal zone or hwmon input, which report millidegrees Celsius.
+pub(crate) fn read(sensor: &Path) -> Result<f64> {
+    let contents = std::fs::read_to_string(sensor).context(format!(
+        "Unable to read temperature sensor {}",
+        sensor.display()
+    ))?;
+    let millidegrees: f64 = contents.trim().parse().context(format!(
+        "Unable to parse temperature sensor {}",
+        sensor.display()
+    ))?;
+    Ok(millidegrees / 1e3)
+}
+
+// Drift as a linear function of temperature, fitted by exponentially weighted least squares.
+// Crystals without temperature compensation drift by several ppm over the range a fanless box
+// goes through in a day, which the drift smoothed over the last few syncs lags behind.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct TemperatureModel {
+    samples: u64,
+    mean_temperature_c: f64,
+    mean_drift_ppm: f64,
+    temperature_variance: f64,
+    covariance: f64,
+    // How far drift strays from the fit, in squared ppm.
+    residual_variance_ppm2: f64,
+}
+
+impl TemperatureModel {
+    pub(crate) fn record(&mut self, temperature_c: f64, drift_ppm: f64) {
+        if self.samples == 0 {
+            self.mean_temperature_c = temperature_c;
+            self.mean_drift_ppm = drift_ppm;
+        } else {
+            if let Some(predicted) = self.predict(temperature_c) {
+                let residual = (drift_ppm - predicted).powi(2);
+                self.residual_variance_ppm2 += SMOOTHING * (residual - self.residual_variance_ppm2);
+            }
+            let dt = temperature_c - self.mean_temperature_c;
+            let dd = drift_ppm - self.mean_drift_ppm;
+            self.mean_temperature_c += SMOOTHING * dt;
+            self.mean_drift_ppm += SMOOTHING * dd;
+            self.temperature_variance =
+                (1.0 - SMOOTHING) * (self.temperature_variance + SMOOTHING * dt * dt);
+            self.covariance = (1.0 - SMOOTHING) * (self.covariance + SMOOTHING * dt * dd);
+        }
+        self.samples += 1;
+    }
+
+    // ppm of drift per degree, once enough samples over a wide enough range have been seen.
+    pub(crate) fn coefficient_ppm_per_c(&self) -> Option<f64> {
+        (self.samples >= MIN_SAMPLES && self.temperature_variance >= MIN_VARIANCE)
+            .then(|| self.covariance / self.temperature_variance)
+    }
+
+    pub(crate) fn predict(&self, temperature_c: f64) -> Option<f64> {
+        let coefficient = self.coefficient_ppm_per_c()?;
+        Some(self.mean_drift_ppm + coefficient * (temperature_c - self.mean_temperature_c))
+    }
+
+    // How far the drift at this temperature may be from the drift last measured: the change the
+    // fit predicts, plus how far drift strays from it.
+    pub(crate) fn drift_uncertainty_ppm(&self, drift_ppm: f64, temperature_c: f64) -> Option<f64> {
+        let predicted = self.predict(temperature_c)?;
+        Some((predicted - drift_ppm).abs() + self.residual_variance_ppm2.sqrt())
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::{bail, Result};
+use lunartick::Offset;
+
+// A span lunartick reads relative to now, like "in 30 days" or "2 hours ago", in whole seconds as
+// the namespace takes it.
+pub(crate) fn parse_shift(value: &str) -> Result<chrono::Duration, String> {
+    let shift = Offset::from_human(value)
+        .map_err(|_| format!("expected a time like 'in 30 days' or '2 hours ago', got '{value}'"))?
+        .as_duration();
+    Some(shift.num_seconds())
+        .filter(|seconds| seconds.unsigned_abs() <= u64::from(u32::MAX))
+        .map(chrono::Duration::seconds)
+        .ok_or_else(|| format!("shift '{value}' is too large"))
+}
+
+// Not in the version of the libc crate this builds with.
+#[cfg(target_os = "linux")]
+const CLONE_NEWTIME: libc::c_int = 0x80;
+
+// Runs the command in a new time namespace whose CLOCK_MONOTONIC and CLOCK_BOOTTIME read `shift`
+// ahead of this one's, and returns its exit code. Time namespaces leave CLOCK_REALTIME alone, so
+// only software timing itself with those clocks, or reading the uptime, sees the shift, not
+// software reading the date. Creating one takes CAP_SYS_ADMIN.
+#[cfg(target_os = "linux")]
+pub(crate) fn run(shift: chrono::Duration, command: &[String]) -> Result<i32> {
+    use anyhow::Context;
+    use std::process::Command;
+
+    let Some((program, args)) = command.split_first() else {
+        bail!("No command to run");
+    };
+    if unsafe { libc::unshare(CLONE_NEWTIME) } == -1 {
+        return Err(std::io::Error::last_os_error())
+            .context("Unable to create a time namespace (it takes Linux 5.6 and CAP_SYS_ADMIN)");
+    }
+    // The namespace is created for the children of this process, its offsets can only be written
+    // until the first of them starts. Nanoseconds must not be negative.
+    let seconds = shift.num_seconds();
+    let offsets = format!("monotonic {seconds} 0\nboottime {seconds} 0\n");
+    std::fs::write("/proc/self/timens_offsets", offsets).context(
+        "Unable to shift the namespace's clocks, a monotonic clock cannot be shifted before boot",
+    )?;
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .context(format!("Unable to run {program}"))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn run(_shift: chrono::Duration, _command: &[String]) -> Result<i32> {
+    bail!("time namespaces are only available on Linux")
+}
+
+// Whether this process runs in a time namespace with shifted clocks. The daemon measures intervals
+// with the monotonic clock, which works as well shifted, but the clock it sets is still the host's.
+#[cfg(target_os = "linux")]
+pub(crate) fn is_shifted() -> bool {
+    std::fs::read_to_string("/proc/self/timens_offsets").is_ok_and(|offsets| {
+        offsets
+            .lines()
+            .flat_map(|line| line.split_whitespace().skip(1))
+            .any(|value| value != "0")
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn is_shifted() -> bool {
+    false
+}
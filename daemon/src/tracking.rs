@@ -0,0 +1,167 @@
+use crate::state::DaemonState;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use lunartick::{Offset, TestResults};
+use serde::{Deserialize, Serialize};
+use std::{net::IpAddr, path::Path, time::Instant};
+
+// Weight given to the newest offset in the RMS offset.
+const SMOOTHING: f64 = 0.1;
+
+// What `chronyc tracking` reports, as far as tdctld knows it, in chrony's units and signs.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Tracking {
+    reference_id: String,
+    reference: String,
+    stratum: u8,
+    ref_time: DateTime<Utc>,
+    // Positive when the system clock is slow of NTP time, by what is still to be corrected.
+    system_time_s: f64,
+    // Positive when the clock was ahead on the last update.
+    last_offset_s: f64,
+    rms_offset_s: f64,
+    // Positive when the clock runs slow.
+    frequency_ppm: f64,
+    residual_frequency_ppm: f64,
+    skew_ppm: f64,
+    root_delay_s: f64,
+    root_dispersion_s: f64,
+    update_interval_s: f64,
+    leap_status: String,
+}
+
+#[derive(Default)]
+pub(crate) struct Tracker {
+    mean_square_s2: Option<f64>,
+    last_update: Option<Instant>,
+}
+
+fn seconds(offset: Offset) -> f64 {
+    offset.as_millis_f64() / 1e3
+}
+
+impl Tracker {
+    // Tracking after a cycle that measured `results` and corrected the clock by `applied`, None
+    // when no server was usable.
+    pub(crate) fn update(
+        &mut self,
+        results: &TestResults,
+        applied: Option<Offset>,
+        state: &DaemonState,
+    ) -> Option<Tracking> {
+        let now = Instant::now();
+        let update_interval_s = self
+            .last_update
+            .replace(now)
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f64());
+        let offset = seconds(results.get_combined_offset().ok()?);
+        let best = results.best_server()?;
+        let index = results
+            .get_all_results()
+            .position(|(server, _)| server == best)?;
+        let square = offset * offset;
+        let mean_square = match self.mean_square_s2 {
+            Some(mean) => mean + SMOOTHING * (square - mean),
+            None => square,
+        };
+        self.mean_square_s2 = Some(mean_square);
+        // An IPv6 peer has a hash for its reference ID, which tdctld does not compute.
+        let reference_id = match results.get_peer_address(index) {
+            Some(IpAddr::V4(v4)) => format!("{:08X}", u32::from(v4)),
+            _ => "00000000".to_owned(),
+        };
+        let reference = results
+            .get_peer_address(index)
+            .map_or_else(|| best.to_owned(), |peer| peer.to_string());
+        let secs = |d: Option<chrono::Duration>| d.map_or(0.0, |d| seconds(Offset::from(d)));
+        Some(Tracking {
+            reference_id,
+            reference,
+            stratum: results
+                .get_reference(index)
+                .map_or(0, |reference| reference.stratum().saturating_add(1)),
+            ref_time: state.last_success.unwrap_or_else(Utc::now),
+            system_time_s: offset - applied.map_or(0.0, seconds),
+            last_offset_s: 0.0 - offset,
+            rms_offset_s: mean_square.sqrt(),
+            // Subtracting keeps an unknown drift from printing as -0.
+            frequency_ppm: 0.0 - state.drift_ppm.unwrap_or(0.0),
+            residual_frequency_ppm: 0.0,
+            skew_ppm: state.drift_variance_ppm2.map_or(0.0, f64::sqrt),
+            root_delay_s: secs(results.get_delay(index)),
+            root_dispersion_s: secs(results.get_root_distance(index)),
+            update_interval_s,
+            leap_status: "Normal".to_owned(),
+        })
+    }
+}
+
+// Prints the running daemon's tracking as `chronyc tracking` does, or as `chronyc -c tracking`
+// with `csv`.
+pub(crate) fn tracking(control_socket: &Path, csv: bool) -> Result<()> {
+    let status = crate::control::query_status(control_socket)?;
+    if status["tracking"].is_null() {
+        bail!("The daemon has not synchronized yet");
+    }
+    let tracking: Tracking = serde_json::from_value(status["tracking"].clone())?;
+    let ref_time = tracking.ref_time;
+    if csv {
+        println!(
+            "{},{},{},{}.{:09},{:.9},{:.9},{:.9},{:.3},{:.3},{:.3},{:.9},{:.9},{:.1},{}",
+            tracking.reference_id,
+            tracking.reference,
+            tracking.stratum,
+            ref_time.timestamp(),
+            ref_time.timestamp_subsec_nanos(),
+            tracking.system_time_s,
+            tracking.last_offset_s,
+            tracking.rms_offset_s,
+            tracking.frequency_ppm,
+            tracking.residual_frequency_ppm,
+            tracking.skew_ppm,
+            tracking.root_delay_s,
+            tracking.root_dispersion_s,
+            tracking.update_interval_s,
+            tracking.leap_status
+        );
+        return Ok(());
+    }
+    let slow_or_fast = |value: f64| if value >= 0.0 { "slow" } else { "fast" };
+    println!(
+        "Reference ID    : {} ({})",
+        tracking.reference_id, tracking.reference
+    );
+    println!("Stratum         : {}", tracking.stratum);
+    println!(
+        "Ref time (UTC)  : {}",
+        ref_time.format("%a %b %e %H:%M:%S %Y")
+    );
+    println!(
+        "System time     : {:.9} seconds {} of NTP time",
+        tracking.system_time_s.abs(),
+        slow_or_fast(tracking.system_time_s)
+    );
+    println!("Last offset     : {:+.9} seconds", tracking.last_offset_s);
+    println!("RMS offset      : {:.9} seconds", tracking.rms_offset_s);
+    println!(
+        "Frequency       : {:.3} ppm {}",
+        tracking.frequency_ppm.abs(),
+        slow_or_fast(tracking.frequency_ppm)
+    );
+    println!(
+        "Residual freq   : {:+.3} ppm",
+        tracking.residual_frequency_ppm
+    );
+    println!("Skew            : {:.3} ppm", tracking.skew_ppm);
+    println!("Root delay      : {:.9} seconds", tracking.root_delay_s);
+    println!(
+        "Root dispersion : {:.9} seconds",
+        tracking.root_dispersion_s
+    );
+    println!(
+        "Update interval : {:.1} seconds",
+        tracking.update_interval_s
+    );
+    println!("Leap status     : {}", tracking.leap_status);
+    Ok(())
+}
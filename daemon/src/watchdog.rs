@@ -0,0 +1,114 @@
+use chrono::{DateTime, Utc};
+use std::{fmt, time::Duration, time::Instant};
+
+// Checks that the system clock keeps pace with the monotonic clock between polls. The daemon's own
+// corrections step it too, so the baseline is reset after every sync.
+pub(crate) struct ClockWatchdog {
+    threshold: Duration,
+    wall: DateTime<Utc>,
+    monotonic: Instant,
+    boottime: Option<Duration>,
+    // How long the system clock has stood still so far.
+    frozen: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Anomaly {
+    // The system clock moved by this much more than the monotonic clock did.
+    Jumped(chrono::Duration),
+    // The system clock stood still, or barely advanced, for this long.
+    Frozen(Duration),
+    // The machine was suspended or the VM paused for this long.
+    Suspended(Duration),
+}
+
+impl Anomaly {
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Anomaly::Jumped(_) => "jumped",
+            Anomaly::Frozen(_) => "frozen",
+            Anomaly::Suspended(_) => "suspended",
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::Jumped(by) => write!(
+                f,
+                "the system clock jumped by {:+.3}s",
+                by.num_milliseconds() as f64 / 1e3
+            ),
+            Anomaly::Frozen(for_) => write!(
+                f,
+                "the system clock stood still for {:.3}s",
+                for_.as_secs_f64()
+            ),
+            Anomaly::Suspended(for_) => write!(
+                f,
+                "the system was suspended or paused for {:.3}s",
+                for_.as_secs_f64()
+            ),
+        }
+    }
+}
+
+// Unlike the monotonic clock, CLOCK_BOOTTIME keeps counting while the system is suspended.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn boottime() -> Option<Duration> {
+    let mut ts: libc::timespec = unsafe { std::mem::zeroed() };
+    if unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut ts) } != 0 {
+        return None;
+    }
+    Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn boottime() -> Option<Duration> {
+    None
+}
+
+impl ClockWatchdog {
+    pub(crate) fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            wall: Utc::now(),
+            monotonic: Instant::now(),
+            boottime: boottime(),
+            frozen: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new(self.threshold);
+    }
+
+    // Compares the clocks against the previous check. Slewing changes the rate of the system clock
+    // by at most a fraction of a percent, far too little to matter between two checks.
+    pub(crate) fn check(&mut self) -> Option<Anomaly> {
+        let threshold = self.threshold;
+        let previous = std::mem::replace(self, Self::new(threshold));
+        let monotonic = self.monotonic - previous.monotonic;
+        let wall = self.wall - previous.wall;
+        if let (Some(now), Some(before)) = (self.boottime, previous.boottime) {
+            let suspended = (now - before).saturating_sub(monotonic);
+            if suspended > self.threshold {
+                return Some(Anomaly::Suspended(suspended));
+            }
+        }
+        let wall_us = wall.num_microseconds()?;
+        let monotonic_us = i64::try_from(monotonic.as_micros()).ok()?;
+        if wall_us.abs() < monotonic_us / 10 {
+            self.frozen = previous.frozen + monotonic;
+            // Reported once, not on every check for as long as it stays stuck.
+            if previous.frozen <= self.threshold && self.frozen > self.threshold {
+                return Some(Anomaly::Frozen(self.frozen));
+            }
+            return None;
+        }
+        let jump_us = wall_us - monotonic_us;
+        (u128::from(jump_us.unsigned_abs()) > self.threshold.as_micros())
+            .then(|| Anomaly::Jumped(chrono::Duration::microseconds(jump_us)))
+    }
+}
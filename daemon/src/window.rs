@@ -0,0 +1,50 @@
+use chrono::NaiveTime;
+use std::{fmt, str::FromStr};
+
+// A daily stretch of local time such as `02:00-04:00`, wrapping past midnight when it ends
+// earlier than it starts.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CorrectionWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl CorrectionWindow {
+    pub(crate) fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl fmt::Display for CorrectionWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+impl FromStr for CorrectionWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("invalid window '{s}', expected HH:MM-HH:MM"))?;
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|_| format!("invalid time '{time}', expected HH:MM"))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start == end {
+            return Err(format!("window '{s}' is empty"));
+        }
+        Ok(Self { start, end })
+    }
+}
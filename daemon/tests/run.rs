@@ -0,0 +1,167 @@
+use clap::Parser;
+use lunartick::{FailureKind, NTPClient, Offset, Transport};
+use tdctld::{run, Args, Outcome, SyncReport};
+
+// Only commands that leave the system clock alone and need no network or running daemon.
+fn run_args(args: &[&str]) -> anyhow::Result<Outcome> {
+    let mut argv = vec!["tdctld"];
+    argv.extend_from_slice(args);
+    run(Args::try_parse_from(argv)?)
+}
+
+fn time(args: &[&str]) -> lunartick::Clock {
+    match run_args(args).unwrap() {
+        Outcome::Time(clock) => clock,
+        outcome => panic!("expected a time, got {outcome:?}"),
+    }
+}
+
+#[test]
+fn convert_rfc3339_to_unix() {
+    let clock = time(&[
+        "convert",
+        "--from",
+        "rfc3339",
+        "--to",
+        "unix",
+        "2024-02-29T12:34:56.5+01:00",
+    ]);
+    assert_eq!(clock.get_timestamp(), 1_709_206_496);
+    assert_eq!(clock.get_subsec_nanos(), 500_000_000);
+}
+
+#[test]
+fn convert_unix_to_rfc3339() {
+    let clock = time(&[
+        "convert",
+        "--from",
+        "unix",
+        "--to",
+        "rfc3339",
+        "1700000000.25",
+    ]);
+    assert_eq!(clock.get_timestamp(), 1_700_000_000);
+    assert_eq!(clock.get_subsec_nanos(), 250_000_000);
+}
+
+#[test]
+fn convert_human() {
+    let clock = time(&[
+        "convert",
+        "--from",
+        "human",
+        "--to",
+        "rfc3339",
+        "--",
+        "2024-01-01 12:00 UTC",
+    ]);
+    assert_eq!(clock.get_rfc3339(), "2024-01-01T12:00:00+00:00");
+}
+
+#[test]
+fn convert_rejects_invalid_input() {
+    let invalid = [
+        &[
+            "convert",
+            "--from",
+            "rfc3339",
+            "--to",
+            "unix",
+            "yesterday-ish",
+        ][..],
+        &["convert", "--from", "unix", "--to", "rfc3339", "12.3.4"],
+        &[
+            "convert",
+            "--from",
+            "rfc3339",
+            "--to",
+            "%Y %Q",
+            "2024-01-01T00:00:00Z",
+        ],
+        &[
+            "convert",
+            "--from",
+            "human",
+            "--to",
+            "rfc3339",
+            "--",
+            "in 2000000000 months in 2000000000 months",
+        ],
+    ];
+    for args in invalid {
+        assert!(run_args(args).is_err(), "{args:?} should fail");
+    }
+}
+
+#[test]
+fn get_reads_the_clock() {
+    let before = lunartick::Clock::now().get_timestamp();
+    let clock = time(&["get", "timestamp"]);
+    let after = lunartick::Clock::now().get_timestamp();
+    assert!((before..=after).contains(&clock.get_timestamp()));
+}
+
+#[test]
+fn outcomes_carry_exit_codes() {
+    assert_eq!(Outcome::Exit(3).exit_code(), 3);
+    assert_eq!(run_args(&["get", "json"]).unwrap().exit_code(), 0);
+}
+
+// Fails every query, for sync reports that need measurements without touching the network.
+struct Unreachable;
+
+impl Transport for Unreachable {
+    fn connect(&mut self, _server: &str, _port: u16) -> Result<(), FailureKind> {
+        Ok(())
+    }
+
+    fn send(&mut self, _request: &[u8]) -> Result<(), FailureKind> {
+        Err(FailureKind::Network(std::io::ErrorKind::NetworkUnreachable))
+    }
+
+    fn recv(&mut self, _response: &mut [u8]) -> Result<usize, FailureKind> {
+        Err(FailureKind::Timeout)
+    }
+}
+
+fn report(applied: Option<Offset>) -> SyncReport {
+    SyncReport {
+        results: NTPClient::new_with_server("192.0.2.1").test_with(&mut Unreachable),
+        applied,
+        stepped: true,
+    }
+}
+
+#[test]
+fn boot_syncs_fail_unless_the_clock_was_set() {
+    let applied = Some(Offset::from_millis_f64(250.0));
+    assert_eq!(Outcome::BootSynced(report(applied)).exit_code(), 0);
+    assert_eq!(Outcome::BootSynced(report(None)).exit_code(), 1);
+    assert_eq!(Outcome::Synced(report(None)).exit_code(), 0);
+}
+
+fn ns_run_shift(shift: &str) -> Result<chrono::Duration, clap::Error> {
+    let args = Args::try_parse_from(["tdctld", "ns-run", "--shift", shift, "--", "true"])?;
+    match args.command {
+        tdctld::Commands::NsRun { shift, .. } => Ok(shift),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn ns_run_shifts_by_human_spans() {
+    assert_eq!(
+        ns_run_shift("in 30 days").unwrap(),
+        chrono::Duration::days(30)
+    );
+    assert_eq!(
+        ns_run_shift("2 hours ago").unwrap(),
+        chrono::Duration::hours(-2)
+    );
+    assert_eq!(
+        ns_run_shift("+90 seconds").unwrap(),
+        chrono::Duration::seconds(90)
+    );
+    assert!(ns_run_shift("30d").is_err());
+    assert!(ns_run_shift("in 200 years").is_err());
+}
@@ -0,0 +1,73 @@
+#ifndef LUNARTICK_H
+#define LUNARTICK_H
+
+/* Generated with cbindgen from lunartick-ffi/src/lib.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum LunartickStatus {
+  LUNARTICK_STATUS_OK = 0,
+  LUNARTICK_STATUS_INVALID_ARGUMENT = 1,
+  LUNARTICK_STATUS_PARSE_ERROR = 2,
+  LUNARTICK_STATUS_CLOCK_SET_DENIED = 3,
+  LUNARTICK_STATUS_CLOCK_SET_FAILED = 4,
+  LUNARTICK_STATUS_IO_ERROR = 5,
+  LUNARTICK_STATUS_DNS_FAILURE = 6,
+  LUNARTICK_STATUS_TIMEOUT = 7,
+  LUNARTICK_STATUS_KISS_OF_DEATH = 8,
+  LUNARTICK_STATUS_MALFORMED_RESPONSE = 9,
+  LUNARTICK_STATUS_NETWORK_ERROR = 10,
+  LUNARTICK_STATUS_NO_USABLE_SERVERS = 11,
+  LUNARTICK_STATUS_UNSUPPORTED = 12,
+  LUNARTICK_STATUS_UNACCEPTABLE_STRATUM = 13,
+  LUNARTICK_STATUS_TIMING_LOOP = 14,
+  LUNARTICK_STATUS_CLOCK_ANOMALY = 15,
+  LUNARTICK_STATUS_DNS_TIMEOUT = 16,
+  LUNARTICK_STATUS_ADDRESS_MISMATCH = 17,
+  LUNARTICK_STATUS_INSUFFICIENT_DIVERSITY = 18,
+  LUNARTICK_STATUS_SUSPECTED_ATTACK = 19,
+  LUNARTICK_STATUS_SET_VERIFICATION_FAILED = 20,
+  LUNARTICK_STATUS_PANIC = 21,
+} LunartickStatus;
+
+/**
+ * Returns the message of the last error raised on the calling thread, or NULL if there is none.
+ *
+ * The returned string is owned by the library and stays valid until the next call into
+ * lunartick from the same thread.
+ */
+const char *lunartick_last_error(void);
+
+/**
+ * Measures the offset of the local clock against a set of NTP servers, in milliseconds.
+ *
+ * Passing NULL as `servers` queries the default server list.
+ *
+ * # Safety
+ *
+ * `servers` must either be NULL or point to `len` valid NUL-terminated strings, and
+ * `offset_ms` must point to writable memory for a `double`.
+ */
+enum LunartickStatus lunartick_query_offset(const char *const *servers,
+                                            size_t len,
+                                            double *offset_ms);
+
+/**
+ * Reads the system clock as seconds and nanoseconds since the Unix epoch.
+ *
+ * # Safety
+ *
+ * `secs` and `nanos` must point to writable memory for their respective types.
+ */
+enum LunartickStatus lunartick_get_time(int64_t *secs, uint32_t *nanos);
+
+/**
+ * Sets the system clock to the given number of seconds and nanoseconds since the Unix epoch.
+ */
+enum LunartickStatus lunartick_set_time(int64_t secs, uint32_t nanos);
+
+#endif  /* LUNARTICK_H */
@@ -0,0 +1,199 @@
+use lunartick::{Clock, LunartickError, NTPClient};
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic::{self, UnwindSafe},
+    ptr, slice,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LunartickStatus {
+    Ok = 0,
+    InvalidArgument = 1,
+    ParseError = 2,
+    ClockSetDenied = 3,
+    ClockSetFailed = 4,
+    IoError = 5,
+    DnsFailure = 6,
+    Timeout = 7,
+    KissOfDeath = 8,
+    MalformedResponse = 9,
+    NetworkError = 10,
+    NoUsableServers = 11,
+    Unsupported = 12,
+    UnacceptableStratum = 13,
+    TimingLoop = 14,
+    ClockAnomaly = 15,
+    DnsTimeout = 16,
+    AddressMismatch = 17,
+    InsufficientDiversity = 18,
+    SuspectedAttack = 19,
+    SetVerificationFailed = 20,
+    Panic = 21,
+}
+
+impl From<&LunartickError> for LunartickStatus {
+    fn from(e: &LunartickError) -> Self {
+        match e {
+            LunartickError::ParseDateTimeError(_) | LunartickError::LeapSecondTable(_) => {
+                LunartickStatus::ParseError
+            }
+            LunartickError::SetError(_) => LunartickStatus::ClockSetFailed,
+            LunartickError::InvalidFormat(_) => LunartickStatus::InvalidArgument,
+            LunartickError::ClockSetDenied(_) => LunartickStatus::ClockSetDenied,
+            LunartickError::IO(_) => LunartickStatus::IoError,
+            LunartickError::DnsFailure { .. } => LunartickStatus::DnsFailure,
+            LunartickError::DnsTimeout { .. } => LunartickStatus::DnsTimeout,
+            LunartickError::AddressMismatch { .. } => LunartickStatus::AddressMismatch,
+            LunartickError::InsufficientDiversity { .. } => LunartickStatus::InsufficientDiversity,
+            LunartickError::SuspectedAttack { .. } => LunartickStatus::SuspectedAttack,
+            LunartickError::SetVerificationFailed { .. } => LunartickStatus::SetVerificationFailed,
+            LunartickError::Timeout { .. } => LunartickStatus::Timeout,
+            LunartickError::KissOfDeath { .. } => LunartickStatus::KissOfDeath,
+            LunartickError::MalformedResponse { .. } => LunartickStatus::MalformedResponse,
+            LunartickError::NoUsableResponse => LunartickStatus::NoUsableServers,
+            LunartickError::Network { .. } | LunartickError::AddressFamily { .. } => {
+                LunartickStatus::NetworkError
+            }
+            LunartickError::Unsupported(_) => LunartickStatus::Unsupported,
+            LunartickError::UnacceptableStratum { .. } => LunartickStatus::UnacceptableStratum,
+            LunartickError::TimingLoop { .. } => LunartickStatus::TimingLoop,
+            LunartickError::ClockAnomaly { .. } => LunartickStatus::ClockAnomaly,
+        }
+    }
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+fn fail(status: LunartickStatus, message: impl Into<String>) -> LunartickStatus {
+    set_last_error(message.into());
+    status
+}
+
+fn fail_with(e: &LunartickError) -> LunartickStatus {
+    fail(e.into(), e.to_string())
+}
+
+// Unwinding out of an extern "C" function aborts the caller, so a panic is caught and reported as
+// a status instead.
+fn guard(f: impl FnOnce() -> LunartickStatus + UnwindSafe) -> LunartickStatus {
+    panic::catch_unwind(f).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| (*message).to_owned())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_owned());
+        fail(
+            LunartickStatus::Panic,
+            format!("lunartick panicked: {message}"),
+        )
+    })
+}
+
+unsafe fn read_servers(
+    servers: *const *const c_char,
+    len: usize,
+) -> Result<NTPClient, LunartickStatus> {
+    if servers.is_null() {
+        return Ok(NTPClient::new());
+    }
+    let mut names = Vec::with_capacity(len);
+    for &server in slice::from_raw_parts(servers, len) {
+        if server.is_null() {
+            return Err(fail(LunartickStatus::InvalidArgument, "server is NULL"));
+        }
+        let name = CStr::from_ptr(server).to_str().map_err(|_| {
+            fail(
+                LunartickStatus::InvalidArgument,
+                "server is not valid UTF-8",
+            )
+        })?;
+        names.push(name);
+    }
+    Ok(NTPClient::new_with_multiple_servers(names))
+}
+
+/// Returns the message of the last error raised on the calling thread, or NULL if there is none.
+///
+/// The returned string is owned by the library and stays valid until the next call into
+/// lunartick from the same thread.
+#[no_mangle]
+pub extern "C" fn lunartick_last_error() -> *const c_char {
+    panic::catch_unwind(|| {
+        LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+    })
+    .unwrap_or(ptr::null())
+}
+
+/// Measures the offset of the local clock against a set of NTP servers, in milliseconds.
+///
+/// Passing NULL as `servers` queries the default server list.
+///
+/// # Safety
+///
+/// `servers` must either be NULL or point to `len` valid NUL-terminated strings, and
+/// `offset_ms` must point to writable memory for a `double`.
+#[no_mangle]
+pub unsafe extern "C" fn lunartick_query_offset(
+    servers: *const *const c_char,
+    len: usize,
+    offset_ms: *mut f64,
+) -> LunartickStatus {
+    guard(|| {
+        if offset_ms.is_null() {
+            return fail(LunartickStatus::InvalidArgument, "offset_ms is NULL");
+        }
+        let ntp_client = match read_servers(servers, len) {
+            Ok(ntp_client) => ntp_client,
+            Err(status) => return status,
+        };
+        let results = match ntp_client.test() {
+            Ok(results) => results,
+            Err(e) => return fail_with(&e),
+        };
+        let offset = match results.get_combined_offset() {
+            Ok(offset) => offset,
+            Err(e) => return fail_with(&e),
+        };
+        *offset_ms = offset.as_millis_f64();
+        LunartickStatus::Ok
+    })
+}
+
+/// Reads the system clock as seconds and nanoseconds since the Unix epoch.
+///
+/// # Safety
+///
+/// `secs` and `nanos` must point to writable memory for their respective types.
+#[no_mangle]
+pub unsafe extern "C" fn lunartick_get_time(secs: *mut i64, nanos: *mut u32) -> LunartickStatus {
+    guard(|| {
+        if secs.is_null() || nanos.is_null() {
+            return fail(LunartickStatus::InvalidArgument, "output pointer is NULL");
+        }
+        let now = Clock::now();
+        *secs = now.get_timestamp();
+        *nanos = now.get_subsec_nanos();
+        LunartickStatus::Ok
+    })
+}
+
+/// Sets the system clock to the given number of seconds and nanoseconds since the Unix epoch.
+#[no_mangle]
+pub extern "C" fn lunartick_set_time(secs: i64, nanos: u32) -> LunartickStatus {
+    guard(
+        || match Clock::from_timestamp(secs, nanos).and_then(|dt| dt.set()) {
+            Ok(()) => LunartickStatus::Ok,
+            Err(e) => fail_with(&e),
+        },
+    )
+}
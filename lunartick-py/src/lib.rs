@@ -0,0 +1,149 @@
+use ::lunartick::{Clock, LunartickError, NTPClient, Offset, TestResults};
+use pyo3::{create_exception, exceptions::PyException, prelude::*};
+use std::collections::HashMap;
+
+create_exception!(lunartick, Error, PyException);
+
+fn to_py_err(e: LunartickError) -> PyErr {
+    Error::new_err(e.to_string())
+}
+
+#[pyclass(name = "Clock", frozen)]
+#[derive(Clone, Copy)]
+struct PyClock(Clock);
+
+#[pymethods]
+impl PyClock {
+    #[staticmethod]
+    fn now() -> Self {
+        Self(Clock::now())
+    }
+
+    #[staticmethod]
+    fn now_synced(py: Python<'_>) -> PyResult<Self> {
+        py.allow_threads(Clock::now_synced)
+            .map(Self)
+            .map_err(to_py_err)
+    }
+
+    #[staticmethod]
+    fn now_synced_cached(py: Python<'_>, max_age_secs: f64) -> PyResult<Self> {
+        let max_age = std::time::Duration::try_from_secs_f64(max_age_secs)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        py.allow_threads(|| Clock::now_synced_cached(max_age))
+            .map(Self)
+            .map_err(to_py_err)
+    }
+
+    #[staticmethod]
+    fn from_rfc2822(dt: String) -> PyResult<Self> {
+        Clock::from_rfc2822(dt).map(Self).map_err(to_py_err)
+    }
+
+    #[staticmethod]
+    fn from_rfc3339(dt: String) -> PyResult<Self> {
+        Clock::from_rfc3339(dt).map(Self).map_err(to_py_err)
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (secs, nanos = 0))]
+    fn from_timestamp(secs: i64, nanos: u32) -> PyResult<Self> {
+        Clock::from_timestamp(secs, nanos)
+            .map(Self)
+            .map_err(to_py_err)
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.0.get_timestamp()
+    }
+
+    fn subsec_nanos(&self) -> u32 {
+        self.0.get_subsec_nanos()
+    }
+
+    fn rfc2822(&self) -> String {
+        self.0.get_rfc2822()
+    }
+
+    fn rfc3339(&self) -> String {
+        self.0.get_rfc3339()
+    }
+
+    fn set(&self) -> PyResult<()> {
+        self.0.set().map_err(to_py_err)
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Clock({})", self.0)
+    }
+}
+
+#[pyclass(name = "NTPClient", frozen)]
+struct PyNTPClient(NTPClient);
+
+#[pymethods]
+impl PyNTPClient {
+    #[new]
+    #[pyo3(signature = (servers = None))]
+    fn new(servers: Option<Vec<String>>) -> Self {
+        match servers {
+            Some(servers) => Self(NTPClient::new_with_multiple_servers(servers)),
+            None => Self(NTPClient::new()),
+        }
+    }
+
+    #[getter]
+    fn servers(&self) -> Vec<String> {
+        self.0.get_servers().iter().map(|s| s.to_string()).collect()
+    }
+
+    fn test(&self, py: Python<'_>) -> PyResult<PyTestResults> {
+        py.allow_threads(|| self.0.test())
+            .map(PyTestResults)
+            .map_err(to_py_err)
+    }
+}
+
+#[pyclass(name = "TestResults", frozen)]
+struct PyTestResults(TestResults);
+
+#[pymethods]
+impl PyTestResults {
+    fn time_millis(&self) -> f64 {
+        self.0.get_time_millis()
+    }
+
+    fn uncertainty_millis(&self) -> Option<f64> {
+        self.0
+            .get_uncertainty()
+            .map(|uncertainty| Offset::from(uncertainty).as_millis_f64())
+    }
+
+    fn offsets(&self) -> HashMap<String, Option<f64>> {
+        self.0
+            .get_all_results()
+            .map(|(server, offset)| (server.to_owned(), offset.ok().map(|o| o.as_millis_f64())))
+            .collect()
+    }
+
+    fn errors(&self) -> HashMap<String, String> {
+        self.0
+            .get_all_results()
+            .filter_map(|(server, offset)| Some((server.to_owned(), offset.err()?.to_string())))
+            .collect()
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "lunartick")]
+fn lunartick_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("Error", m.py().get_type::<Error>())?;
+    m.add_class::<PyClock>()?;
+    m.add_class::<PyNTPClient>()?;
+    m.add_class::<PyTestResults>()?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use chrono::Utc;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lunartick::{parse_response, FailureKind, NTPClient, NTPTimestamp, Transport};
+
+// Answers every request at once like a stratum 1 server whose clock is 250ms ahead, so a whole
+// test runs without the network.
+struct LoopbackServer {
+    request: [u8; 48],
+}
+
+impl LoopbackServer {
+    fn new() -> Self {
+        Self { request: [0; 48] }
+    }
+}
+
+fn put(packet: &mut [u8], offset: usize, timestamp: NTPTimestamp) {
+    packet[offset..offset + 8].copy_from_slice(&timestamp.as_u64().to_be_bytes());
+}
+
+fn response(origin: &[u8]) -> [u8; 48] {
+    let now = NTPTimestamp::local(Utc::now() + chrono::Duration::milliseconds(250));
+    let mut packet = [0; 48];
+    packet[..4].copy_from_slice(&[0x24, 1, 6, 0xec]);
+    packet[12..16].copy_from_slice(b"GPS\0");
+    packet[24..32].copy_from_slice(origin);
+    put(&mut packet, 32, now);
+    put(&mut packet, 40, now);
+    packet
+}
+
+impl Transport for LoopbackServer {
+    fn connect(&mut self, _server: &str, _port: u16) -> Result<(), FailureKind> {
+        Ok(())
+    }
+
+    fn send(&mut self, request: &[u8]) -> Result<(), FailureKind> {
+        self.request.copy_from_slice(&request[..48]);
+        Ok(())
+    }
+
+    fn recv(&mut self, response_buf: &mut [u8]) -> Result<usize, FailureKind> {
+        response_buf[..48].copy_from_slice(&response(&self.request[40..48]));
+        Ok(48)
+    }
+}
+
+fn packets(c: &mut Criterion) {
+    let packet = response(&[0; 8]);
+    c.bench_function("parse_response", |b| {
+        b.iter(|| parse_response(black_box(&packet)).map(|parsed| parsed.transmit_time()))
+    });
+    let now = Utc::now();
+    c.bench_function("timestamp_roundtrip", |b| {
+        b.iter(|| NTPTimestamp::local(black_box(now)).to_datetime(now))
+    });
+}
+
+// A 1 Hz probe of 50 servers does this once a second.
+fn test_50_servers(c: &mut Criterion) {
+    let client = NTPClient::new_with_multiple_servers((0..50).map(|i| format!("10.0.0.{i}")));
+    let mut server = LoopbackServer::new();
+    c.bench_function("test_with_50_servers", |b| {
+        b.iter(|| client.test_with(&mut server))
+    });
+    let results = client.test_with(&mut server);
+    assert!(results.get_combined_offset().is_ok());
+    c.bench_function("combine_50_offsets", |b| {
+        b.iter(|| {
+            let results = black_box(&results);
+            (results.get_combined_offset(), results.get_uncertainty())
+        })
+    });
+}
+
+criterion_group!(benches, packets, test_50_servers);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = lunartick::parse_response(data) {
+        let _ = (packet.receive_time(), packet.transmit_time(), packet.origin_time());
+        let _ = packet.extension_fields().len();
+    }
+});
@@ -0,0 +1,29 @@
+#![no_main]
+
+use chrono::{TimeZone, Utc};
+use libfuzzer_sys::fuzz_target;
+use lunartick::NTPTimestamp;
+
+// Half an NTP era, how far from the pivot a time can be and still be read back in its own era.
+const HALF_ERA: i64 = 1 << 31;
+
+fuzz_target!(|input: (u64, i32, i64, u32)| {
+    let (bits, pivot, ahead, nanos) = input;
+    // Any pivot from 1901 to 2038.
+    let pivot = Utc.timestamp_opt(i64::from(pivot), 0).unwrap();
+
+    let ntp = NTPTimestamp::from_u64(bits);
+    let utc = ntp.to_datetime(pivot).expect("every era near the pivot is in range");
+    assert!((utc.timestamp() - pivot.timestamp()).abs() <= HALF_ERA);
+    let back = NTPTimestamp::from_datetime(utc, pivot).unwrap();
+    assert_eq!(back.seconds(), ntp.seconds());
+    // Rounding down to the nanosecond loses up to 2^32 / 10^9 steps of the fraction.
+    assert!(back.fraction() <= ntp.fraction());
+    assert!(ntp.fraction() - back.fraction() <= 4);
+
+    let secs = pivot.timestamp() - HALF_ERA + ahead.rem_euclid(2 * HALF_ERA);
+    if let Some(utc) = Utc.timestamp_opt(secs, nanos % 1_000_000_000).single() {
+        let ntp = NTPTimestamp::from_datetime(utc, pivot).expect("within half an era");
+        assert_eq!(ntp.to_datetime(pivot).unwrap(), utc);
+    }
+});
@@ -0,0 +1,85 @@
+use crate::{Clock, LunartickError};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use std::sync::Mutex;
+
+// What corrections are written to: the system clock for SystemClock, anything else for tests and
+// dry runs that must not touch it. Slewing and setting the frequency are unsupported unless a
+// backend says otherwise.
+pub trait ClockBackend {
+    fn set_time(&self, time: DateTime<Utc>) -> Result<(), LunartickError>;
+
+    fn slew(&self, _delta: ChronoDuration) -> Result<(), LunartickError> {
+        Err(LunartickError::Unsupported("slewing this clock"))
+    }
+
+    fn set_frequency(&self, _ppm: f64) -> Result<(), LunartickError> {
+        Err(LunartickError::Unsupported(
+            "adjusting the frequency of this clock",
+        ))
+    }
+}
+
+// The system clock, through clock_settime, settimeofday or SetSystemTime, adjtime and adjtimex.
+// Setting it reads it back as Clock::set does.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl ClockBackend for SystemClock {
+    fn set_time(&self, time: DateTime<Utc>) -> Result<(), LunartickError> {
+        Clock { time: time.into() }.set()
+    }
+
+    fn slew(&self, delta: ChronoDuration) -> Result<(), LunartickError> {
+        crate::slew(delta)
+    }
+
+    fn set_frequency(&self, ppm: f64) -> Result<(), LunartickError> {
+        crate::set_frequency(ppm)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockWrite {
+    Set(DateTime<Utc>),
+    Slew(ChronoDuration),
+    Frequency(f64),
+}
+
+// Records what would have been written and changes nothing, which needs no privileges.
+#[derive(Debug, Default)]
+pub struct RecordingClock {
+    writes: Mutex<Vec<ClockWrite>>,
+}
+
+impl RecordingClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The writes recorded so far, oldest first, leaving none behind.
+    pub fn take_writes(&self) -> Vec<ClockWrite> {
+        std::mem::take(&mut *self.writes.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn record(&self, write: ClockWrite) -> Result<(), LunartickError> {
+        self.writes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(write);
+        Ok(())
+    }
+}
+
+impl ClockBackend for RecordingClock {
+    fn set_time(&self, time: DateTime<Utc>) -> Result<(), LunartickError> {
+        self.record(ClockWrite::Set(time))
+    }
+
+    fn slew(&self, delta: ChronoDuration) -> Result<(), LunartickError> {
+        self.record(ClockWrite::Slew(delta))
+    }
+
+    fn set_frequency(&self, ppm: f64) -> Result<(), LunartickError> {
+        self.record(ClockWrite::Frequency(ppm))
+    }
+}
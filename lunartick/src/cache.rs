@@ -0,0 +1,55 @@
+use crate::{LunartickError, NTPClient, Offset};
+use std::{
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+// The last offset measured and when, so that asking for the time again within a few seconds does
+// not query every server again. The offset stays right only as long as nothing steps the clock in
+// the meantime.
+#[derive(Debug, Default)]
+pub struct OffsetCache {
+    last: Mutex<Option<(Offset, Instant)>>,
+}
+
+// Shared by Clock::now_synced_cached across the process.
+pub(crate) static PROCESS_CACHE: OffsetCache = OffsetCache::new();
+
+impl OffsetCache {
+    pub const fn new() -> Self {
+        Self {
+            last: Mutex::new(None),
+        }
+    }
+
+    // The cached offset and how long ago it was measured.
+    pub fn get(&self) -> Option<(Offset, Duration)> {
+        let last = *self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        last.map(|(offset, measured)| (offset, measured.elapsed()))
+    }
+
+    pub fn store(&self, offset: Offset) {
+        *self.last.lock().unwrap_or_else(PoisonError::into_inner) = Some((offset, Instant::now()));
+    }
+
+    pub fn clear(&self) {
+        *self.last.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    // The cached offset if it is no older than `max_age`, otherwise a fresh one measured with the
+    // client, which replaces it.
+    pub fn offset(
+        &self,
+        ntp_client: &NTPClient,
+        max_age: Duration,
+    ) -> Result<Offset, LunartickError> {
+        if let Some((offset, age)) = self.get() {
+            if age <= max_age {
+                return Ok(offset);
+            }
+        }
+        let offset = ntp_client.test()?.get_combined_offset()?;
+        self.store(offset);
+        Ok(offset)
+    }
+}
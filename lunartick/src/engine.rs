@@ -0,0 +1,598 @@
+#[cfg(feature = "net")]
+use crate::NTPClient;
+use crate::{Clock, ClockBackend, LunartickError, Offset, SystemClock, TestResults};
+use chrono::Duration as ChronoDuration;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+// How the combined offset of a set of results is to be corrected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Correction {
+    Step(ChronoDuration),
+    Slew(ChronoDuration),
+}
+
+impl Correction {
+    pub fn offset(self) -> ChronoDuration {
+        match self {
+            Correction::Step(offset) | Correction::Slew(offset) => offset,
+        }
+    }
+
+    pub fn is_step(self) -> bool {
+        matches!(self, Correction::Step(_))
+    }
+}
+
+// chrony-style makestep policy: large offsets may be stepped during the first few corrections,
+// every other correction is slewed.
+#[derive(Debug, Clone, Copy)]
+struct MakeStep {
+    threshold: ChronoDuration,
+    limit: Option<u64>,
+}
+
+// ntpd's huff-n-puff filter. A congested access link, as DSL and LTE links often are, mostly
+// delays one direction, which biases the offset by up to half the extra delay. The shortest delay
+// each server was measured with over the window is taken as the uncongested one, and offsets are
+// pulled towards zero by half of whatever a sample took longer, though never past it.
+#[derive(Debug)]
+struct HuffPuff {
+    window: Duration,
+    // The delays each server was measured with, oldest first.
+    delays: HashMap<String, VecDeque<(Instant, ChronoDuration)>>,
+}
+
+impl HuffPuff {
+    fn filter(&mut self, results: &TestResults) -> TestResults {
+        let now = Instant::now();
+        for delays in self.delays.values_mut() {
+            while delays
+                .front()
+                .is_some_and(|&(at, _)| now.saturating_duration_since(at) > self.window)
+            {
+                delays.pop_front();
+            }
+        }
+        self.delays.retain(|_, delays| !delays.is_empty());
+        let mut filtered = results.clone();
+        for (server, result) in results.servers.iter().zip(filtered.result.iter_mut()) {
+            let Ok(result) = result else {
+                continue;
+            };
+            let delay = result.delay();
+            let delays = self.delays.entry(server.to_string()).or_default();
+            delays.push_back((now, delay));
+            let Some(&(_, shortest)) = delays.iter().min_by_key(|&&(_, delay)| delay) else {
+                continue;
+            };
+            let excess = (delay - shortest) / 2;
+            let offset = result.offset();
+            let bias = if offset > excess {
+                excess
+            } else if -offset > excess {
+                -excess
+            } else {
+                offset
+            };
+            // Shifting both halves of the exchange moves the offset and keeps the delay.
+            result.outbound -= bias;
+            result.inbound -= bias;
+        }
+        filtered
+    }
+}
+
+// Why a decision came out the way it did, see SyncEngine::take_explanation.
+#[derive(Debug, Clone, Default)]
+pub struct Explanation {
+    pub servers: Vec<ServerVerdict>,
+    // How the accepted samples were combined into one offset.
+    pub combine: String,
+    // Why the clock was stepped, slewed or held.
+    pub correction: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerVerdict {
+    pub server: String,
+    pub accepted: bool,
+    pub reason: String,
+}
+
+fn explain_servers(measured: &TestResults, filtered: &TestResults) -> Vec<ServerVerdict> {
+    let rejected: Vec<_> = filtered.rejected_servers().collect();
+    measured
+        .get_all_results()
+        .enumerate()
+        .map(|(i, (server, result))| {
+            let (accepted, reason) = match result {
+                Err(failure) => (false, failure.to_string()),
+                Ok(_) if rejected.contains(&server) => {
+                    (false, "sample could not be weighted".to_owned())
+                }
+                Ok(offset) => {
+                    let mut reason = format!("offset {offset}");
+                    if let Some(delay) = measured.get_delay(i) {
+                        reason.push_str(&format!(", delay {}", Offset::from(delay)));
+                    }
+                    if let Some(Ok(corrected)) = filtered.get_offset(i) {
+                        if corrected != offset {
+                            reason.push_str(&format!(", {corrected} after huff-n-puff"));
+                        }
+                    }
+                    if measured.weights[i] != 1.0 {
+                        reason.push_str(&format!(", weight {}", measured.weights[i]));
+                    }
+                    if measured.trusted[i] {
+                        reason.push_str(", trusted");
+                    }
+                    (true, reason)
+                }
+            };
+            ServerVerdict {
+                server: server.to_owned(),
+                accepted,
+                reason,
+            }
+        })
+        .collect()
+}
+
+// Most a kernel will hold the clock frequency off nominal by.
+const MAX_FREQUENCY_PPM: f64 = 500.0;
+// Updates further apart than this are dominated by frequency wander rather than measurement
+// noise, and also correct the frequency from how fast the offset grew (ntpd's Allan intercept).
+const ALLAN_INTERCEPT: Duration = Duration::from_secs(2048);
+// Share of the frequency error measured across a long interval that is corrected at once.
+const FLL_GAIN: f64 = 0.25;
+
+// A hybrid phase- and frequency-locked loop. Each update slews away the share of the offset the
+// time since the last one makes up of the time constant, and nudges the clock frequency by the
+// offset integrated over that time, so the clock converges over a few time constants instead of
+// chasing every noisy sample. The gains make the loop critically damped while updates come much
+// more often than the time constant, and overdamped as they come less often. The loop would go
+// unstable with updates more than about 2.8 time constants apart, so like ntpd, which ties its
+// time constant to the poll interval, it never uses one shorter than the time between updates.
+#[derive(Debug)]
+struct Discipline {
+    time_constant: Duration,
+    frequency_ppm: f64,
+    // When the last update was, and the offset it left for the next one to find.
+    last: Option<(Instant, ChronoDuration)>,
+}
+
+impl Discipline {
+    // Returns the phase correction to slew, and updates the frequency to run the clock at.
+    fn update(&mut self, offset: ChronoDuration, now: Instant) -> ChronoDuration {
+        let theta = seconds(offset);
+        let phase = match self.last {
+            Some((at, residual)) => {
+                let mu = now.saturating_duration_since(at).as_secs_f64();
+                let tau = self.time_constant.as_secs_f64().max(mu).max(1.0);
+                let mut frequency = theta * mu / (4.0 * tau * tau);
+                if mu >= ALLAN_INTERCEPT.as_secs_f64() {
+                    frequency += FLL_GAIN * (theta - seconds(residual)) / mu;
+                }
+                self.frequency_ppm = (self.frequency_ppm + frequency * 1e6)
+                    .clamp(-MAX_FREQUENCY_PPM, MAX_FREQUENCY_PPM);
+                theta * mu / tau
+            }
+            // Nothing to tell noise from a real offset with yet.
+            None => theta,
+        };
+        let phase = ChronoDuration::nanoseconds((phase * 1e9) as i64);
+        self.last = Some((now, offset - phase));
+        phase
+    }
+
+    // A step takes the whole offset away, the frequency is left as it was.
+    fn stepped(&mut self) {
+        self.last = Some((Instant::now(), ChronoDuration::zero()));
+    }
+}
+
+fn seconds(d: ChronoDuration) -> f64 {
+    match d.num_nanoseconds() {
+        Some(nanos) => nanos as f64 / 1e9,
+        None => d.num_milliseconds() as f64 / 1e3,
+    }
+}
+
+// The discipline on top of the measurements of an NTPClient: the combined offset of the servers
+// that survive falseticker rejection is corrected, by a step or a slew as the policy decides.
+// Without a makestep policy every correction is a step, unless the clock is disciplined.
+#[derive(Debug, Default)]
+pub struct SyncEngine {
+    makestep: Option<MakeStep>,
+    min_networks: usize,
+    attack_threshold: Option<ChronoDuration>,
+    huff_puff: Option<HuffPuff>,
+    discipline: Option<Discipline>,
+    max_correction: Option<ChronoDuration>,
+    limited: bool,
+    explanation: Option<Explanation>,
+    corrections: u64,
+}
+
+fn exceeds(offset: ChronoDuration, threshold: ChronoDuration) -> bool {
+    offset > threshold || -offset > threshold
+}
+
+impl SyncEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Steps offsets larger than `threshold` during the first `limit` corrections, all of them
+    // with no limit, and slews everything else.
+    pub fn with_makestep(mut self, threshold: ChronoDuration, limit: Option<u64>) -> Self {
+        self.makestep = Some(MakeStep { threshold, limit });
+        self
+    }
+
+    // Refuses to correct the clock unless the accepted responses came from at least this many
+    // networks, see TestResults::get_networks.
+    pub fn with_min_networks(mut self, networks: usize) -> Self {
+        self.min_networks = networks;
+        self
+    }
+
+    // With trusted servers among the results, holds the clock when the others call for a
+    // correction larger than `threshold` and the trusted ones do not confirm it, either measuring
+    // an offset more than `threshold` away or not answering at all. Public servers suddenly
+    // agreeing on a jump the trusted ones do not see suggests their responses are being forged or
+    // delayed on the way, see LunartickError::SuspectedAttack.
+    pub fn with_attack_threshold(mut self, threshold: ChronoDuration) -> Self {
+        self.attack_threshold = Some(threshold);
+        self
+    }
+
+    // Corrects offsets measured during congestion against the shortest delay of each server over
+    // the last `window`, which should span a quiet period of the link, a few hours or a night.
+    // Only offsets are corrected, results keep what was measured.
+    pub fn with_huff_puff(mut self, window: Duration) -> Self {
+        self.huff_puff = Some(HuffPuff {
+            window,
+            delays: HashMap::new(),
+        });
+        self
+    }
+
+    // Disciplines the clock with a phase- and frequency-locked loop instead of slewing each offset
+    // away in full: offsets are slewed off over about `time_constant` and the clock frequency is
+    // adjusted to stop them building up again. A longer time constant rides out noisier offsets
+    // but takes longer to converge, about as long as the polling interval or longer works best.
+    // Offsets the makestep policy steps are still stepped. The loop starts from the frequency the
+    // kernel already runs the clock at, so a restart keeps what was learned.
+    pub fn with_discipline(mut self, time_constant: Duration) -> Self {
+        self.discipline = Some(Discipline {
+            time_constant,
+            frequency_ppm: crate::kernel_sync_status()
+                .ok()
+                .and_then(|status| status.frequency_ppm)
+                .unwrap_or(0.0),
+            last: None,
+        });
+        self
+    }
+
+    // Corrects at most `max` per cycle, steps included, leaving the rest of a larger offset to the
+    // following cycles so applications never see the clock jump by more than that at once.
+    pub fn with_max_correction(mut self, max: ChronoDuration) -> Self {
+        self.max_correction = Some(max);
+        self
+    }
+
+    // Whether the last correction was cut short by the limit of with_max_correction, leaving part
+    // of the offset for the following cycles.
+    pub fn is_limited(&self) -> bool {
+        self.limited
+    }
+
+    // The frequency the discipline runs the clock at, None without one.
+    pub fn get_frequency_ppm(&self) -> Option<f64> {
+        self.discipline
+            .as_ref()
+            .map(|discipline| discipline.frequency_ppm)
+    }
+
+    fn check_attack(&self, results: &TestResults) -> Result<(), LunartickError> {
+        let Some(threshold) = self.attack_threshold else {
+            return Ok(());
+        };
+        if !results.has_trusted_servers() {
+            return Ok(());
+        }
+        let Some(offset) = results.get_untrusted_offset() else {
+            return Ok(());
+        };
+        if !exceeds(offset.into(), threshold) {
+            return Ok(());
+        }
+        let trusted = results.get_trusted_offset();
+        let confirmed = trusted.is_some_and(|trusted| {
+            !exceeds(
+                ChronoDuration::from(offset) - ChronoDuration::from(trusted),
+                threshold,
+            )
+        });
+        if confirmed {
+            Ok(())
+        } else {
+            Err(LunartickError::SuspectedAttack { offset, trusted })
+        }
+    }
+
+    // Corrections decided so far, whether or not they could be applied.
+    pub fn get_corrections(&self) -> u64 {
+        self.corrections
+    }
+
+    // The explanation of the last decision, once. None when nothing was decided since, as when no
+    // server could be measured at all.
+    pub fn take_explanation(&mut self) -> Option<Explanation> {
+        self.explanation.take()
+    }
+
+    pub fn decide(&mut self, results: &TestResults) -> Result<Correction, LunartickError> {
+        let filtered = self
+            .huff_puff
+            .as_mut()
+            .map(|huff_puff| huff_puff.filter(results));
+        let measured = results;
+        let results = filtered.as_ref().unwrap_or(results);
+        let mut explanation = Explanation {
+            servers: explain_servers(measured, results),
+            ..Explanation::default()
+        };
+        let accepted = explanation
+            .servers
+            .iter()
+            .filter(|verdict| verdict.accepted)
+            .count();
+        explanation.combine = format!(
+            "mean of {accepted} of {} servers weighted by their weight over the square of their delay{}",
+            explanation.servers.len(),
+            if filtered.is_some() {
+                ", offsets corrected by huff-n-puff"
+            } else {
+                ""
+            }
+        );
+        self.limited = false;
+        let decision = self
+            .decide_filtered(results, &mut explanation)
+            .map(|correction| self.limit(correction, &mut explanation));
+        if let Err(e) = &decision {
+            explanation.correction = format!("held the clock: {e}");
+        }
+        self.explanation = Some(explanation);
+        decision
+    }
+
+    fn limit(&mut self, correction: Correction, explanation: &mut Explanation) -> Correction {
+        let offset = correction.offset();
+        let Some(max) = self.max_correction.filter(|max| exceeds(offset, *max)) else {
+            return correction;
+        };
+        self.limited = true;
+        let limited = if offset < ChronoDuration::zero() {
+            -max
+        } else {
+            max
+        };
+        explanation.correction = format!(
+            "{}, limited to {} this cycle",
+            explanation.correction,
+            Offset::from(limited)
+        );
+        match correction {
+            Correction::Step(_) => Correction::Step(limited),
+            Correction::Slew(_) => Correction::Slew(limited),
+        }
+    }
+
+    fn decide_filtered(
+        &mut self,
+        results: &TestResults,
+        explanation: &mut Explanation,
+    ) -> Result<Correction, LunartickError> {
+        let offset = results.applied_correction()?;
+        let networks = results.get_networks();
+        if networks < self.min_networks {
+            return Err(LunartickError::InsufficientDiversity {
+                networks,
+                required: self.min_networks,
+            });
+        }
+        self.check_attack(results)?;
+        let corrections = self.corrections;
+        self.corrections += 1;
+        let shown = Offset::from(offset);
+        let step = match self.makestep {
+            Some(makestep) => {
+                let threshold = Offset::from(makestep.threshold);
+                let within_limit = makestep.limit.is_none_or(|limit| corrections < limit);
+                let exceeded = exceeds(offset, makestep.threshold);
+                explanation.correction = match makestep.limit {
+                    Some(limit) if !within_limit => format!(
+                        "slewed {shown}: all {limit} corrections makestep may step have been made"
+                    ),
+                    _ if exceeded => {
+                        format!(
+                            "stepped {shown}: larger than the makestep threshold of {threshold}"
+                        )
+                    }
+                    _ => format!("slewed {shown}: within the makestep threshold of {threshold}"),
+                };
+                within_limit && exceeded
+            }
+            None if self.discipline.is_none() => {
+                explanation.correction = format!("stepped {shown}: no makestep policy");
+                true
+            }
+            None => false,
+        };
+        if step {
+            if let Some(discipline) = &mut self.discipline {
+                discipline.stepped();
+            }
+            return Ok(Correction::Step(offset));
+        }
+        Ok(Correction::Slew(match &mut self.discipline {
+            Some(discipline) => {
+                let phase = discipline.update(offset, Instant::now());
+                explanation.correction = format!(
+                    "slewed {} of {shown} by the clock discipline with a {}s time constant, \
+                     frequency {:+.3} ppm",
+                    Offset::from(phase),
+                    discipline.time_constant.as_secs(),
+                    discipline.frequency_ppm
+                );
+                phase
+            }
+            None => offset,
+        }))
+    }
+
+    pub fn apply(&self, correction: Correction) -> Result<(), LunartickError> {
+        self.apply_with(correction, &SystemClock)
+    }
+
+    // Applies the correction to `backend` instead of the system clock, e.g. a RecordingClock to
+    // see what would be written.
+    pub fn apply_with(
+        &self,
+        correction: Correction,
+        backend: &dyn ClockBackend,
+    ) -> Result<(), LunartickError> {
+        match correction {
+            Correction::Step(offset) => Clock::now_with_offset_duration(offset).set_with(backend),
+            Correction::Slew(offset) => {
+                if let Some(discipline) = &self.discipline {
+                    backend.set_frequency(discipline.frequency_ppm)?;
+                }
+                backend.slew(offset)
+            }
+        }
+    }
+
+    // One full cycle: measure, decide and correct.
+    #[cfg(feature = "net")]
+    pub fn sync(
+        &mut self,
+        client: &NTPClient,
+    ) -> Result<(TestResults, Correction), LunartickError> {
+        let results = client.test()?;
+        let correction = self.decide(&results)?;
+        self.apply(correction)?;
+        Ok((results, correction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClockWrite, RecordingClock};
+    use chrono::{DateTime, Utc};
+
+    // Runs the loop against a clock `drift_ppm` fast with updates `interval` apart, and returns the
+    // offset each update measured.
+    fn converge(time_constant: u64, interval: u64, drift_ppm: f64) -> Vec<f64> {
+        let mut discipline = Discipline {
+            time_constant: Duration::from_secs(time_constant),
+            frequency_ppm: 0.0,
+            last: None,
+        };
+        let mut now = Instant::now();
+        let mut offset = 0.1;
+        let mut measured = Vec::new();
+        for _ in 0..200 {
+            measured.push(offset);
+            let phase = discipline.update(ChronoDuration::nanoseconds((offset * 1e9) as i64), now);
+            now += Duration::from_secs(interval);
+            offset +=
+                (drift_ppm - discipline.frequency_ppm) * 1e-6 * interval as f64 - seconds(phase);
+        }
+        measured
+    }
+
+    #[test]
+    fn discipline_converges_with_updates_further_apart_than_the_time_constant() {
+        for time_constant in [64, 300, 600, 1800] {
+            let measured = converge(time_constant, 1800, 50.0);
+            let settled = measured[100..]
+                .iter()
+                .fold(0.0f64, |max, o| max.max(o.abs()));
+            assert!(settled < 1e-6, "{time_constant}s: {settled}s");
+        }
+    }
+
+    #[test]
+    fn steps_set_the_time_offset_from_now() {
+        let clock = RecordingClock::new();
+        let offset = ChronoDuration::seconds(-3600);
+        let before = Utc::now() + offset;
+        SyncEngine::new()
+            .apply_with(Correction::Step(offset), &clock)
+            .unwrap();
+        let after = Utc::now() + offset;
+        match clock.take_writes()[..] {
+            [ClockWrite::Set(time)] => assert!((before..=after).contains(&time), "{time}"),
+            ref writes => panic!("{writes:?}"),
+        }
+    }
+
+    #[test]
+    fn slews_leave_the_frequency_alone_without_a_discipline() {
+        let clock = RecordingClock::new();
+        let offset = ChronoDuration::milliseconds(25);
+        SyncEngine::new()
+            .apply_with(Correction::Slew(offset), &clock)
+            .unwrap();
+        assert_eq!(clock.take_writes(), [ClockWrite::Slew(offset)]);
+    }
+
+    #[test]
+    fn disciplined_slews_set_the_frequency_first() {
+        let clock = RecordingClock::new();
+        let mut engine = SyncEngine::new().with_discipline(Duration::from_secs(64));
+        let discipline = engine.discipline.as_mut().unwrap();
+        discipline.frequency_ppm = -12.5;
+        let offset = ChronoDuration::microseconds(-800);
+        engine.apply_with(Correction::Slew(offset), &clock).unwrap();
+        assert_eq!(
+            clock.take_writes(),
+            [ClockWrite::Frequency(-12.5), ClockWrite::Slew(offset)]
+        );
+        // Steps never touch the frequency.
+        engine.apply_with(Correction::Step(offset), &clock).unwrap();
+        assert!(matches!(clock.take_writes()[..], [ClockWrite::Set(_)]));
+    }
+
+    #[test]
+    fn backend_errors_are_returned() {
+        struct SetOnly(RecordingClock);
+
+        impl ClockBackend for SetOnly {
+            fn set_time(&self, time: DateTime<Utc>) -> Result<(), LunartickError> {
+                self.0.set_time(time)
+            }
+        }
+
+        let clock = SetOnly(RecordingClock::new());
+        let result = SyncEngine::new().apply_with(Correction::Slew(ChronoDuration::zero()), &clock);
+        assert!(matches!(result, Err(LunartickError::Unsupported(_))));
+        assert_eq!(clock.0.take_writes(), []);
+    }
+
+    #[test]
+    fn discipline_does_not_overshoot_within_the_time_constant() {
+        let measured = converge(1024, 64, 0.0);
+        assert!(measured.iter().all(|&offset| offset >= 0.0));
+        assert!(measured.last().unwrap().abs() < 1e-3);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::InvalidPacket;
+
+const HEADER_LENGTH: usize = 4;
+// RFC 7822: every field is at least 16 octets, and without a MAC the last one at least 28, so that a
+// receiver can tell it apart from a legacy 20 or 24 octet MAC.
+const MIN_FIELD_LENGTH: usize = 16;
+const MIN_LAST_FIELD_LENGTH: usize = 28;
+const MAX_MAC_LENGTH: usize = 24;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionField {
+    field_type: u16,
+    value: Vec<u8>,
+}
+
+impl ExtensionField {
+    pub fn new<V: Into<Vec<u8>>>(field_type: u16, value: V) -> Self {
+        Self {
+            field_type,
+            value: value.into(),
+        }
+    }
+
+    pub fn field_type(&self) -> u16 {
+        self.field_type
+    }
+
+    // Decoded values include the zero padding, the wire format does not record the unpadded length.
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    pub fn encoded_len(&self, last: bool) -> usize {
+        let min = if last {
+            MIN_LAST_FIELD_LENGTH
+        } else {
+            MIN_FIELD_LENGTH
+        };
+        (HEADER_LENGTH + self.value.len())
+            .next_multiple_of(4)
+            .max(min)
+    }
+
+    pub fn encode(fields: &[ExtensionField], out: &mut Vec<u8>) -> Result<(), InvalidPacket> {
+        for (i, field) in fields.iter().enumerate() {
+            let len = field.encoded_len(i + 1 == fields.len());
+            let len_field = u16::try_from(len).map_err(|_| InvalidPacket::ExtensionLength(len))?;
+            out.extend_from_slice(&field.field_type.to_be_bytes());
+            out.extend_from_slice(&len_field.to_be_bytes());
+            out.extend_from_slice(&field.value);
+            out.resize(out.len() + len - HEADER_LENGTH - field.value.len(), 0);
+        }
+        Ok(())
+    }
+
+    // Decodes the fields following the 48 octet header. A trailing legacy MAC is skipped.
+    pub fn decode(mut data: &[u8]) -> Result<Vec<ExtensionField>, InvalidPacket> {
+        let mut fields = Vec::new();
+        while data.len() > MAX_MAC_LENGTH {
+            let field_type = u16::from_be_bytes([data[0], data[1]]);
+            let len = usize::from(u16::from_be_bytes([data[2], data[3]]));
+            if len < MIN_FIELD_LENGTH || len % 4 != 0 || len > data.len() {
+                return Err(InvalidPacket::ExtensionLength(len));
+            }
+            fields.push(ExtensionField::new(field_type, &data[HEADER_LENGTH..len]));
+            data = &data[len..];
+        }
+        if !matches!(data.len(), 0 | 20 | MAX_MAC_LENGTH) {
+            return Err(InvalidPacket::TrailingData(data.len()));
+        }
+        Ok(fields)
+    }
+}
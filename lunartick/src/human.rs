@@ -0,0 +1,307 @@
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, FixedOffset, Local, NaiveDate, NaiveDateTime,
+    NaiveTime, Offset as _, TimeZone, Utc, Weekday,
+};
+
+// Offsets from UTC in minutes. Abbreviations are ambiguous in general, so this
+// picks the reading most people typing them at a terminal would mean.
+const TIMEZONES: &[(&str, i32)] = &[
+    ("UTC", 0),
+    ("UT", 0),
+    ("GMT", 0),
+    ("Z", 0),
+    ("WET", 0),
+    ("WEST", 60),
+    ("BST", 60),
+    ("CET", 60),
+    ("CEST", 120),
+    ("EET", 120),
+    ("EEST", 180),
+    ("MSK", 180),
+    ("IST", 330),
+    ("ICT", 420),
+    ("WIB", 420),
+    ("CST", -360),
+    ("CDT", -300),
+    ("EST", -300),
+    ("EDT", -240),
+    ("MST", -420),
+    ("MDT", -360),
+    ("PST", -480),
+    ("PDT", -420),
+    ("AKST", -540),
+    ("AKDT", -480),
+    ("HST", -600),
+    ("SGT", 480),
+    ("HKT", 480),
+    ("AWST", 480),
+    ("JST", 540),
+    ("KST", 540),
+    ("ACST", 570),
+    ("AEST", 600),
+    ("AEDT", 660),
+    ("NZST", 720),
+    ("NZDT", 780),
+];
+
+struct Parsed {
+    date: Option<NaiveDate>,
+    day_shift: i64,
+    weekday: Option<(Weekday, bool)>,
+    time: Option<NaiveTime>,
+    offset: Option<FixedOffset>,
+    delta: ChronoDuration,
+    months: i32,
+    now: bool,
+}
+
+impl Default for Parsed {
+    fn default() -> Self {
+        Self {
+            date: None,
+            day_shift: 0,
+            weekday: None,
+            time: None,
+            offset: None,
+            delta: ChronoDuration::zero(),
+            months: 0,
+            now: false,
+        }
+    }
+}
+
+pub(crate) fn parse(input: &str, now: DateTime<Utc>) -> Option<DateTime<FixedOffset>> {
+    let lower = input.trim().to_ascii_lowercase();
+    let mut tokens = lower.split_whitespace().peekable();
+    let mut parsed = Parsed::default();
+    tokens.peek()?;
+    while let Some(token) = tokens.next() {
+        match token {
+            "now" => parsed.now = true,
+            "today" => (),
+            "tomorrow" => parsed.day_shift += 1,
+            "yesterday" => parsed.day_shift -= 1,
+            "at" | "on" | "and" | "," => (),
+            "noon" | "midday" => parsed.time = Some(NaiveTime::from_hms_opt(12, 0, 0)?),
+            "midnight" => parsed.time = Some(NaiveTime::from_hms_opt(0, 0, 0)?),
+            "next" => {
+                let weekday = parse_weekday(tokens.next()?)?;
+                parsed.weekday = Some((weekday, true));
+            }
+            "in" => {
+                let (delta, months) = parse_amount(tokens.next()?, tokens.next()?)?;
+                parsed.delta = parsed.delta.checked_add(&delta)?;
+                parsed.months = parsed.months.checked_add(months)?;
+            }
+            _ if token.starts_with('+')
+                && tokens
+                    .peek()
+                    .and_then(|unit| parse_amount("1", unit))
+                    .is_some() =>
+            {
+                let (delta, months) = parse_amount(&token[1..], tokens.next()?)?;
+                parsed.delta = parsed.delta.checked_add(&delta)?;
+                parsed.months = parsed.months.checked_add(months)?;
+            }
+            _ => {
+                if let Some(weekday) = parse_weekday(token) {
+                    parsed.weekday = Some((weekday, false));
+                } else if let Some(date) = parse_date(token) {
+                    parsed.date = Some(date);
+                } else if let Some(time) = parse_time(token, &mut tokens) {
+                    parsed.time = Some(time);
+                } else if let Some(offset) = parse_offset(token) {
+                    parsed.offset = Some(offset);
+                } else if let Some(unit) = tokens.next() {
+                    let (delta, months) = parse_amount(token, unit)?;
+                    tokens.next_if_eq(&"ago")?;
+                    parsed.delta = parsed.delta.checked_sub(&delta)?;
+                    parsed.months = parsed.months.checked_sub(months)?;
+                } else {
+                    return None;
+                }
+            }
+        }
+    }
+    resolve(parsed, now)
+}
+
+fn resolve(parsed: Parsed, now: DateTime<Utc>) -> Option<DateTime<FixedOffset>> {
+    let offset = parsed
+        .offset
+        .unwrap_or_else(|| Local.offset_from_utc_datetime(&now.naive_utc()).fix());
+    let local_now = now.with_timezone(&offset);
+    let has_calendar = parsed.date.is_some()
+        || parsed.weekday.is_some()
+        || parsed.day_shift != 0
+        || parsed.time.is_some();
+    let base = if has_calendar && !parsed.now {
+        let mut date = parsed
+            .date
+            .unwrap_or_else(|| local_now.naive_local().date());
+        if let Some((weekday, skip_today)) = parsed.weekday {
+            let today = date.weekday().num_days_from_monday() as i64;
+            let target = weekday.num_days_from_monday() as i64;
+            let mut ahead = (target - today).rem_euclid(7);
+            if ahead == 0 && skip_today {
+                ahead = 7;
+            }
+            date = date.checked_add_signed(ChronoDuration::days(ahead))?;
+        }
+        date = date.checked_add_signed(checked_seconds(parsed.day_shift, SECONDS_PER_DAY)?)?;
+        let time = match parsed.time {
+            Some(time) => time,
+            None => NaiveTime::from_hms_opt(0, 0, 0)?,
+        };
+        let naive = NaiveDateTime::new(date, time);
+        match parsed.offset {
+            Some(offset) => offset.from_local_datetime(&naive).single()?,
+            None => {
+                let local = Local.from_local_datetime(&naive).earliest()?;
+                local.with_timezone(&local.offset().fix())
+            }
+        }
+    } else {
+        local_now
+    };
+    let shifted = add_months(base, parsed.months)?;
+    shifted.checked_add_signed(parsed.delta)
+}
+
+fn add_months(dt: DateTime<FixedOffset>, months: i32) -> Option<DateTime<FixedOffset>> {
+    if months == 0 {
+        return Some(dt);
+    }
+    let naive = dt.naive_local();
+    let total = (naive.year() * 12 + naive.month0() as i32).checked_add(months)?;
+    let (year, month0) = (total.div_euclid(12), total.rem_euclid(12) as u32);
+    let day = (1..=naive.day())
+        .rev()
+        .find(|&day| NaiveDate::from_ymd_opt(year, month0 + 1, day).is_some())?;
+    let date = NaiveDate::from_ymd_opt(year, month0 + 1, day)?;
+    dt.offset()
+        .from_local_datetime(&NaiveDateTime::new(date, naive.time()))
+        .single()
+}
+
+fn parse_amount(amount: &str, unit: &str) -> Option<(ChronoDuration, i32)> {
+    let n: i64 = match amount {
+        "a" | "an" | "one" => 1,
+        _ => amount.parse().ok()?,
+    };
+    let unit = unit.trim_end_matches(',');
+    let unit = if unit.len() > 1 {
+        unit.trim_end_matches('s')
+    } else {
+        unit
+    };
+    let delta = match unit {
+        "s" | "sec" | "second" => checked_seconds(n, 1)?,
+        "m" | "min" | "minute" => checked_seconds(n, 60)?,
+        "h" | "hr" | "hour" => checked_seconds(n, 3600)?,
+        "d" | "day" => checked_seconds(n, SECONDS_PER_DAY)?,
+        "w" | "wk" | "week" => checked_seconds(n, 7 * SECONDS_PER_DAY)?,
+        "fortnight" => checked_seconds(n, 14 * SECONDS_PER_DAY)?,
+        "mo" | "mon" | "month" => return Some((ChronoDuration::zero(), i32::try_from(n).ok()?)),
+        "y" | "yr" | "year" => {
+            return Some((
+                ChronoDuration::zero(),
+                i32::try_from(n.checked_mul(12)?).ok()?,
+            ))
+        }
+        _ => return None,
+    };
+    Some((delta, 0))
+}
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+// The constructors of chrono's Duration panic on amounts it cannot hold, which a mistyped number
+// easily is.
+fn checked_seconds(n: i64, unit: i64) -> Option<ChronoDuration> {
+    let seconds = n.checked_mul(unit)?;
+    (seconds.checked_abs()? <= i64::MAX / 1000).then(|| ChronoDuration::seconds(seconds))
+}
+
+fn parse_weekday(token: &str) -> Option<Weekday> {
+    let weekday = match token.trim_end_matches(',') {
+        "mon" | "monday" => Weekday::Mon,
+        "tue" | "tues" | "tuesday" => Weekday::Tue,
+        "wed" | "wednesday" => Weekday::Wed,
+        "thu" | "thur" | "thurs" | "thursday" => Weekday::Thu,
+        "fri" | "friday" => Weekday::Fri,
+        "sat" | "saturday" => Weekday::Sat,
+        "sun" | "sunday" => Weekday::Sun,
+        _ => return None,
+    };
+    Some(weekday)
+}
+
+fn parse_date(token: &str) -> Option<NaiveDate> {
+    ["%Y-%m-%d", "%Y/%m/%d", "%d.%m.%Y"]
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(token, fmt).ok())
+}
+
+fn parse_time<'a, I>(token: &str, tokens: &mut std::iter::Peekable<I>) -> Option<NaiveTime>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let (clock, meridiem) = if let Some(clock) = token.strip_suffix("am") {
+        (clock, Some(false))
+    } else if let Some(clock) = token.strip_suffix("pm") {
+        (clock, Some(true))
+    } else {
+        (token, None)
+    };
+    if clock.is_empty() || !clock.bytes().all(|b| b.is_ascii_digit() || b == b':') {
+        return None;
+    }
+    let mut parts = clock.split(':').map(str::parse::<u32>);
+    let hour = parts.next()?.ok()?;
+    let minute = parts.next().transpose().ok()?.unwrap_or(0);
+    let second = parts.next().transpose().ok()?.unwrap_or(0);
+    if parts.next().is_some() {
+        return None;
+    }
+    let meridiem = meridiem.or_else(|| match tokens.peek() {
+        Some(&"am") => tokens.next().map(|_| false),
+        Some(&"pm") => tokens.next().map(|_| true),
+        _ => None,
+    });
+    let hour = match meridiem {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(true) => hour % 12 + 12,
+        Some(false) => hour % 12,
+        None if !clock.contains(':') => return None,
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, second)
+}
+
+fn parse_offset(token: &str) -> Option<FixedOffset> {
+    let upper = token.to_ascii_uppercase();
+    if let Some((_, value)) = TIMEZONES.iter().find(|(name, _)| *name == upper) {
+        return FixedOffset::east_opt(value * 60);
+    }
+    let upper = upper
+        .strip_prefix("UTC")
+        .or_else(|| upper.strip_prefix("GMT"))
+        .unwrap_or(&upper);
+    let (sign, rest) = match upper.as_bytes().first()? {
+        b'+' => (1, &upper[1..]),
+        b'-' => (-1, &upper[1..]),
+        _ => return None,
+    };
+    let digits: String = rest.chars().filter(|c| *c != ':').collect();
+    if digits.is_empty() || digits.len() > 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = if digits.len() <= 2 {
+        (digits.parse::<i32>().ok()?, 0)
+    } else {
+        let split = digits.len() - 2;
+        (digits[..split].parse().ok()?, digits[split..].parse().ok()?)
+    };
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
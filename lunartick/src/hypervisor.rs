@@ -0,0 +1,59 @@
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HypervisorTimeSync {
+    KvmClock,
+    HyperV,
+    VmwareTools,
+    Xen,
+}
+
+impl Display for HypervisorTimeSync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HypervisorTimeSync::KvmClock => "kvm-clock",
+            HypervisorTimeSync::HyperV => "Hyper-V time synchronization",
+            HypervisorTimeSync::VmwareTools => "VMware Tools time synchronization",
+            HypervisorTimeSync::Xen => "Xen clocksource",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn detect_hypervisor_time_sync() -> Option<HypervisorTimeSync> {
+    use std::{fs, path::Path};
+
+    let read = |path: &str| fs::read_to_string(path).map(|s| s.trim().to_owned());
+    let clocksource = read("/sys/devices/system/clocksource/clocksource0/current_clocksource")
+        .unwrap_or_default();
+    if Path::new("/sys/module/hv_utils").exists() || clocksource.starts_with("hyperv_clocksource") {
+        return Some(HypervisorTimeSync::HyperV);
+    }
+    if read("/sys/class/dmi/id/sys_vendor").is_ok_and(|vendor| vendor.contains("VMware"))
+        && vmtoolsd_running()
+    {
+        return Some(HypervisorTimeSync::VmwareTools);
+    }
+    match clocksource.as_str() {
+        "kvm-clock" => Some(HypervisorTimeSync::KvmClock),
+        "xen" => Some(HypervisorTimeSync::Xen),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn vmtoolsd_running() -> bool {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        std::fs::read_to_string(entry.path().join("comm"))
+            .is_ok_and(|comm| comm.trim() == "vmtoolsd")
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn detect_hypervisor_time_sync() -> Option<HypervisorTimeSync> {
+    None
+}
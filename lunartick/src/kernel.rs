@@ -0,0 +1,199 @@
+use crate::LunartickError;
+use chrono::Duration as ChronoDuration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KernelSyncStatus {
+    pub synchronized: Option<bool>,
+    pub offset: Option<ChronoDuration>,
+    pub max_error: Option<ChronoDuration>,
+    pub estimated_error: Option<ChronoDuration>,
+    pub frequency_ppm: Option<f64>,
+    pub tick: Option<ChronoDuration>,
+    pub tick_ppm: Option<f64>,
+}
+
+impl KernelSyncStatus {
+    pub fn total_frequency_ppm(&self) -> Option<f64> {
+        match (self.frequency_ppm, self.tick_ppm) {
+            (None, None) => None,
+            (frequency, tick) => Some(frequency.unwrap_or(0.0) + tick.unwrap_or(0.0)),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn kernel_sync_status() -> Result<KernelSyncStatus, LunartickError> {
+    use libc::{adjtimex, sysconf, timex, _SC_CLK_TCK, STA_NANO, STA_UNSYNC, TIME_ERROR};
+
+    let mut tx: timex = unsafe { std::mem::zeroed() };
+    let state = unsafe { adjtimex(&mut tx) };
+    if state == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let offset = if tx.status & STA_NANO != 0 {
+        ChronoDuration::nanoseconds(tx.offset as i64)
+    } else {
+        ChronoDuration::microseconds(tx.offset as i64)
+    };
+    let hz = unsafe { sysconf(_SC_CLK_TCK) };
+    let tick_ppm = (hz > 0).then(|| {
+        let nominal = 1e6 / hz as f64;
+        (tx.tick as f64 - nominal) / nominal * 1e6
+    });
+    Ok(KernelSyncStatus {
+        synchronized: Some(tx.status & STA_UNSYNC == 0 && state != TIME_ERROR),
+        offset: Some(offset),
+        max_error: Some(ChronoDuration::microseconds(tx.maxerror as i64)),
+        estimated_error: Some(ChronoDuration::microseconds(tx.esterror as i64)),
+        frequency_ppm: Some(tx.freq as f64 / 65536.0),
+        tick: Some(ChronoDuration::microseconds(tx.tick as i64)),
+        tick_ppm,
+    })
+}
+
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+pub fn kernel_sync_status() -> Result<KernelSyncStatus, LunartickError> {
+    use libc::{ntp_adjtime, timex, STA_UNSYNC, TIME_ERROR};
+
+    let mut tx: timex = unsafe { std::mem::zeroed() };
+    let state = unsafe { ntp_adjtime(&mut tx) };
+    if state == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(KernelSyncStatus {
+        synchronized: Some(tx.status & STA_UNSYNC == 0 && state != TIME_ERROR),
+        offset: Some(ChronoDuration::microseconds(tx.offset as i64)),
+        max_error: Some(ChronoDuration::microseconds(tx.maxerror as i64)),
+        estimated_error: Some(ChronoDuration::microseconds(tx.esterror as i64)),
+        frequency_ppm: Some(tx.freq as f64 / 65536.0),
+        tick: None,
+        tick_ppm: None,
+    })
+}
+
+// Sets how much faster than nominal the kernel runs the clock, in parts per million, as a
+// frequency-locked loop needs. Slews pending from adjtime are left alone.
+#[cfg(target_os = "linux")]
+pub fn set_frequency(ppm: f64) -> Result<(), LunartickError> {
+    use libc::{adjtimex, timex, ADJ_FREQUENCY};
+
+    let mut tx: timex = unsafe { std::mem::zeroed() };
+    tx.modes = ADJ_FREQUENCY;
+    tx.freq = (ppm * 65536.0) as _;
+    if unsafe { adjtimex(&mut tx) } == -1 {
+        return Err(crate::set_error(std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+pub fn set_frequency(ppm: f64) -> Result<(), LunartickError> {
+    use libc::{ntp_adjtime, timex, MOD_FREQUENCY};
+
+    require_sys_time()?;
+    let mut tx: timex = unsafe { std::mem::zeroed() };
+    tx.modes = MOD_FREQUENCY;
+    tx.freq = (ppm * 65536.0) as _;
+    if unsafe { ntp_adjtime(&mut tx) } == -1 {
+        return Err(crate::set_error(std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "illumos", target_os = "solaris")))]
+pub fn set_frequency(_ppm: f64) -> Result<(), LunartickError> {
+    Err(LunartickError::Unsupported("adjusting the clock frequency"))
+}
+
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+pub(crate) fn require_sys_time() -> Result<(), LunartickError> {
+    extern "C" {
+        fn priv_ineffect(name: *const libc::c_char) -> libc::c_int;
+    }
+
+    if unsafe { priv_ineffect(c"sys_time".as_ptr()) } == 0 {
+        return Err(LunartickError::ClockSetDenied(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "the sys_time privilege is not in the effective set",
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn kernel_sync_status() -> Result<KernelSyncStatus, LunartickError> {
+    use windows::Win32::{Foundation::BOOL, System::SystemInformation::GetSystemTimeAdjustment};
+
+    let mut adjustment = 0u32;
+    let mut increment = 0u32;
+    let mut disabled = BOOL::default();
+    if !unsafe { GetSystemTimeAdjustment(&mut adjustment, &mut increment, &mut disabled) }.as_bool()
+    {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let disciplined = !disabled.as_bool() && increment != 0;
+    let frequency_ppm =
+        disciplined.then(|| (adjustment as f64 - increment as f64) / increment as f64 * 1e6);
+    let tick = if disciplined { adjustment } else { increment };
+    Ok(KernelSyncStatus {
+        synchronized: None,
+        offset: None,
+        max_error: None,
+        estimated_error: None,
+        frequency_ppm,
+        tick: Some(ChronoDuration::nanoseconds(tick as i64 * 100)),
+        tick_ppm: None,
+    })
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "illumos",
+    target_os = "solaris",
+    windows
+)))]
+pub fn kernel_sync_status() -> Result<KernelSyncStatus, LunartickError> {
+    Err(LunartickError::Unsupported(
+        "reading kernel clock discipline",
+    ))
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly",
+    target_os = "illumos",
+    target_os = "solaris"
+))]
+pub fn slew(delta: ChronoDuration) -> Result<(), LunartickError> {
+    use libc::{adjtime, suseconds_t, time_t, timeval};
+
+    let micros = delta
+        .num_microseconds()
+        .ok_or_else(|| LunartickError::SetError("slew amount is out of range".to_owned()))?;
+    let tv = timeval {
+        tv_sec: micros.div_euclid(1_000_000) as time_t,
+        tv_usec: micros.rem_euclid(1_000_000) as suseconds_t,
+    };
+    if unsafe { adjtime(&tv, std::ptr::null_mut()) } != 0 {
+        return Err(crate::set_error(std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly",
+    target_os = "illumos",
+    target_os = "solaris"
+)))]
+pub fn slew(_delta: ChronoDuration) -> Result<(), LunartickError> {
+    Err(LunartickError::Unsupported("slewing the system clock"))
+}
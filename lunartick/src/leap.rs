@@ -0,0 +1,152 @@
+use crate::{LunartickError, NTP_TO_UNIX_SECONDS};
+use chrono::{DateTime, Duration as ChronoDuration, NaiveDateTime, TimeZone, Utc};
+use std::{
+    fmt::{self, Display},
+    path::Path,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+const BUNDLED_TABLE: &str = include_str!("../data/leap-seconds.list");
+const GPS_EPOCH_UNIX_SECONDS: i64 = 315_964_800;
+const GPS_TAI_OFFSET_SECONDS: i64 = 19;
+const SECONDS_PER_WEEK: i64 = 604_800;
+
+static CURRENT_TABLE: OnceLock<RwLock<Arc<LeapSecondTable>>> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeapSecondTable {
+    entries: Vec<(i64, i64)>,
+    expires: Option<i64>,
+}
+
+impl LeapSecondTable {
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED_TABLE).expect("bundled leap second table is valid")
+    }
+
+    pub fn parse(list: &str) -> Result<Self, LunartickError> {
+        let mut entries = Vec::new();
+        let mut expires = None;
+        for (i, line) in list.lines().enumerate() {
+            let line = line.trim();
+            if let Some(expiry) = line.strip_prefix("#@") {
+                let ntp: i64 = expiry
+                    .trim()
+                    .parse()
+                    .map_err(|_| LunartickError::LeapSecondTable(i + 1))?;
+                expires = Some(ntp - NTP_TO_UNIX_SECONDS);
+                continue;
+            }
+            let data = line.split('#').next().unwrap_or_default();
+            if data.trim().is_empty() {
+                continue;
+            }
+            let mut fields = data.split_whitespace().map(str::parse::<i64>);
+            match (fields.next(), fields.next()) {
+                (Some(Ok(ntp)), Some(Ok(offset))) => {
+                    entries.push((ntp - NTP_TO_UNIX_SECONDS, offset));
+                }
+                _ => return Err(LunartickError::LeapSecondTable(i + 1)),
+            }
+        }
+        if entries.is_empty() || entries.windows(2).any(|w| w[0].0 >= w[1].0) {
+            return Err(LunartickError::LeapSecondTable(0));
+        }
+        Ok(Self { entries, expires })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, LunartickError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn current() -> Arc<Self> {
+        let table = current_table().read().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(&table)
+    }
+
+    pub fn install(self) {
+        let mut table = current_table().write().unwrap_or_else(|e| e.into_inner());
+        *table = Arc::new(self);
+    }
+
+    pub fn expires(&self) -> Option<DateTime<Utc>> {
+        Utc.timestamp_opt(self.expires?, 0).single()
+    }
+
+    pub fn is_expired_at(&self, unix: i64) -> bool {
+        self.expires.is_some_and(|expires| unix >= expires)
+    }
+
+    pub fn tai_offset(&self, unix: i64) -> i64 {
+        self.entries
+            .iter()
+            .take_while(|(since, _)| *since <= unix)
+            .last()
+            .map_or(0, |(_, offset)| *offset)
+    }
+
+    pub fn unix_to_tai(&self, unix: i64) -> i64 {
+        unix + self.tai_offset(unix)
+    }
+
+    pub fn tai_to_unix(&self, tai: i64) -> i64 {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(since, offset)| since + offset <= tai)
+            .map_or(tai, |(_, offset)| tai - offset)
+    }
+}
+
+impl Default for LeapSecondTable {
+    fn default() -> Self {
+        Self::bundled()
+    }
+}
+
+fn current_table() -> &'static RwLock<Arc<LeapSecondTable>> {
+    CURRENT_TABLE.get_or_init(|| RwLock::new(Arc::new(LeapSecondTable::bundled())))
+}
+
+pub fn unix_to_tai(unix: i64) -> i64 {
+    LeapSecondTable::current().unix_to_tai(unix)
+}
+
+pub fn tai_to_unix(tai: i64) -> i64 {
+    LeapSecondTable::current().tai_to_unix(tai)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpsTime {
+    pub week: i64,
+    pub time_of_week: ChronoDuration,
+}
+
+impl GpsTime {
+    pub(crate) fn from_utc(utc: &DateTime<Utc>, table: &LeapSecondTable) -> Self {
+        let tai = table.unix_to_tai(utc.timestamp());
+        let seconds = tai - GPS_TAI_OFFSET_SECONDS - GPS_EPOCH_UNIX_SECONDS;
+        let time_of_week = ChronoDuration::seconds(seconds.rem_euclid(SECONDS_PER_WEEK))
+            + ChronoDuration::nanoseconds(utc.timestamp_subsec_nanos() as i64);
+        Self {
+            week: seconds.div_euclid(SECONDS_PER_WEEK),
+            time_of_week,
+        }
+    }
+
+    pub fn seconds_since_epoch(&self) -> f64 {
+        let tow = self.time_of_week.num_microseconds().unwrap_or(i64::MAX) as f64 / 1e6;
+        (self.week * SECONDS_PER_WEEK) as f64 + tow
+    }
+}
+
+impl Display for GpsTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tow = self.time_of_week.num_microseconds().unwrap_or(i64::MAX) as f64 / 1e6;
+        write!(f, "week {} + {tow:.6}s", self.week)
+    }
+}
+
+pub(crate) fn utc_to_tai(utc: &DateTime<Utc>, table: &LeapSecondTable) -> NaiveDateTime {
+    utc.naive_utc() + ChronoDuration::seconds(table.tai_offset(utc.timestamp()))
+}
@@ -1,13 +1,71 @@
-use byteorder::{BigEndian, ReadBytesExt};
-use chrono::{DateTime, Duration as ChronoDuration, FixedOffset, Local, TimeZone, Timelike, Utc};
+use chrono::{
+    DateTime, Duration as ChronoDuration, FixedOffset, Local, NaiveDateTime, TimeZone, Timelike,
+    Utc,
+};
+#[cfg(feature = "net")]
+use std::net::{Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
 use std::{
-    collections::HashMap,
+    borrow::Cow,
     fmt::{self, Debug, Display},
-    net::{ToSocketAddrs, UdpSocket},
-    time::Duration,
+    net::{IpAddr, Ipv4Addr},
+    ops::RangeInclusive,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 
+mod backend;
+#[cfg(feature = "net")]
+mod cache;
+pub mod engine;
+mod extension;
+mod human;
+mod hypervisor;
+mod kernel;
+mod leap;
+#[cfg(feature = "net")]
+mod manycast;
+mod parse;
+#[cfg(feature = "net")]
+mod pool;
+#[cfg(feature = "net")]
+mod proxy;
+mod refid;
+#[cfg(feature = "regional-pool")]
+mod region;
+#[cfg(feature = "net")]
+mod resolve;
+mod rtc;
+#[cfg(feature = "net")]
+mod socket;
+mod steady;
+#[cfg(feature = "net")]
+mod tcp;
+mod timestamper;
+mod validate;
+#[cfg(any(windows, test))]
+mod windows_time;
+
+pub use backend::{ClockBackend, ClockWrite, RecordingClock, SystemClock};
+#[cfg(feature = "net")]
+pub use cache::OffsetCache;
+pub use extension::ExtensionField;
+pub use hypervisor::{detect_hypervisor_time_sync, HypervisorTimeSync};
+pub use kernel::{kernel_sync_status, set_frequency, slew, KernelSyncStatus};
+pub use leap::{tai_to_unix, unix_to_tai, GpsTime, LeapSecondTable};
+#[cfg(feature = "net")]
+pub use manycast::{manycast_discover, NTP_MULTICAST_GROUP};
+pub use parse::{parse_response, ParsedPacket};
+#[cfg(feature = "net")]
+pub use proxy::Socks5Proxy;
+pub use refid::ReferenceId;
+pub use rtc::{read_rtc, write_rtc, RtcReading, RtcTimescale, ADJTIME_PATH, DEFAULT_RTC_DEVICE};
+pub use steady::SteadyClock;
+#[cfg(feature = "net")]
+pub use tcp::TcpTransport;
+pub use timestamper::{Timestamp, Timestamper};
+pub use validate::InvalidPacket;
+
 #[derive(Clone, Copy)]
 pub struct Clock {
     time: DateTime<FixedOffset>,
@@ -15,19 +73,54 @@ pub struct Clock {
 
 impl Clock {
     pub fn new(dt: DateTime<Local>) -> Self {
-        let dt = DateTime::<FixedOffset>::from_utc(dt.naive_utc(), *dt.offset());
-        Self { time: dt }
+        Self {
+            time: dt.with_timezone(dt.offset()),
+        }
     }
 
+    #[deprecated(note = "use `Clock::now_with_offset_duration` instead")]
     pub fn now_with_offset(offset: f64) -> Self {
-        let adjusted_dt = Local::now() + ChronoDuration::milliseconds(offset as i64);
-        Self::new(adjusted_dt)
+        Self::now_with_offset_duration(Offset::from_millis_f64(offset).into())
+    }
+
+    pub fn now_with_offset_duration(offset: ChronoDuration) -> Self {
+        Self::new(Local::now() + offset)
     }
 
+    #[cfg(feature = "net")]
     pub fn now_synced() -> Result<Self, LunartickError> {
-        let ntp_client = NTPClient::new();
-        let adjust_ms = ntp_client.test()?.get_time_millis();
-        Ok(Self::now_with_offset(adjust_ms))
+        Self::now_synced_with(&NTPClient::new())
+    }
+
+    #[cfg(feature = "net")]
+    pub fn now_synced_with(ntp_client: &NTPClient) -> Result<Self, LunartickError> {
+        let offset = ntp_client.test()?.get_combined_offset()?;
+        Ok(Self::now_with_offset_duration(offset.into()))
+    }
+
+    // Like now_synced, but reuses an offset measured by an earlier call in the process if it is no
+    // older than `max_age`.
+    #[cfg(feature = "net")]
+    pub fn now_synced_cached(max_age: Duration) -> Result<Self, LunartickError> {
+        Self::now_synced_cached_with(&NTPClient::new(), &cache::PROCESS_CACHE, max_age)
+    }
+
+    #[cfg(feature = "net")]
+    pub fn now_synced_cached_with(
+        ntp_client: &NTPClient,
+        cache: &OffsetCache,
+        max_age: Duration,
+    ) -> Result<Self, LunartickError> {
+        let offset = cache.offset(ntp_client, max_age)?;
+        Ok(Self::now_with_offset_duration(offset.into()))
+    }
+
+    #[cfg(feature = "net")]
+    pub fn now_synced_timeout(budget: Duration) -> Result<Self, LunartickError> {
+        let offset = NTPClient::new()
+            .test_within(budget)?
+            .get_combined_offset()?;
+        Ok(Self::now_with_offset_duration(offset.into()))
     }
 
     pub fn now() -> Self {
@@ -47,14 +140,78 @@ impl Clock {
         Ok(Self { time: dt })
     }
 
-    pub fn to_string(&self) -> String {
-        format!("{} {}", self.time.naive_local(), self.time.offset())
+    pub fn from_format(dt: &str, fmt: &str) -> Result<Self, LunartickError> {
+        if let Ok(dt) = DateTime::parse_from_str(dt, fmt) {
+            return Ok(Self { time: dt });
+        }
+        NaiveDateTime::parse_from_str(dt, fmt)
+            .ok()
+            .and_then(|naive| Local.from_local_datetime(&naive).single())
+            .map(Self::new)
+            .ok_or_else(|| {
+                LunartickError::ParseDateTimeError(DateTimeFormat::Custom(fmt.to_owned()))
+            })
+    }
+
+    pub fn from_human(dt: &str) -> Result<Self, LunartickError> {
+        let dt = human::parse(dt, Utc::now())
+            .ok_or(LunartickError::ParseDateTimeError(DateTimeFormat::Human))?;
+        Ok(Self { time: dt })
+    }
+
+    pub fn from_timestamp(secs: i64, nanos: u32) -> Result<Self, LunartickError> {
+        let dt =
+            Utc.timestamp_opt(secs, nanos)
+                .single()
+                .ok_or(LunartickError::ParseDateTimeError(
+                    DateTimeFormat::Timestamp,
+                ))?;
+        Ok(Self::new(dt.with_timezone(&Local)))
     }
 
     pub fn get_timestamp(&self) -> i64 {
         self.time.timestamp()
     }
 
+    pub fn get_subsec_nanos(&self) -> u32 {
+        self.time.timestamp_subsec_nanos()
+    }
+
+    pub fn to_tai(&self) -> NaiveDateTime {
+        leap::utc_to_tai(&self.time.with_timezone(&Utc), &LeapSecondTable::current())
+    }
+
+    pub fn to_gps_time(&self) -> GpsTime {
+        GpsTime::from_utc(&self.time.with_timezone(&Utc), &LeapSecondTable::current())
+    }
+
+    pub fn to_julian_date(&self) -> f64 {
+        let secs = self.time.timestamp() as f64 + self.time.timestamp_subsec_nanos() as f64 / 1e9;
+        secs / SECONDS_PER_DAY + UNIX_EPOCH_JULIAN_DATE
+    }
+
+    pub fn to_modified_julian_date(&self) -> f64 {
+        self.to_julian_date() - MODIFIED_JULIAN_DATE_OFFSET
+    }
+
+    pub fn get_iso_week_date(&self) -> String {
+        self.time.format("%G-W%V-%u").to_string()
+    }
+
+    pub fn get_ordinal_date(&self) -> String {
+        self.time.format("%Y-%j").to_string()
+    }
+
+    pub fn format(&self, fmt: &str) -> Result<String, LunartickError> {
+        use std::fmt::Write;
+
+        // Formatting with an invalid specifier fails, which to_string would panic on.
+        let mut formatted = String::new();
+        write!(formatted, "{}", self.time.format(fmt))
+            .map_err(|_| LunartickError::InvalidFormat(fmt.to_owned()))?;
+        Ok(formatted)
+    }
+
     pub fn get_rfc2822(&self) -> String {
         self.time.to_rfc2822()
     }
@@ -64,36 +221,74 @@ impl Clock {
     }
 
     #[cfg(windows)]
-    pub fn set(&self) -> Result<(), LunartickError> {
-        use chrono::Datelike;
+    fn set_unverified(&self) -> Result<(), LunartickError> {
+        windows_time::set_system_time(&windows_time::Win32, &self.time).map_err(set_error)
+    }
+
+    // Sets the clock through SetLocalTime, with the fields of the time in the system's time zone,
+    // for software that hooks or audits that call rather than SetSystemTime. Windows converts them
+    // back to UTC with the time zone in effect, so during the hour repeated when daylight saving
+    // ends the result can be an hour off, which the read-back then reports.
+    #[cfg(windows)]
+    pub fn set_local_time(&self) -> Result<(), LunartickError> {
+        self.verified(|| {
+            windows_time::set_local_time(&windows_time::Win32, &self.time, &Local)
+                .map_err(set_error)
+        })
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))]
+    fn set_unverified(&self) -> Result<(), LunartickError> {
+        use libc::{clock_settime, time_t, timespec, CLOCK_REALTIME};
         use std::mem::zeroed;
-        use windows::Win32::{Foundation::SYSTEMTIME, System::SystemInformation::SetSystemTime};
 
         let t = self.time;
-        let mut systime: SYSTEMTIME = unsafe { zeroed() };
-        let dow = t.weekday().num_days_from_sunday();
-        let mut ns = t.nanosecond();
-        let is_leap_second = ns > 1_000_000_000;
-        if is_leap_second {
-            ns -= 1_000_000_000;
-        }
-        systime.wYear = t.year() as u16;
-        systime.wMonth = t.month() as u16;
-        systime.wDayOfWeek = dow as u16;
-        systime.wDay = t.day() as u16;
-        systime.wHour = t.hour() as u16;
-        systime.wMinute = t.minute() as u16;
-        systime.wSecond = t.second() as u16;
-        systime.wMilliseconds = (ns / 1_000_000) as u16;
-        let systime_ptr = &systime as *const SYSTEMTIME;
-        unsafe {
-            SetSystemTime(systime_ptr);
-        }
-        catch_os_error()
-    }
-
-    #[cfg(not(windows))]
-    pub fn set(&self) -> Result<(), LunartickError> {
+        let mut ts: timespec = unsafe { zeroed() };
+        ts.tv_sec = t.timestamp() as time_t;
+        ts.tv_nsec = t.timestamp_subsec_nanos().min(999_999_999) as _;
+        if unsafe { clock_settime(CLOCK_REALTIME, &ts) } == 0 {
+            Ok(())
+        } else {
+            Err(set_error(std::io::Error::last_os_error()))
+        }
+    }
+
+    #[cfg(any(target_os = "illumos", target_os = "solaris"))]
+    fn set_unverified(&self) -> Result<(), LunartickError> {
+        use libc::{settimeofday, suseconds_t, time_t, timeval};
+        use std::mem::zeroed;
+
+        kernel::require_sys_time()?;
+        let t = self.time;
+        let mut u: timeval = unsafe { zeroed() };
+        u.tv_sec = t.timestamp() as time_t;
+        u.tv_usec = t.timestamp_subsec_micros() as suseconds_t;
+        if unsafe { settimeofday(&u, std::ptr::null()) } == 0 {
+            Ok(())
+        } else {
+            Err(set_error(std::io::Error::last_os_error()))
+        }
+    }
+
+    #[cfg(all(
+        unix,
+        not(any(
+            target_os = "linux",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd",
+            target_os = "dragonfly",
+            target_os = "illumos",
+            target_os = "solaris"
+        ))
+    ))]
+    fn set_unverified(&self) -> Result<(), LunartickError> {
         use libc::{settimeofday, suseconds_t, time_t, timeval, timezone};
         use std::mem::zeroed;
 
@@ -101,21 +296,65 @@ impl Clock {
         let mut u: timeval = unsafe { zeroed() };
         u.tv_sec = t.timestamp() as time_t;
         u.tv_usec = t.timestamp_subsec_micros() as suseconds_t;
-        unsafe {
-            let mock_tz: *const timezone = std::ptr::null();
-            settimeofday(&u as *const timeval, mock_tz);
+        let mock_tz: *const timezone = std::ptr::null();
+        if unsafe { settimeofday(&u as *const timeval, mock_tz) } == 0 {
+            Ok(())
+        } else {
+            Err(set_error(std::io::Error::last_os_error()))
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    pub fn set(&self) -> Result<(), LunartickError> {
+        Err(LunartickError::Unsupported("setting the system clock"))
+    }
+
+    // Sets the system clock and reads it back, since some systems accept the change and quietly
+    // ignore it, as a container sharing the host's clock may, or round it further than expected.
+    // The clock must then read the time set plus at most however long setting it took.
+    #[cfg(any(unix, windows))]
+    pub fn set(&self) -> Result<(), LunartickError> {
+        self.verified(|| self.set_unverified())
+    }
+
+    // Sets `backend` instead of the system clock, such as a RecordingClock.
+    pub fn set_with(&self, backend: &dyn ClockBackend) -> Result<(), LunartickError> {
+        backend.set_time(self.time.with_timezone(&Utc))
+    }
+
+    #[cfg(any(unix, windows))]
+    fn verified(
+        &self,
+        set: impl FnOnce() -> Result<(), LunartickError>,
+    ) -> Result<(), LunartickError> {
+        let target = self.time.with_timezone(&Utc);
+        let started = Instant::now();
+        set()?;
+        let elapsed =
+            ChronoDuration::from_std(started.elapsed()).unwrap_or_else(|_| ChronoDuration::zero());
+        let actual = Utc::now();
+        let tolerance =
+            ChronoDuration::from_std(SET_TOLERANCE).unwrap_or_else(|_| ChronoDuration::zero());
+        if actual < target - tolerance || actual > target + elapsed + tolerance {
+            return Err(LunartickError::SetVerificationFailed {
+                difference: Offset::from(actual - target),
+            });
         }
-        catch_os_error()
+        Ok(())
     }
 }
 
-fn catch_os_error() -> Result<(), LunartickError> {
-    let maybe_error = std::io::Error::last_os_error();
-    let os_error_code = &maybe_error.raw_os_error();
-    match os_error_code {
-        Some(0) => Ok(()),
-        Some(_) => Err(LunartickError::SetError(maybe_error.to_string())),
-        None => Ok(()),
+// How far the clock may read before, or after, the time it was set to once the time it took to set
+// is allowed for. Windows keeps milliseconds only.
+#[cfg(any(unix, windows))]
+const SET_TOLERANCE: Duration = Duration::from_millis(5);
+
+#[cfg(any(unix, windows))]
+fn set_error(e: std::io::Error) -> LunartickError {
+    if e.kind() == std::io::ErrorKind::PermissionDenied {
+        LunartickError::ClockSetDenied(e)
+    } else {
+        LunartickError::SetError(e.to_string())
     }
 }
 
@@ -127,13 +366,13 @@ impl From<DateTime<Local>> for Clock {
 
 impl Display for Clock {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.to_string())
+        write!(f, "{} {}", self.time.naive_local(), self.time.offset())
     }
 }
 
 impl Debug for Clock {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.to_string())
+        Display::fmt(self, f)
     }
 }
 
@@ -151,83 +390,404 @@ pub enum LunartickError {
     #[error("{0}")]
     SetError(String),
 
+    #[error("invalid format string {0:?}")]
+    InvalidFormat(String),
+
+    #[error("insufficient privileges to set the system clock")]
+    ClockSetDenied(#[source] std::io::Error),
+
     #[error(transparent)]
     IO(#[from] std::io::Error),
 
-    #[error("error connecting to server")]
-    ConnectionError,
+    #[error("unable to resolve {server}")]
+    DnsFailure { server: Arc<str> },
+
+    #[error("resolving {server} took too long")]
+    DnsTimeout { server: Arc<str> },
+
+    #[error("{server} resolved to {address}, which is not one of its pinned addresses")]
+    AddressMismatch { server: Arc<str>, address: IpAddr },
+
+    #[error(
+        "{server} resolved to {address}, which the socket cannot reach across address families"
+    )]
+    AddressFamily { server: Arc<str>, address: IpAddr },
+
+    #[error("{server} did not respond in time")]
+    Timeout { server: Arc<str> },
+
+    #[error("{server} refused the request with kiss-o'-death code {code}")]
+    KissOfDeath { server: Arc<str>, code: String },
+
+    #[error("malformed response from {server}: {reason}")]
+    MalformedResponse {
+        server: Arc<str>,
+        reason: InvalidPacket,
+    },
 
-    #[error("error parsing timestamp")]
-    ParseTimestampError,
+    #[error("{server} advertised stratum {stratum}, outside the accepted range")]
+    UnacceptableStratum { server: Arc<str>, stratum: u8 },
+
+    #[error("{server} synchronizes to {upstream}, forming a timing loop")]
+    TimingLoop {
+        server: Arc<str>,
+        upstream: Ipv4Addr,
+    },
+
+    #[error("invalid leap second table (line {0})")]
+    LeapSecondTable(usize),
+
+    #[error("no server returned a usable response")]
+    NoUsableResponse,
+
+    #[error("responses came from {networks} distinct networks, at least {required} are required")]
+    InsufficientDiversity { networks: usize, required: usize },
+
+    #[error(
+        "untrusted servers call for a {offset} correction that trusted servers {}",
+        confirmation(.trusted)
+    )]
+    SuspectedAttack {
+        offset: Offset,
+        trusted: Option<Offset>,
+    },
+
+    #[error("the local clock jumped by {jump_ms}ms while querying {server}")]
+    ClockAnomaly { server: Arc<str>, jump_ms: i64 },
+
+    #[error("network error while querying {server}")]
+    Network {
+        server: Arc<str>,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("{0} is not supported on this platform")]
+    Unsupported(&'static str),
+
+    #[error("the clock reads {difference} from the time it was set to, the system refused or rounded the change")]
+    SetVerificationFailed { difference: Offset },
+}
+
+fn confirmation(trusted: &Option<Offset>) -> String {
+    match trusted {
+        Some(trusted) => format!("do not confirm (they measure {trusted})"),
+        None => "did not answer to confirm".to_owned(),
+    }
+}
+
+impl LunartickError {
+    pub fn server(&self) -> Option<&str> {
+        match self {
+            LunartickError::DnsFailure { server }
+            | LunartickError::DnsTimeout { server }
+            | LunartickError::AddressMismatch { server, .. }
+            | LunartickError::AddressFamily { server, .. }
+            | LunartickError::Timeout { server }
+            | LunartickError::KissOfDeath { server, .. }
+            | LunartickError::MalformedResponse { server, .. }
+            | LunartickError::UnacceptableStratum { server, .. }
+            | LunartickError::TimingLoop { server, .. }
+            | LunartickError::ClockAnomaly { server, .. }
+            | LunartickError::Network { server, .. } => Some(server),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum DateTimeFormat {
     RFC2822,
     RFC3339,
+    Timestamp,
+    Human,
+    Custom(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    Dns,
+    // The name did not resolve within the DNS timeout, told apart from a server that is slow to
+    // answer.
+    DnsTimeout,
+    // The name resolved to this address and none that the server is pinned to.
+    AddressMismatch(IpAddr),
+    // The name resolved to this address and none of the family the socket was bound to, such as
+    // an IPv6 address for a socket bound to the IPv4 address of an interface.
+    AddressFamily(IpAddr),
+    Timeout,
+    KissOfDeath([u8; 4]),
+    Malformed(InvalidPacket),
+    Stratum(u8),
+    TimingLoop(Ipv4Addr),
+    Network(std::io::ErrorKind),
+    // The system clock moved by this much more or less than the monotonic clock during the
+    // exchange, so its timestamps do not measure anything.
+    ClockAnomaly(ChronoDuration),
+}
+
+impl Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureKind::Dns => write!(f, "unable to resolve server address"),
+            FailureKind::DnsTimeout => write!(f, "resolving server address took too long"),
+            FailureKind::AddressMismatch(address) => {
+                write!(f, "resolved to {address}, not a pinned address")
+            }
+            FailureKind::AddressFamily(address) => write!(
+                f,
+                "resolved to {address}, which the socket cannot reach across address families"
+            ),
+            FailureKind::Timeout => write!(f, "response took too long"),
+            FailureKind::KissOfDeath(code) => {
+                write!(f, "kiss-o'-death ({})", String::from_utf8_lossy(code))
+            }
+            FailureKind::Malformed(reason) => write!(f, "malformed response: {reason}"),
+            FailureKind::Stratum(stratum) => write!(f, "unacceptable stratum {stratum}"),
+            FailureKind::TimingLoop(upstream) => {
+                write!(f, "timing loop, synchronizes to {upstream}")
+            }
+            FailureKind::Network(kind) => write!(f, "network error: {kind}"),
+            FailureKind::ClockAnomaly(jump) => {
+                write!(f, "local clock jumped by {}ms", jump.num_milliseconds())
+            }
+        }
+    }
+}
+
+impl FailureKind {
+    pub fn into_error(self, server: Arc<str>) -> LunartickError {
+        match self {
+            FailureKind::Dns => LunartickError::DnsFailure { server },
+            FailureKind::DnsTimeout => LunartickError::DnsTimeout { server },
+            FailureKind::AddressMismatch(address) => {
+                LunartickError::AddressMismatch { server, address }
+            }
+            FailureKind::AddressFamily(address) => {
+                LunartickError::AddressFamily { server, address }
+            }
+            FailureKind::Timeout => LunartickError::Timeout { server },
+            FailureKind::KissOfDeath(code) => LunartickError::KissOfDeath {
+                server,
+                code: String::from_utf8_lossy(&code).into_owned(),
+            },
+            FailureKind::Malformed(reason) => LunartickError::MalformedResponse { server, reason },
+            FailureKind::Stratum(stratum) => {
+                LunartickError::UnacceptableStratum { server, stratum }
+            }
+            FailureKind::TimingLoop(upstream) => LunartickError::TimingLoop { server, upstream },
+            FailureKind::Network(kind) => LunartickError::Network {
+                server,
+                source: kind.into(),
+            },
+            FailureKind::ClockAnomaly(jump) => LunartickError::ClockAnomaly {
+                server,
+                jump_ms: jump.num_milliseconds(),
+            },
+        }
+    }
+}
+
+impl From<InvalidPacket> for FailureKind {
+    fn from(reason: InvalidPacket) -> Self {
+        FailureKind::Malformed(reason)
+    }
+}
+
+impl From<std::io::Error> for FailureKind {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => FailureKind::Timeout,
+            kind => FailureKind::Network(kind),
+        }
+    }
+}
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+const UNIX_EPOCH_JULIAN_DATE: f64 = 2_440_587.5;
+const MODIFIED_JULIAN_DATE_OFFSET: f64 = 2_400_000.5;
+
 const NTP_MESSAGE_LENGTH: usize = 48;
+const ORIGIN_TIMESTAMP: usize = 24;
+const RECEIVE_TIMESTAMP: usize = 32;
+const TRANSMIT_TIMESTAMP: usize = 40;
 const NTP_TO_UNIX_SECONDS: i64 = 2_208_988_800;
-const LOCAL_ADDR: &str = "0.0.0.0:12300";
+const NTP_PORT: u16 = 123;
+const MIN_DELAY_MILLIS: f64 = 0.001;
+// RFC 5905 caps the poll exponent at 17, about a day and a half.
+const MAX_POLL: i8 = 17;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_DNS_TIMEOUT: Duration = Duration::from_secs(2);
+// Spacing between the rounds of a burst, as with ntpd's iburst.
+const BURST_INTERVAL: Duration = Duration::from_secs(2);
+// A slewing clock runs off the monotonic clock by at most 500 ppm, well under this over one
+// exchange. More than this and the clock was stepped, or the VM paused, while the request was out.
+const ANOMALY_TOLERANCE: Duration = Duration::from_millis(20);
+#[cfg(feature = "net")]
+const NETWORK_POLL: Duration = Duration::from_millis(500);
 
-#[derive(Debug, Default, Copy, Clone)]
-struct NTPTimestamp {
+/// A timestamp as NTP puts it on the wire: seconds and a binary fraction of a second.
+///
+/// The seconds count from the start of the current era and wrap every 2^32 s, about 136 years.
+/// The wire format carries no era number, so RFC 5905 §6 has the reader pick the era that puts
+/// the timestamp closest to a clock it trusts. `to_datetime` and `from_datetime` take that clock
+/// as a pivot, and the `TryFrom` conversions use the system clock. Times from half an era (about
+/// 68 years) before the pivot up to, but not including, half an era after it are read back in
+/// their own era, and any other time is rejected.
+///
+/// A `DateTime<Utc>` converted to an `NTPTimestamp` and back is the same to the nanosecond, as a
+/// step of the fraction, 2^-32 s, is finer than a nanosecond: the fraction is rounded up on the
+/// way in and down on the way out. Leap seconds are folded into the next second.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NTPTimestamp {
     seconds: u32,
     fraction: u32,
 }
 
+#[derive(Clone)]
 struct NTPMessage {
     data: [u8; NTP_MESSAGE_LENGTH],
+    extensions: Vec<ExtensionField>,
 }
 
 #[derive(Debug, Clone)]
 struct NTPResult {
-    t1: DateTime<Utc>,
-    t2: DateTime<Utc>,
-    t3: DateTime<Utc>,
-    t4: DateTime<Utc>,
+    // The server's receive time less our transmit time, and its transmit time less our receive
+    // time. Offsets are combined far more often than they are measured, and working them out from
+    // the four timestamps each time dominated combining them.
+    outbound: ChronoDuration,
+    inbound: ChronoDuration,
+    reference: ReferenceId,
+    // log2 of the interval in seconds the server asks to be polled at.
+    poll: i8,
+    local: Option<IpAddr>,
+    peer: Option<IpAddr>,
+    ttl: Option<u8>,
+    root_distance: ChronoDuration,
 }
 
 impl NTPResult {
-    fn delay(&self) -> i64 {
-        let duration = (self.t4 - self.t1) - (self.t3 - self.t2);
-        duration.num_milliseconds()
+    fn delay(&self) -> ChronoDuration {
+        self.outbound - self.inbound
     }
 
-    fn offset(&self) -> i64 {
-        let delta = self.delay();
-        delta.abs() / 2
+    fn offset(&self) -> ChronoDuration {
+        (self.outbound + self.inbound) / 2
     }
 }
 
-impl From<NTPTimestamp> for DateTime<Utc> {
-    fn from(ntp: NTPTimestamp) -> Self {
-        let secs = ntp.seconds as i64 - NTP_TO_UNIX_SECONDS;
-        let mut nanos = ntp.fraction as f64;
-        nanos *= 1e9;
-        nanos /= 2_f64.powi(32);
-        Utc.timestamp(secs, nanos as u32)
+fn duration_millis_f64(d: ChronoDuration) -> f64 {
+    match d.num_microseconds() {
+        Some(micros) => micros as f64 / 1000.0,
+        None => d.num_milliseconds() as f64,
     }
 }
 
-impl From<DateTime<Utc>> for NTPTimestamp {
-    fn from(utc: DateTime<Utc>) -> Self {
-        let secs = utc.timestamp() + NTP_TO_UNIX_SECONDS;
-        let mut fraction = utc.nanosecond() as f64;
-        fraction *= 2_f64.powi(32);
-        fraction /= 1e9;
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const ERA_SECONDS: i64 = 1 << 32;
+const HALF_ERA_SECONDS: i64 = ERA_SECONDS / 2;
+
+impl NTPTimestamp {
+    /// A timestamp from its seconds and fraction fields.
+    pub const fn new(seconds: u32, fraction: u32) -> Self {
+        Self { seconds, fraction }
+    }
+
+    /// A timestamp from the 64-bit wire format, seconds in the high half.
+    pub const fn from_u64(bits: u64) -> Self {
         Self {
-            seconds: secs as u32,
+            seconds: (bits >> 32) as u32,
+            fraction: bits as u32,
+        }
+    }
+
+    /// The 64-bit wire format, seconds in the high half.
+    pub const fn as_u64(&self) -> u64 {
+        ((self.seconds as u64) << 32) | self.fraction as u64
+    }
+
+    /// Seconds since the start of the era.
+    pub const fn seconds(&self) -> u32 {
+        self.seconds
+    }
+
+    /// The fraction of a second, in units of 2^-32 s.
+    pub const fn fraction(&self) -> u32 {
+        self.fraction
+    }
+
+    /// Nanoseconds into the second, rounded down.
+    pub const fn subsec_nanos(&self) -> u32 {
+        ((self.fraction as u64 * NANOS_PER_SECOND) >> 32) as u32
+    }
+
+    /// The time this timestamp stands for in the era that puts it closest to `pivot`, at most
+    /// 2^31 s before `pivot` and fewer than 2^31 s after it.
+    ///
+    /// Fails with `InvalidPacket::TimestampOutOfRange` when that time is outside what
+    /// `DateTime<Utc>` can hold.
+    pub fn to_datetime(&self, pivot: DateTime<Utc>) -> Result<DateTime<Utc>, InvalidPacket> {
+        let pivot = pivot.timestamp() + NTP_TO_UNIX_SECONDS;
+        let mut ahead = (i64::from(self.seconds) - pivot).rem_euclid(ERA_SECONDS);
+        if ahead >= HALF_ERA_SECONDS {
+            ahead -= ERA_SECONDS;
+        }
+        Utc.timestamp_opt(pivot + ahead - NTP_TO_UNIX_SECONDS, self.subsec_nanos())
+            .single()
+            .ok_or(InvalidPacket::TimestampOutOfRange)
+    }
+
+    /// The timestamp of `time`, which `to_datetime` with the same pivot turns back into `time`.
+    ///
+    /// Fails with `InvalidPacket::TimestampOutOfRange` when `time` is more than 2^31 s before
+    /// `pivot`, or 2^31 s or more after it, as it would be read back in another era.
+    pub fn from_datetime(time: DateTime<Utc>, pivot: DateTime<Utc>) -> Result<Self, InvalidPacket> {
+        let ahead = time.timestamp() - pivot.timestamp();
+        // A leap second is still the second before the one that follows it.
+        let ahead = ahead + i64::from(time.nanosecond() >= NANOS_PER_SECOND as u32);
+        if !(-HALF_ERA_SECONDS..HALF_ERA_SECONDS).contains(&ahead) {
+            return Err(InvalidPacket::TimestampOutOfRange);
+        }
+        Ok(Self::local(time))
+    }
+
+    /// The timestamp of `time` read by a peer whose clock shows `time`, such as the local clock
+    /// stamping its own packets, which cannot be out of range.
+    pub fn local(time: DateTime<Utc>) -> Self {
+        // chrono counts a leap second as nanoseconds past 1_000_000_000.
+        let nanos = u64::from(time.nanosecond());
+        let secs = time.timestamp() + NTP_TO_UNIX_SECONDS + (nanos / NANOS_PER_SECOND) as i64;
+        let fraction = ((nanos % NANOS_PER_SECOND) << 32).div_ceil(NANOS_PER_SECOND);
+        Self {
+            seconds: secs.rem_euclid(ERA_SECONDS) as u32,
             fraction: fraction as u32,
         }
     }
 }
 
+/// Reads the timestamp in the era closest to the system clock, see `NTPTimestamp::to_datetime`.
+impl TryFrom<NTPTimestamp> for DateTime<Utc> {
+    type Error = InvalidPacket;
+
+    fn try_from(ntp: NTPTimestamp) -> Result<Self, Self::Error> {
+        ntp.to_datetime(Utc::now())
+    }
+}
+
+/// Fails for times more than half an era from the system clock, see `NTPTimestamp::from_datetime`.
+impl TryFrom<DateTime<Utc>> for NTPTimestamp {
+    type Error = InvalidPacket;
+
+    fn try_from(utc: DateTime<Utc>) -> Result<Self, Self::Error> {
+        NTPTimestamp::from_datetime(utc, Utc::now())
+    }
+}
+
 impl NTPMessage {
     fn new() -> Self {
         NTPMessage {
             data: [0; NTP_MESSAGE_LENGTH],
+            extensions: Vec::new(),
         }
     }
 
@@ -240,112 +800,724 @@ impl NTPMessage {
         msg
     }
 
-    fn parse_timestamp(&self, i: usize) -> Result<NTPTimestamp, std::io::Error> {
-        let mut reader = &self.data[i..i + 8];
-        let seconds = reader.read_u32::<BigEndian>()?;
-        let fraction = reader.read_u32::<BigEndian>()?;
-        Ok(NTPTimestamp { seconds, fraction })
+    // Extension fields are only defined for NTPv4.
+    fn with_extensions(mut self, extensions: &[ExtensionField]) -> Self {
+        const VERSION_MASK: u8 = 0b00_111_000;
+        const VERSION_4: u8 = 0b00_100_000;
+        if !extensions.is_empty() {
+            self.data[0] = (self.data[0] & !VERSION_MASK) | VERSION_4;
+        }
+        self.extensions = extensions.to_vec();
+        self
+    }
+
+    // Plain requests, by far the most common, are sent straight from the header without copying.
+    fn encode(&self) -> Result<Cow<'_, [u8]>, FailureKind> {
+        if self.extensions.is_empty() {
+            return Ok(Cow::Borrowed(&self.data));
+        }
+        let mut packet = self.data.to_vec();
+        ExtensionField::encode(&self.extensions, &mut packet)?;
+        Ok(Cow::Owned(packet))
     }
 
-    fn rx_time(&self) -> Result<NTPTimestamp, std::io::Error> {
-        self.parse_timestamp(32)
+    fn read_u32(&self, i: usize) -> u32 {
+        let d = &self.data;
+        u32::from_be_bytes([d[i], d[i + 1], d[i + 2], d[i + 3]])
     }
 
-    fn tx_time(&self) -> Result<NTPTimestamp, std::io::Error> {
-        self.parse_timestamp(40)
+    fn parse_timestamp(&self, i: usize) -> NTPTimestamp {
+        NTPTimestamp {
+            seconds: self.read_u32(i),
+            fraction: self.read_u32(i + 4),
+        }
+    }
+
+    fn leap(&self) -> u8 {
+        self.data[0] >> 6
+    }
+
+    fn version(&self) -> u8 {
+        (self.data[0] >> 3) & 0b111
+    }
+
+    fn mode(&self) -> u8 {
+        self.data[0] & 0b111
+    }
+
+    fn stratum(&self) -> u8 {
+        self.data[1]
+    }
+
+    fn poll(&self) -> i8 {
+        self.data[2] as i8
+    }
+
+    // Half the round trip to the primary reference plus the dispersion accumulated on the way, how
+    // far the server itself may be from true time.
+    fn root_distance(&self) -> ChronoDuration {
+        let short = |i| (i64::from(self.read_u32(i)) * 1_000_000) >> 16;
+        ChronoDuration::microseconds(short(4) / 2 + short(8))
+    }
+
+    fn reference_id(&self) -> [u8; 4] {
+        let mut id = [0; 4];
+        id.copy_from_slice(&self.data[12..16]);
+        id
+    }
+
+    fn origin_timestamp(&self) -> NTPTimestamp {
+        self.parse_timestamp(ORIGIN_TIMESTAMP)
+    }
+
+    fn receive_timestamp(&self) -> NTPTimestamp {
+        self.parse_timestamp(RECEIVE_TIMESTAMP)
+    }
+
+    fn transmit_timestamp(&self) -> NTPTimestamp {
+        self.parse_timestamp(TRANSMIT_TIMESTAMP)
+    }
+
+    fn set_timestamp(&mut self, i: usize, timestamp: NTPTimestamp) {
+        self.data[i..i + 4].copy_from_slice(&timestamp.seconds.to_be_bytes());
+        self.data[i + 4..i + 8].copy_from_slice(&timestamp.fraction.to_be_bytes());
     }
 }
 
-fn weighted_mean(values: &[f64], weights: &[f64]) -> f64 {
-    let (result, sum_of_weights) = values
-        .iter()
-        .zip(weights)
-        .fold((0.0, 0.0), |(result, sum_of_weights), (v, w)| {
-            (result + v * w, sum_of_weights + w)
+pub trait Transport {
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<(), FailureKind> {
+        Ok(())
+    }
+
+    fn connect(&mut self, server: &str, port: u16) -> Result<(), FailureKind>;
+
+    fn send(&mut self, request: &[u8]) -> Result<(), FailureKind>;
+
+    fn recv(&mut self, response: &mut [u8]) -> Result<usize, FailureKind>;
+
+    fn local_addr(&self) -> Option<IpAddr> {
+        None
+    }
+
+    fn peer_addr(&self) -> Option<IpAddr> {
+        None
+    }
+
+    // The IP TTL of the last response received, where the transport can tell.
+    fn received_ttl(&self) -> Option<u8> {
+        None
+    }
+}
+
+#[cfg(feature = "net")]
+#[derive(Debug)]
+pub struct UdpTransport {
+    socket: UdpSocket,
+    ttl: Option<u8>,
+}
+
+#[cfg(feature = "net")]
+impl UdpTransport {
+    // Port 0 leaves the choice to the OS, which picks a random ephemeral port.
+    pub fn bind() -> Result<Self, LunartickError> {
+        Self::bind_port(0)
+    }
+
+    pub fn bind_port(port: u16) -> Result<Self, LunartickError> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+        Self::new(socket)
+    }
+
+    // The same for IPv6 servers, which an IPv4 socket cannot reach.
+    pub fn bind_ipv6_port(port: u16) -> Result<Self, LunartickError> {
+        let socket = UdpSocket::bind((Ipv6Addr::UNSPECIFIED, port))?;
+        Self::new(socket)
+    }
+
+    pub fn bind_interface(interface: &str) -> Result<Self, LunartickError> {
+        Self::bind_interface_port(interface, 0)
+    }
+
+    pub fn bind_interface_port(interface: &str, port: u16) -> Result<Self, LunartickError> {
+        let socket = socket::bind_interface(interface, port)?;
+        Self::new(socket)
+    }
+
+    fn new(socket: UdpSocket) -> Result<Self, LunartickError> {
+        socket.set_read_timeout(Some(DEFAULT_TIMEOUT))?;
+        // The TTL is only informational, so queries go ahead without it.
+        let _ = socket::enable_ttl(&socket);
+        Ok(Self { socket, ttl: None })
+    }
+
+    pub fn set_dscp(&self, dscp: u8) -> Result<(), LunartickError> {
+        socket::set_dscp(&self.socket, dscp)
+    }
+}
+
+#[cfg(feature = "net")]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ServerTransport {
+    #[default]
+    Udp,
+    // NTP over TCP to a relay, directly or through a SOCKS5 proxy.
+    Tcp,
+    Socks5(Socks5Proxy),
+}
+
+#[cfg(feature = "net")]
+impl Transport for UdpTransport {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), FailureKind> {
+        self.socket.set_read_timeout(Some(timeout))?;
+        Ok(())
+    }
+
+    // Like UdpSocket::connect with a name, tries every address of the socket's family in turn until
+    // one can be connected to.
+    fn connect(&mut self, server: &str, port: u16) -> Result<(), FailureKind> {
+        let addrs: Vec<_> = (server, port)
+            .to_socket_addrs()
+            .map_err(|_| FailureKind::Dns)?
+            .collect();
+        let ipv6 = self.socket.local_addr()?.is_ipv6();
+        let mut result = Err(match addrs.first() {
+            Some(addr) => FailureKind::AddressFamily(addr.ip()),
+            None => FailureKind::Dns,
         });
-    result / sum_of_weights
-}
-
-fn ntp_roundtrip<A: ToSocketAddrs>(host: A) -> Result<NTPResult, LunartickError> {
-    let timeout = Duration::from_secs(1);
-    let request = NTPMessage::client();
-    let mut response = NTPMessage::new();
-    let message = request.data;
-    let udp = UdpSocket::bind(LOCAL_ADDR)?;
-    udp.connect(host)
-        .map_err(|_| LunartickError::ConnectionError)?;
-    let t1 = Utc::now();
-    udp.send(&message)?;
-    udp.set_read_timeout(Some(timeout))?;
-    udp.recv_from(&mut response.data)?;
+        for addr in addrs.iter().filter(|addr| addr.is_ipv6() == ipv6) {
+            result = self.socket.connect(addr).map_err(FailureKind::from);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    fn send(&mut self, request: &[u8]) -> Result<(), FailureKind> {
+        self.socket.send(request)?;
+        Ok(())
+    }
+
+    fn recv(&mut self, response: &mut [u8]) -> Result<usize, FailureKind> {
+        let (len, ttl) = socket::recv_with_ttl(&self.socket, response)?;
+        self.ttl = ttl;
+        Ok(len)
+    }
+
+    fn local_addr(&self) -> Option<IpAddr> {
+        self.socket.local_addr().ok().map(|addr| addr.ip())
+    }
+
+    fn peer_addr(&self) -> Option<IpAddr> {
+        self.socket.peer_addr().ok().map(|addr| addr.ip())
+    }
+
+    fn received_ttl(&self) -> Option<u8> {
+        self.ttl
+    }
+}
+
+fn server_address(server: &str) -> (&str, u16) {
+    let split = if let Some(bracketed) = server.strip_prefix('[') {
+        bracketed.split_once("]:")
+    } else {
+        server
+            .split_once(':')
+            .filter(|(host, _)| !host.contains(':'))
+    };
+    match split.and_then(|(host, port)| Some((host, port.parse().ok()?))) {
+        Some(address) => address,
+        None => (
+            server.trim_start_matches('[').trim_end_matches(']'),
+            NTP_PORT,
+        ),
+    }
+}
+
+struct Exchange {
+    response: NTPMessage,
+    sent: NTPTimestamp,
+    t1: DateTime<Utc>,
+    t4: DateTime<Utc>,
+    ttl: Option<u8>,
+}
+
+impl Exchange {
+    fn result<T: Transport + ?Sized>(&self, transport: &T) -> Result<NTPResult, FailureKind> {
+        self.result_between(transport.local_addr(), transport.peer_addr())
+    }
+
+    fn result_between(
+        &self,
+        local: Option<IpAddr>,
+        peer: Option<IpAddr>,
+    ) -> Result<NTPResult, FailureKind> {
+        let t2: DateTime<Utc> = self.response.receive_timestamp().try_into()?;
+        let t3: DateTime<Utc> = self.response.transmit_timestamp().try_into()?;
+        Ok(NTPResult {
+            outbound: t2 - self.t1,
+            inbound: t3 - self.t4,
+            reference: ReferenceId::new(self.response.stratum(), self.response.reference_id()),
+            poll: self.response.poll(),
+            local,
+            peer,
+            ttl: self.ttl,
+            root_distance: self.response.root_distance(),
+        })
+    }
+}
+
+// A request stamped with its transmit time, waiting for the response.
+struct Query {
+    request: NTPMessage,
+    sent: NTPTimestamp,
+    t1: DateTime<Utc>,
+    started: Instant,
+}
+
+impl Query {
+    // Stamped as late as possible, right before the request is sent.
+    fn new(mut request: NTPMessage) -> Self {
+        let t1 = Utc::now();
+        let started = Instant::now();
+        let sent = NTPTimestamp::local(t1);
+        request.set_timestamp(TRANSMIT_TIMESTAMP, sent);
+        Self {
+            request,
+            sent,
+            t1,
+            started,
+        }
+    }
+
+    fn finish(
+        self,
+        packet: &[u8],
+        t4: DateTime<Utc>,
+        ttl: Option<u8>,
+        strata: &RangeInclusive<u8>,
+    ) -> Result<Exchange, FailureKind> {
+        if let Ok(elapsed) = ChronoDuration::from_std(self.started.elapsed()) {
+            let jump = (Utc::now() - self.t1) - elapsed;
+            let jump_us = jump.num_microseconds().unwrap_or(i64::MAX);
+            if u128::from(jump_us.unsigned_abs()) > ANOMALY_TOLERANCE.as_micros() {
+                return Err(FailureKind::ClockAnomaly(jump));
+            }
+        }
+        let response = parse_response(packet)?.message;
+        validate::validate_origin(&self.request, &response)?;
+        if response.stratum() == 0 {
+            return Err(FailureKind::KissOfDeath(response.reference_id()));
+        }
+        if !strata.contains(&response.stratum()) {
+            return Err(FailureKind::Stratum(response.stratum()));
+        }
+        validate::validate_timestamps(&self.request, &response)?;
+        Ok(Exchange {
+            response,
+            sent: self.sent,
+            t1: self.t1,
+            t4,
+            ttl,
+        })
+    }
+}
+
+fn ntp_exchange<T: Transport + ?Sized>(
+    transport: &mut T,
+    request: NTPMessage,
+    strata: &RangeInclusive<u8>,
+) -> Result<Exchange, FailureKind> {
+    let mut packet = [0; parse::MAX_PACKET_LENGTH + 1];
+    let query = Query::new(request);
+    transport.send(&query.request.encode()?)?;
+    let len = transport.recv(&mut packet)?;
     let t4 = Utc::now();
-    let t2: DateTime<Utc> = response
-        .rx_time()
-        .map_err(|_| LunartickError::ParseTimestampError)?
-        .into();
-    let t3: DateTime<Utc> = response
-        .tx_time()
-        .map_err(|_| LunartickError::ParseTimestampError)?
-        .into();
-    Ok(NTPResult { t1, t2, t3, t4 })
+    let ttl = transport.received_ttl();
+    query.finish(&packet[..len], t4, ttl, strata)
+}
+
+fn ntp_roundtrip<T: Transport + ?Sized>(
+    transport: &mut T,
+    server: &str,
+    port: u16,
+    request: &NTPMessage,
+    strata: &RangeInclusive<u8>,
+) -> Result<NTPResult, FailureKind> {
+    transport.connect(server, port)?;
+    ntp_exchange(transport, request.clone(), strata)?.result(transport)
+}
+
+// Interleaved mode: the second request echoes the first response's timestamps, and a server that
+// supports it answers with the transmit timestamp it captured after actually sending the first
+// response. Servers that do not cooperate answer in basic mode, which is used as is.
+fn ntp_interleaved<T: Transport + ?Sized>(
+    transport: &mut T,
+    server: &str,
+    port: u16,
+    request: &NTPMessage,
+    strata: &RangeInclusive<u8>,
+) -> Result<NTPResult, FailureKind> {
+    transport.connect(server, port)?;
+    let first = ntp_exchange(transport, request.clone(), strata)?;
+    let first_received = NTPTimestamp::local(first.t4);
+    let mut request = request.clone();
+    request.set_timestamp(ORIGIN_TIMESTAMP, first.response.receive_timestamp());
+    request.set_timestamp(RECEIVE_TIMESTAMP, first_received);
+    let Ok(second) = ntp_exchange(transport, request, strata) else {
+        return first.result(transport);
+    };
+    let origin = second.response.origin_timestamp();
+    if origin != first_received || origin == second.sent {
+        return second.result(transport);
+    }
+    Ok(NTPResult {
+        inbound: DateTime::<Utc>::try_from(second.response.transmit_timestamp())? - first.t4,
+        ..first.result(transport)?
+    })
+}
+
+// A server whose reference ID is our own address synchronizes to us, and two configured servers
+// pointing at each other only agree with one another. Neither is an independent source.
+fn mark_timing_loops(results: &mut [Result<NTPResult, FailureKind>]) {
+    let is = |addr: Option<IpAddr>, upstream: Ipv4Addr| addr == Some(IpAddr::V4(upstream));
+    let looped: Vec<_> = results
+        .iter()
+        .enumerate()
+        .map(|(i, result)| {
+            let result = result.as_ref().ok()?;
+            let upstream = result.reference.upstream()?;
+            let mutual = results.iter().enumerate().any(|(j, other)| {
+                other.as_ref().is_ok_and(|other| {
+                    j != i
+                        && is(other.peer, upstream)
+                        && other
+                            .reference
+                            .upstream()
+                            .is_some_and(|theirs| is(result.peer, theirs))
+                })
+            });
+            (is(result.local, upstream) || mutual).then_some(upstream)
+        })
+        .collect();
+    for (result, upstream) in results.iter_mut().zip(looped) {
+        if let Some(upstream) = upstream {
+            *result = Err(FailureKind::TimingLoop(upstream));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Offset(ChronoDuration);
+
+impl Offset {
+    pub fn from_millis_f64(millis: f64) -> Self {
+        Self(ChronoDuration::microseconds((millis * 1000.0) as i64))
+    }
+
+    pub fn as_duration(&self) -> ChronoDuration {
+        self.0
+    }
+
+    pub fn as_millis_f64(&self) -> f64 {
+        duration_millis_f64(self.0)
+    }
+
+    // How far from now a human-readable time like "in 30 days" or "2 hours ago" is, as
+    // Clock::from_human reads it.
+    pub fn from_human(input: &str) -> Result<Self, LunartickError> {
+        let now = Utc::now();
+        let time = human::parse(input, now)
+            .ok_or(LunartickError::ParseDateTimeError(DateTimeFormat::Human))?;
+        Ok(Self(time.with_timezone(&Utc) - now))
+    }
+}
+
+impl From<ChronoDuration> for Offset {
+    fn from(d: ChronoDuration) -> Self {
+        Self(d)
+    }
+}
+
+impl From<Offset> for ChronoDuration {
+    fn from(o: Offset) -> Self {
+        o.0
+    }
+}
+
+impl Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}ms", self.as_millis_f64())
+    }
+}
+
+fn sample_offset(ntp_result: &NTPResult) -> Offset {
+    Offset(ntp_result.offset())
 }
 
 #[derive(Debug, Clone)]
 pub struct TestResults {
-    result: HashMap<String, Option<NTPResult>>,
+    servers: Arc<[Arc<str>]>,
+    weights: Arc<[f64]>,
+    trusted: Arc<[bool]>,
+    result: Vec<Result<NTPResult, FailureKind>>,
 }
 
 impl TestResults {
-    pub fn get_all_results(&self) -> HashMap<String, Option<i64>> {
-        self.result
+    pub fn get_server(&self, index: usize) -> Option<&str> {
+        self.servers.get(index).map(AsRef::as_ref)
+    }
+
+    pub fn get_offset(&self, index: usize) -> Option<Result<Offset, FailureKind>> {
+        let ntp_result = self.result.get(index)?;
+        Some(ntp_result.as_ref().map(sample_offset).map_err(|e| *e))
+    }
+
+    pub fn get_reference(&self, index: usize) -> Option<ReferenceId> {
+        Some(self.result.get(index)?.as_ref().ok()?.reference)
+    }
+
+    // The round-trip delay of the exchange, less the time the server spent on it.
+    pub fn get_delay(&self, index: usize) -> Option<ChronoDuration> {
+        Some(self.result.get(index)?.as_ref().ok()?.delay())
+    }
+
+    // Half the server's round trip to its primary reference plus the dispersion it advertises.
+    pub fn get_root_distance(&self, index: usize) -> Option<ChronoDuration> {
+        Some(self.result.get(index)?.as_ref().ok()?.root_distance)
+    }
+
+    // The address the response came from, unknown behind a proxy.
+    pub fn get_peer_address(&self, index: usize) -> Option<IpAddr> {
+        self.result.get(index)?.as_ref().ok()?.peer
+    }
+
+    // The IP TTL the response arrived with, known for UDP queries on Linux and Android.
+    pub fn get_ttl(&self, index: usize) -> Option<u8> {
+        self.result.get(index)?.as_ref().ok()?.ttl
+    }
+
+    // The shortest interval the server wants to be polled at. Zero and negative exponents ask for
+    // nothing slower than once a second, and are not treated as a hint.
+    pub fn get_poll_interval(&self, index: usize) -> Option<Duration> {
+        let poll = self.result.get(index)?.as_ref().ok()?.poll;
+        (poll > 0).then(|| Duration::from_secs(1 << poll.min(MAX_POLL)))
+    }
+
+    // Moves every offset by `by`, so they measure the local clock against true time plus `by`
+    // instead, for clocks that are meant to run that far ahead. Delays are left as measured.
+    pub fn shift(&mut self, by: ChronoDuration) {
+        for result in self.result.iter_mut().flatten() {
+            result.outbound += by;
+            result.inbound += by;
+        }
+    }
+
+    pub fn get_error(&self, index: usize) -> Option<LunartickError> {
+        let failure = *self.result.get(index)?.as_ref().err()?;
+        Some(failure.into_error(Arc::clone(&self.servers[index])))
+    }
+
+    pub fn get_errors(&self) -> impl Iterator<Item = LunartickError> + '_ {
+        (0..self.result.len()).filter_map(|i| self.get_error(i))
+    }
+
+    pub fn get_all_results(
+        &self,
+    ) -> impl Iterator<Item = (&str, Result<Offset, FailureKind>)> + '_ {
+        self.servers
             .iter()
+            .zip(&self.result)
             .map(|(server, ntp_result)| {
-                (server.to_owned(), ntp_result.as_ref().map(|r| r.offset()))
+                let offset = ntp_result.as_ref().map(sample_offset).map_err(|e| *e);
+                (server.as_ref(), offset)
             })
-            .collect()
     }
 
-    pub fn get_time_millis(&self) -> f64 {
-        let mut offsets = Vec::with_capacity(self.result.len());
-        let mut offset_weights = Vec::with_capacity(self.result.len());
+    fn weighted_samples(&self) -> impl Iterator<Item = (usize, f64, f64)> + '_ {
         self.result
             .iter()
-            .filter_map(|r| r.1.as_ref())
-            .filter_map(|time| {
-                let offset = time.offset() as f64;
-                let delay = time.delay() as f64;
-                let weight = 1_000_000.0 / (delay * delay);
+            .enumerate()
+            .filter_map(|(i, time)| Some((i, time.as_ref().ok()?)))
+            .filter_map(|(i, time)| {
+                let offset = duration_millis_f64(time.offset());
+                let delay = duration_millis_f64(time.delay()).max(MIN_DELAY_MILLIS);
+                let weight = self.weights[i] * 1_000_000.0 / (delay * delay);
                 if weight.is_finite() {
-                    Some((offset, weight))
+                    Some((i, offset, weight))
                 } else {
                     None
                 }
             })
-            .for_each(|(offset, weight)| {
-                offsets.push(offset);
-                offset_weights.push(weight);
+    }
+
+    pub fn get_time_millis(&self) -> f64 {
+        let (weighted_sum, sum_of_weights) = self
+            .weighted_samples()
+            .fold((0.0, 0.0), |(result, sum_of_weights), (_, v, w)| {
+                (result + v * w, sum_of_weights + w)
+            });
+        weighted_sum / sum_of_weights
+    }
+
+    // How far the combined offset may be from the true one: the weighted mean of how uncertain each
+    // sample is, half its round trip plus the root distance its server advertises, together with
+    // how much the samples disagree with each other.
+    pub fn get_uncertainty(&self) -> Option<ChronoDuration> {
+        let mean = self.get_time_millis();
+        let (distance, variance, sum_of_weights) = self.weighted_samples().fold(
+            (0.0, 0.0, 0.0),
+            |(distance, variance, sum_of_weights), (i, offset, weight)| {
+                let result = self.result[i].as_ref().ok();
+                let sample = result.map_or(0.0, |result| {
+                    duration_millis_f64(result.delay()) / 2.0
+                        + duration_millis_f64(result.root_distance)
+                });
+                (
+                    distance + sample * weight,
+                    variance + (offset - mean).powi(2) * weight,
+                    sum_of_weights + weight,
+                )
+            },
+        );
+        if sum_of_weights == 0.0 {
+            return None;
+        }
+        let (distance, variance) = (distance / sum_of_weights, variance / sum_of_weights);
+        let millis = (distance * distance + variance).sqrt();
+        Some(ChronoDuration::microseconds((millis * 1000.0) as i64))
+    }
+
+    pub fn has_trusted_servers(&self) -> bool {
+        self.trusted.contains(&true)
+    }
+
+    // The combined offsets of the trusted servers alone and of the others, see
+    // NTPClient::with_server_trust. None when no server of the kind answered.
+    pub fn get_trusted_offset(&self) -> Option<Offset> {
+        self.offset_where(true)
+    }
+
+    pub fn get_untrusted_offset(&self) -> Option<Offset> {
+        self.offset_where(false)
+    }
+
+    fn offset_where(&self, trusted: bool) -> Option<Offset> {
+        let (weighted_sum, sum_of_weights) = self
+            .weighted_samples()
+            .filter(|&(i, _, _)| self.trusted[i] == trusted)
+            .fold((0.0, 0.0), |(result, sum_of_weights), (_, v, w)| {
+                (result + v * w, sum_of_weights + w)
             });
-        let avg_offset = weighted_mean(&offsets, &offset_weights);
-        avg_offset
+        let offset = weighted_sum / sum_of_weights;
+        offset.is_finite().then(|| Offset::from_millis_f64(offset))
+    }
+
+    pub fn get_combined_offset(&self) -> Result<Offset, LunartickError> {
+        let offset = self.get_time_millis();
+        if offset.is_finite() {
+            Ok(Offset::from_millis_f64(offset))
+        } else {
+            Err(self
+                .get_errors()
+                .next()
+                .unwrap_or(LunartickError::NoUsableResponse))
+        }
+    }
+
+    pub fn applied_correction(&self) -> Result<ChronoDuration, LunartickError> {
+        self.get_combined_offset().map(Into::into)
+    }
+
+    // Distinct networks the accepted responses came from: the /24 of an IPv4 peer and the /48 of
+    // an IPv6 one. Several pool names often resolve to the same host, which is no more trustworthy
+    // for being asked twice. Peers that are not known, as behind a proxy, do not count.
+    pub fn get_networks(&self) -> usize {
+        let mut networks: Vec<_> = self
+            .weighted_samples()
+            .filter_map(|(i, _, _)| self.result[i].as_ref().ok()?.peer)
+            .map(|peer| match peer {
+                IpAddr::V4(v4) => IpAddr::V4(Ipv4Addr::from(u32::from(v4) & 0xffff_ff00)),
+                IpAddr::V6(v6) => IpAddr::V6((u128::from(v6) & !0 << 80).into()),
+            })
+            .collect();
+        networks.sort_unstable();
+        networks.dedup();
+        networks.len()
+    }
+
+    pub fn best_server(&self) -> Option<&str> {
+        self.weighted_samples()
+            .max_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+            .map(|(i, _, _)| self.servers[i].as_ref())
+    }
+
+    pub fn rejected_servers(&self) -> impl Iterator<Item = &str> + '_ {
+        let mut accepted = self.weighted_samples().map(|(i, _, _)| i).peekable();
+        self.servers
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, server)| {
+                if accepted.next_if_eq(&i).is_some() {
+                    None
+                } else {
+                    Some(server.as_ref())
+                }
+            })
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct NTPClient {
-    servers: Vec<String>,
+    servers: Arc<[Arc<str>]>,
+    weights: Arc<[f64]>,
+    trusted: Arc<[bool]>,
+    asymmetries: Arc<[ChronoDuration]>,
+    strata: RangeInclusive<u8>,
+    interleaved: bool,
+    burst: u8,
+    extensions: Vec<ExtensionField>,
+    timeout: Duration,
+    dns_timeout: Duration,
+    dscp: Option<u8>,
+    interface: Option<Arc<str>>,
+    source_port: Option<u16>,
+    concurrency: usize,
+    #[cfg(feature = "net")]
+    transports: Arc<[ServerTransport]>,
+    #[cfg(feature = "net")]
+    pins: Arc<[Arc<[IpAddr]>]>,
 }
 
 impl Default for NTPClient {
     fn default() -> Self {
-        let servers = vec![
-            "time.nist.gov".to_owned(),
-            "time.apple.com".to_owned(),
-            "time.euro.apple.com".to_owned(),
-            "time.google.com".to_owned(),
-            "time2.google.com".to_owned(),
-            // "time.windows.com".to_owned(),
-        ];
-        Self { servers }
+        // Products shipping lunartick can build in servers of their own, or the vendor zone the NTP
+        // pool gave them, which it asks them to use instead of the global zones.
+        if let Some(servers) = option_env!("LUNARTICK_DEFAULT_SERVERS") {
+            let servers: Vec<_> = servers
+                .split(',')
+                .map(str::trim)
+                .filter(|server| !server.is_empty())
+                .collect();
+            if !servers.is_empty() {
+                return Self::new_with_multiple_servers(servers);
+            }
+        }
+        if let Some(zone) = option_env!("LUNARTICK_VENDOR_ZONE") {
+            return Self::new_with_pool_zone(zone);
+        }
+        #[cfg(feature = "regional-pool")]
+        if let Some(zone) = region::local_pool_zone() {
+            return Self::new_with_pool_zone(zone);
+        }
+        Self::new_with_multiple_servers([
+            "time.nist.gov",
+            "time.apple.com",
+            "time.euro.apple.com",
+            "time.google.com",
+            "time2.google.com",
+            // "time.windows.com",
+        ])
     }
 }
 
@@ -354,43 +1526,637 @@ impl NTPClient {
         Self::default()
     }
 
-    pub fn new_with_server(server: String) -> Self {
+    pub fn new_with_server<S: Into<Arc<str>>>(server: S) -> Self {
+        Self::new_with_multiple_servers([server])
+    }
+
+    // The four names the NTP pool serves a zone under, 0.<zone>.pool.ntp.org to
+    // 3.<zone>.pool.ntp.org.
+    pub fn new_with_pool_zone(zone: &str) -> Self {
+        Self::new_with_multiple_servers((0..4).map(|i| format!("{i}.{zone}.pool.ntp.org")))
+    }
+
+    pub fn new_with_multiple_servers<I, S>(servers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Arc<str>>,
+    {
+        let servers: Arc<[Arc<str>]> = servers.into_iter().map(Into::into).collect();
         Self {
-            servers: vec![server],
+            weights: servers.iter().map(|_| 1.0).collect(),
+            trusted: servers.iter().map(|_| false).collect(),
+            asymmetries: servers.iter().map(|_| ChronoDuration::zero()).collect(),
+            #[cfg(feature = "net")]
+            transports: servers.iter().map(|_| ServerTransport::Udp).collect(),
+            #[cfg(feature = "net")]
+            pins: servers.iter().map(|_| Arc::from([])).collect(),
+            servers,
+            strata: 1..=u8::MAX,
+            interleaved: false,
+            burst: 1,
+            extensions: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+            dns_timeout: DEFAULT_DNS_TIMEOUT,
+            dscp: None,
+            interface: None,
+            source_port: None,
+            concurrency: 1,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // Names are resolved all at once before querying, and those that take longer than this fail
+    // with FailureKind::DnsTimeout.
+    pub fn with_dns_timeout(mut self, timeout: Duration) -> Self {
+        self.dns_timeout = timeout;
+        self
+    }
+
+    pub fn get_dns_timeout(&self) -> Duration {
+        self.dns_timeout
+    }
+
+    pub fn get_servers(&self) -> &[Arc<str>] {
+        &self.servers
+    }
+
+    // Narrows the client to some of its servers, keeping their weights, asymmetries, transports
+    // and pins.
+    pub fn filter_servers<F: FnMut(&str) -> bool>(&self, mut keep: F) -> Self {
+        let kept: Vec<_> = (0..self.servers.len())
+            .filter(|&i| keep(&self.servers[i]))
+            .collect();
+        Self {
+            servers: kept.iter().map(|&i| Arc::clone(&self.servers[i])).collect(),
+            weights: kept.iter().map(|&i| self.weights[i]).collect(),
+            trusted: kept.iter().map(|&i| self.trusted[i]).collect(),
+            asymmetries: kept.iter().map(|&i| self.asymmetries[i]).collect(),
+            #[cfg(feature = "net")]
+            transports: kept.iter().map(|&i| self.transports[i].clone()).collect(),
+            #[cfg(feature = "net")]
+            pins: kept.iter().map(|&i| Arc::clone(&self.pins[i])).collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_server_weight(mut self, server: &str, weight: f64) -> Self {
+        self.weights = self
+            .servers
+            .iter()
+            .zip(self.weights.iter())
+            .map(|(name, &current)| {
+                if name.as_ref() == server {
+                    weight
+                } else {
+                    current
+                }
+            })
+            .collect();
+        self
+    }
+
+    pub fn get_server_weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    // Trusted servers vouch for the others, see SyncEngine::with_attack_threshold. They are the
+    // ones an attacker on the path cannot forge or delay unnoticed, such as a local proxy that
+    // authenticates its upstreams with NTS or a reference clock on the local network.
+    pub fn with_server_trust(mut self, server: &str, trusted: bool) -> Self {
+        self.trusted = self
+            .servers
+            .iter()
+            .zip(self.trusted.iter())
+            .map(|(name, &current)| {
+                if name.as_ref() == server {
+                    trusted
+                } else {
+                    current
+                }
+            })
+            .collect();
+        self
+    }
+
+    pub fn get_server_trust(&self) -> &[bool] {
+        &self.trusted
+    }
+
+    // How much longer the path to the server takes than the path back, such as over a satellite
+    // link or a DOCSIS uplink, negative when the way back is the slower one. Offsets assume both
+    // ways take as long and are off by half the difference otherwise, which is taken out of the
+    // server's samples.
+    pub fn with_server_asymmetry(mut self, server: &str, asymmetry: ChronoDuration) -> Self {
+        self.asymmetries = self
+            .servers
+            .iter()
+            .zip(self.asymmetries.iter())
+            .map(|(name, &current)| {
+                if name.as_ref() == server {
+                    asymmetry
+                } else {
+                    current
+                }
+            })
+            .collect();
+        self
+    }
+
+    pub fn get_server_asymmetries(&self) -> &[ChronoDuration] {
+        &self.asymmetries
+    }
+
+    fn compensate_asymmetry(&self, results: &mut [Result<NTPResult, FailureKind>]) {
+        for (result, &asymmetry) in results.iter_mut().zip(self.asymmetries.iter()) {
+            if let Ok(result) = result {
+                // Shifting both halves of the exchange moves the offset and keeps the delay.
+                let half = asymmetry / 2;
+                result.outbound -= half;
+                result.inbound -= half;
+            }
+        }
+    }
+
+    #[cfg(feature = "net")]
+    pub fn with_server_transport(mut self, server: &str, transport: ServerTransport) -> Self {
+        self.transports = self
+            .servers
+            .iter()
+            .zip(self.transports.iter())
+            .map(|(name, current)| {
+                if name.as_ref() == server {
+                    transport.clone()
+                } else {
+                    current.clone()
+                }
+            })
+            .collect();
+        self
+    }
+
+    #[cfg(feature = "net")]
+    pub fn get_server_transports(&self) -> &[ServerTransport] {
+        &self.transports
+    }
+
+    // Only these addresses are queried for the server, and its name resolving to none of them
+    // fails with FailureKind::AddressMismatch. Servers reached through a SOCKS5 proxy are resolved
+    // by the proxy and cannot be pinned.
+    #[cfg(feature = "net")]
+    pub fn with_server_pins(mut self, server: &str, pins: &[IpAddr]) -> Self {
+        self.pins = self
+            .servers
+            .iter()
+            .zip(self.pins.iter())
+            .map(|(name, current)| {
+                if name.as_ref() == server {
+                    Arc::from(pins)
+                } else {
+                    Arc::clone(current)
+                }
+            })
+            .collect();
+        self
+    }
+
+    #[cfg(feature = "net")]
+    pub fn get_server_pins(&self) -> &[Arc<[IpAddr]>] {
+        &self.pins
+    }
+
+    pub fn with_stratum_range(mut self, strata: RangeInclusive<u8>) -> Self {
+        self.strata = strata;
+        self
+    }
+
+    pub fn get_stratum_range(&self) -> &RangeInclusive<u8> {
+        &self.strata
+    }
+
+    pub fn with_interleaved(mut self, interleaved: bool) -> Self {
+        self.interleaved = interleaved;
+        self
+    }
+
+    pub fn get_interleaved(&self) -> bool {
+        self.interleaved
+    }
+
+    // Each server is queried this many times per test and its sample with the lowest delay, the one
+    // least disturbed by queuing, is kept.
+    pub fn with_burst(mut self, burst: u8) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    pub fn get_burst(&self) -> u8 {
+        self.burst
+    }
+
+    // Attached to every request, which are then sent as NTPv4.
+    pub fn with_extension_field(mut self, field: ExtensionField) -> Self {
+        self.extensions.push(field);
+        self
+    }
+
+    pub fn get_extension_fields(&self) -> &[ExtensionField] {
+        &self.extensions
+    }
+
+    fn query<T: Transport + ?Sized>(
+        &self,
+        transport: &mut T,
+        server: &str,
+    ) -> Result<NTPResult, FailureKind> {
+        let (host, port) = server_address(server);
+        self.query_address(transport, host, port)
+    }
+
+    fn query_address<T: Transport + ?Sized>(
+        &self,
+        transport: &mut T,
+        host: &str,
+        port: u16,
+    ) -> Result<NTPResult, FailureKind> {
+        let request = NTPMessage::client().with_extensions(&self.extensions);
+        if self.interleaved {
+            ntp_interleaved(transport, host, port, &request, &self.strata)
+        } else {
+            ntp_roundtrip(transport, host, port, &request, &self.strata)
         }
     }
 
-    pub fn new_with_multiple_servers(servers: Vec<String>) -> Self {
-        Self { servers }
+    pub fn with_dscp(mut self, dscp: u8) -> Self {
+        self.dscp = Some(dscp);
+        self
     }
 
-    pub fn get_servers(&self) -> Vec<String> {
-        self.servers.clone()
+    pub fn get_dscp(&self) -> Option<u8> {
+        self.dscp
     }
 
+    pub fn with_interface<S: Into<Arc<str>>>(mut self, interface: S) -> Self {
+        self.interface = Some(interface.into());
+        self
+    }
+
+    pub fn get_interface(&self) -> Option<&str> {
+        self.interface.as_deref()
+    }
+
+    // Without a pinned port every query is sent from a fresh random ephemeral port, which an
+    // off-path attacker spoofing responses has to guess.
+    pub fn with_source_port(mut self, port: u16) -> Self {
+        self.source_port = Some(port);
+        self
+    }
+
+    pub fn get_source_port(&self) -> Option<u16> {
+        self.source_port
+    }
+
+    // Above 1, UDP servers are queried from one shared socket with up to this many requests in
+    // flight, so testing hundreds of servers takes about as long as the slowest of them rather than
+    // all of them together. The shared socket keeps one port for the whole test. Servers reached
+    // over TCP or queried in interleaved mode still go one at a time.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn get_concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    // A socket bound to an interface is bound to its IPv4 address, and cannot reach IPv6 servers.
+    #[cfg(feature = "net")]
+    fn udp_transport(&self, ipv6: bool) -> Result<UdpTransport, LunartickError> {
+        let port = self.source_port.unwrap_or(0);
+        let udp = match &self.interface {
+            Some(interface) => UdpTransport::bind_interface_port(interface, port)?,
+            None if ipv6 => UdpTransport::bind_ipv6_port(port)?,
+            None => UdpTransport::bind_port(port)?,
+        };
+        if let Some(dscp) = self.dscp {
+            udp.set_dscp(dscp)?;
+        }
+        Ok(udp)
+    }
+
+    // UDP sockets are bound to the family of the server's address, IPv4 for names left to resolve.
+    #[cfg(feature = "net")]
+    fn transport(
+        &self,
+        index: usize,
+        address: Option<SocketAddr>,
+    ) -> Result<Box<dyn Transport>, LunartickError> {
+        Ok(match &self.transports[index] {
+            ServerTransport::Udp => {
+                Box::new(self.udp_transport(address.is_some_and(|addr| addr.is_ipv6()))?)
+            }
+            ServerTransport::Tcp => Box::new(TcpTransport::new()),
+            ServerTransport::Socks5(proxy) => Box::new(TcpTransport::with_proxy(proxy.clone())),
+        })
+    }
+
+    // Early in boot names may not resolve and interfaces may have no address yet. Returns true as
+    // soon as one of the servers resolves and has a route, false if none does within `timeout`.
+    #[cfg(feature = "net")]
+    pub fn wait_for_network(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let reachable = self.servers.iter().any(|server| {
+                let Ok(addrs) = server_address(server).to_socket_addrs() else {
+                    return false;
+                };
+                addrs.into_iter().any(|addr| {
+                    let unspecified: std::net::SocketAddr = if addr.is_ipv4() {
+                        (std::net::Ipv4Addr::UNSPECIFIED, 0).into()
+                    } else {
+                        (std::net::Ipv6Addr::UNSPECIFIED, 0).into()
+                    };
+                    UdpSocket::bind(unspecified).is_ok_and(|socket| socket.connect(addr).is_ok())
+                })
+            });
+            if reachable {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(NETWORK_POLL);
+        }
+    }
+
+    #[cfg(feature = "net")]
     pub fn test(&self) -> Result<TestResults, LunartickError> {
-        const NTP_PORT: u16 = 123;
-        let mut times = Vec::with_capacity(self.servers.len());
-        for server in &self.servers {
-            let destination = format!("{}:{}", server, NTP_PORT);
-            let calc = ntp_roundtrip(destination);
-            match calc {
-                Err(e)
-                    if matches!(
-                        e,
-                        LunartickError::ConnectionError | LunartickError::ParseTimestampError
-                    ) =>
-                {
-                    return Err(e);
+        self.test_net(None)
+    }
+
+    #[cfg(feature = "net")]
+    pub fn test_within(&self, budget: Duration) -> Result<TestResults, LunartickError> {
+        self.test_net(Some(Instant::now() + budget))
+    }
+
+    fn sample_servers<E>(
+        &self,
+        deadline: Option<Instant>,
+        mut sample: impl FnMut(usize, &str) -> Result<Result<NTPResult, FailureKind>, E>,
+    ) -> Result<Vec<Result<NTPResult, FailureKind>>, E> {
+        self.sample_rounds(deadline, |servers| {
+            servers
+                .iter()
+                .map(|&i| sample(i, &self.servers[i]))
+                .collect()
+        })
+    }
+
+    // Rounds of a burst query every server in turn, so servers are not held up by each other's
+    // spacing. A server that answers with a kiss-o'-death is left alone for the rest of the burst.
+    // Each round is given the servers to query and returns their results in the same order.
+    fn sample_rounds<E>(
+        &self,
+        deadline: Option<Instant>,
+        mut round: impl FnMut(&[usize]) -> Result<Vec<Result<NTPResult, FailureKind>>, E>,
+    ) -> Result<Vec<Result<NTPResult, FailureKind>>, E> {
+        let mut best: Vec<Option<Result<NTPResult, FailureKind>>> =
+            self.servers.iter().map(|_| None).collect();
+        for n in 0..self.burst {
+            if n > 0 {
+                // The samples so far are kept rather than starting a round that cannot finish.
+                if deadline.is_some_and(|deadline| Instant::now() + BURST_INTERVAL >= deadline) {
+                    break;
                 }
-                _ => times.push(calc.ok()),
+                std::thread::sleep(BURST_INTERVAL);
+            }
+            let servers: Vec<_> = (0..self.servers.len())
+                .filter(|&i| !matches!(best[i], Some(Err(FailureKind::KissOfDeath(_)))))
+                .collect();
+            for (i, result) in servers.iter().copied().zip(round(&servers)?) {
+                best[i] = Some(match (best[i].take(), result) {
+                    (Some(Ok(best)), Ok(result)) if best.delay() <= result.delay() => Ok(best),
+                    (Some(Ok(best)), Err(_)) => Ok(best),
+                    (_, result) => result,
+                });
             }
         }
-        let result = times
+        Ok(best
             .into_iter()
-            .zip(&self.servers)
-            .map(|(score, server)| (server.to_owned(), score))
+            .map(|result| result.unwrap_or(Err(FailureKind::Timeout)))
+            .collect())
+    }
+
+    // Addresses of the servers not reached through a proxy, which resolves names itself.
+    #[cfg(feature = "net")]
+    fn resolve(
+        &self,
+        deadline: Option<Instant>,
+    ) -> Vec<Option<Result<Vec<SocketAddr>, FailureKind>>> {
+        let direct: Vec<_> = (0..self.servers.len())
+            .filter(|&i| !matches!(self.transports[i], ServerTransport::Socks5(_)))
+            .collect();
+        let names: Vec<_> = direct
+            .iter()
+            .map(|&i| server_address(&self.servers[i]))
             .collect();
-        Ok(TestResults { result })
+        let timeout = match deadline {
+            Some(deadline) => self
+                .dns_timeout
+                .min(deadline.saturating_duration_since(Instant::now())),
+            None => self.dns_timeout,
+        };
+        let mut addresses = vec![None; self.servers.len()];
+        for (i, resolved) in direct
+            .into_iter()
+            .zip(resolve::resolve_all(&names, timeout))
+        {
+            addresses[i] = Some(resolved.and_then(|addrs| resolve::select(addrs, &self.pins[i])));
+        }
+        addresses
+    }
+
+    #[cfg(feature = "net")]
+    fn sample_one(
+        &self,
+        i: usize,
+        addresses: Option<&Result<Vec<SocketAddr>, FailureKind>>,
+        deadline: Option<Instant>,
+    ) -> Result<Result<NTPResult, FailureKind>, LunartickError> {
+        match addresses {
+            Some(Err(failure)) => Ok(Err(*failure)),
+            Some(Ok(addresses)) => self.sample_addresses(i, addresses, deadline),
+            None => self.sample_address(i, None, deadline),
+        }
+    }
+
+    // Like connecting to a name, falls back to the next address while the server cannot be reached
+    // at all, but not when it does not answer in time, so a server takes at most one timeout.
+    #[cfg(feature = "net")]
+    fn sample_addresses(
+        &self,
+        i: usize,
+        addresses: &[SocketAddr],
+        deadline: Option<Instant>,
+    ) -> Result<Result<NTPResult, FailureKind>, LunartickError> {
+        let mut result = Err(FailureKind::Dns);
+        for &address in addresses {
+            result = self.sample_address(i, Some(address), deadline)?;
+            if !matches!(
+                result,
+                Err(FailureKind::Network(_) | FailureKind::AddressFamily(_))
+            ) {
+                break;
+            }
+        }
+        Ok(result)
     }
+
+    #[cfg(feature = "net")]
+    fn sample_address(
+        &self,
+        i: usize,
+        address: Option<SocketAddr>,
+        deadline: Option<Instant>,
+    ) -> Result<Result<NTPResult, FailureKind>, LunartickError> {
+        let timeout = match deadline {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+            None => self.timeout,
+        };
+        if timeout.is_zero() {
+            return Ok(Err(FailureKind::Timeout));
+        }
+        let mut transport = match self.transport(i, address) {
+            Ok(transport) => transport,
+            // A host without IPv6 cannot even create the socket, which only rules out this address.
+            Err(LunartickError::IO(e)) if address.is_some_and(|addr| addr.is_ipv6()) => {
+                return Ok(Err(e.into()));
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(transport
+            .set_timeout(timeout.min(self.timeout))
+            .and_then(|()| match address {
+                Some(address) => self.query_address(
+                    transport.as_mut(),
+                    &address.ip().to_string(),
+                    address.port(),
+                ),
+                None => self.query(transport.as_mut(), &self.servers[i]),
+            }))
+    }
+
+    // Each round sends to the UDP servers all at once over an IPv4 socket shared for the whole
+    // test, then queries the rest one at a time. Servers whose first address is IPv6 are among the
+    // rest, as are those the shared socket could not reach, which go on to their other addresses.
+    #[cfg(feature = "net")]
+    fn sample_pooled(
+        &self,
+        addresses: &[Option<Result<Vec<SocketAddr>, FailureKind>>],
+        deadline: Option<Instant>,
+    ) -> Result<Vec<Result<NTPResult, FailureKind>>, LunartickError> {
+        let request = NTPMessage::client().with_extensions(&self.extensions);
+        let mut shared = None;
+        self.sample_rounds(deadline, |servers| {
+            let (pooled, targets): (Vec<_>, Vec<_>) = servers
+                .iter()
+                .filter_map(|&i| match (&self.transports[i], &addresses[i]) {
+                    (ServerTransport::Udp, Some(Ok(addresses))) if addresses[0].is_ipv4() => {
+                        Some((i, addresses[0]))
+                    }
+                    _ => None,
+                })
+                .unzip();
+            let mut answered = vec![None; self.servers.len()];
+            if !pooled.is_empty() {
+                let udp = match &mut shared {
+                    Some(udp) => udp,
+                    slot @ None => slot.insert(self.udp_transport(false)?),
+                };
+                let results = pool::query_all(
+                    &udp.socket,
+                    &targets,
+                    &request,
+                    &self.strata,
+                    self.timeout,
+                    deadline,
+                    self.concurrency,
+                );
+                for (i, result) in pooled.into_iter().zip(results) {
+                    answered[i] = Some(result);
+                }
+            }
+            servers
+                .iter()
+                .map(|&i| match (answered[i].take(), &addresses[i]) {
+                    (Some(Err(FailureKind::Network(_))), Some(Ok(addresses))) => {
+                        self.sample_addresses(i, &addresses[1..], deadline)
+                    }
+                    (Some(result), _) => Ok(result),
+                    (None, addresses) => self.sample_one(i, addresses.as_ref(), deadline),
+                })
+                .collect()
+        })
+    }
+
+    #[cfg(feature = "net")]
+    fn test_net(&self, deadline: Option<Instant>) -> Result<TestResults, LunartickError> {
+        let addresses = self.resolve(deadline);
+        let mut result = if self.concurrency > 1 && !self.interleaved {
+            self.sample_pooled(&addresses, deadline)?
+        } else {
+            self.sample_servers(deadline, |i, _| {
+                self.sample_one(i, addresses[i].as_ref(), deadline)
+            })?
+        };
+        self.compensate_asymmetry(&mut result);
+        mark_timing_loops(&mut result);
+        Ok(TestResults {
+            servers: Arc::clone(&self.servers),
+            weights: Arc::clone(&self.weights),
+            trusted: Arc::clone(&self.trusted),
+            result,
+        })
+    }
+
+    pub fn test_with<T: Transport + ?Sized>(&self, transport: &mut T) -> TestResults {
+        let Ok(mut result) = self.sample_servers(None, |_, server| {
+            Ok::<_, std::convert::Infallible>(
+                transport
+                    .set_timeout(self.timeout)
+                    .and_then(|()| self.query(transport, server)),
+            )
+        });
+        self.compensate_asymmetry(&mut result);
+        mark_timing_loops(&mut result);
+        TestResults {
+            servers: Arc::clone(&self.servers),
+            weights: Arc::clone(&self.weights),
+            trusted: Arc::clone(&self.trusted),
+            result,
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+pub fn sntp_time(server: &str) -> Result<DateTime<Utc>, LunartickError> {
+    let results = NTPClient::new_with_server(server).test()?;
+    let offset = results
+        .get_offset(0)
+        .ok_or(LunartickError::NoUsableResponse)?
+        .map_err(|kind| kind.into_error(server.into()))?;
+    Ok(Utc::now() + offset.as_duration())
+}
+
+#[cfg(feature = "net")]
+pub fn network_offset() -> Result<ChronoDuration, LunartickError> {
+    let offset = NTPClient::new().test()?.get_combined_offset()?;
+    Ok(offset.into())
 }
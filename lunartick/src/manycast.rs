@@ -0,0 +1,56 @@
+use crate::{
+    parse::{self, MAX_PACKET_LENGTH},
+    validate, LunartickError, NTPMessage, NTPTimestamp, NTP_PORT, TRANSMIT_TIMESTAMP,
+};
+use chrono::Utc;
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+// The group IANA assigned to NTP.
+pub const NTP_MULTICAST_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 1, 1);
+// Manycast is meant to find servers on the local segment, so requests are not routed further.
+const MANYCAST_TTL: u32 = 1;
+
+// Sends one client request to a multicast group and collects the addresses of the servers that
+// answer with a usable unicast reply within `wait`, in the order they answered.
+pub fn manycast_discover(group: Ipv4Addr, wait: Duration) -> Result<Vec<IpAddr>, LunartickError> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_multicast_ttl_v4(MANYCAST_TTL)?;
+    let mut request = NTPMessage::client();
+    request.set_timestamp(TRANSMIT_TIMESTAMP, NTPTimestamp::local(Utc::now()));
+    socket.send_to(&request.data, (group, NTP_PORT))?;
+    let deadline = Instant::now() + wait;
+    let mut responders = Vec::new();
+    let mut packet = [0; MAX_PACKET_LENGTH + 1];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let (len, from) = match socket.recv_from(&mut packet) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                break
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // Kiss-o'-death, unsynchronized and unsolicited replies do not make a server worth adding.
+        let usable = parse::parse_response(&packet[..len]).is_ok_and(|response| {
+            response.stratum() != 0
+                && validate::validate_timestamps(&request, &response.message).is_ok()
+        });
+        if usable && !responders.contains(&from.ip()) {
+            responders.push(from.ip());
+        }
+    }
+    Ok(responders)
+}
@@ -0,0 +1,69 @@
+use crate::{validate, ExtensionField, InvalidPacket, NTPMessage, ReferenceId, NTP_MESSAGE_LENGTH};
+use chrono::{DateTime, Utc};
+
+// Generously above anything a server sends in reply to the requests we make, extension fields
+// included. The receive buffer is one byte larger so oversized datagrams are noticed.
+pub(crate) const MAX_PACKET_LENGTH: usize = 1024;
+
+#[derive(Clone)]
+pub struct ParsedPacket {
+    pub(crate) message: NTPMessage,
+}
+
+// Parses a server response without touching the network or the clock, so arbitrary bytes can be
+// thrown at it. Whether the packet answers a particular request is checked separately.
+pub fn parse_response(data: &[u8]) -> Result<ParsedPacket, InvalidPacket> {
+    if data.len() < NTP_MESSAGE_LENGTH {
+        return Err(InvalidPacket::Length(data.len()));
+    }
+    if data.len() > MAX_PACKET_LENGTH {
+        return Err(InvalidPacket::Oversized);
+    }
+    let mut message = NTPMessage::new();
+    message.data.copy_from_slice(&data[..NTP_MESSAGE_LENGTH]);
+    validate::validate_header(&message)?;
+    message.extensions = ExtensionField::decode(&data[NTP_MESSAGE_LENGTH..])?;
+    Ok(ParsedPacket { message })
+}
+
+impl ParsedPacket {
+    pub fn leap(&self) -> u8 {
+        self.message.leap()
+    }
+
+    pub fn version(&self) -> u8 {
+        self.message.version()
+    }
+
+    pub fn mode(&self) -> u8 {
+        self.message.mode()
+    }
+
+    pub fn stratum(&self) -> u8 {
+        self.message.stratum()
+    }
+
+    pub fn poll(&self) -> i8 {
+        self.message.poll()
+    }
+
+    pub fn reference(&self) -> ReferenceId {
+        ReferenceId::new(self.message.stratum(), self.message.reference_id())
+    }
+
+    pub fn origin_time(&self) -> Result<DateTime<Utc>, InvalidPacket> {
+        self.message.origin_timestamp().try_into()
+    }
+
+    pub fn receive_time(&self) -> Result<DateTime<Utc>, InvalidPacket> {
+        self.message.receive_timestamp().try_into()
+    }
+
+    pub fn transmit_time(&self) -> Result<DateTime<Utc>, InvalidPacket> {
+        self.message.transmit_timestamp().try_into()
+    }
+
+    pub fn extension_fields(&self) -> &[ExtensionField] {
+        &self.message.extensions
+    }
+}
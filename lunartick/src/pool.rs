@@ -0,0 +1,120 @@
+use crate::{
+    parse, socket, FailureKind, NTPMessage, NTPResult, NTPTimestamp, Query, ORIGIN_TIMESTAMP,
+    TRANSMIT_TIMESTAMP,
+};
+use chrono::Utc;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    net::{SocketAddr, UdpSocket},
+    ops::RangeInclusive,
+    time::{Duration, Instant},
+};
+
+// What the kernel sets aside for each queued response, bookkeeping included, with room to spare.
+const BUFFER_PER_RESPONSE: usize = 2048;
+
+struct InFlight {
+    index: usize,
+    query: Query,
+    expires: Instant,
+}
+
+// Queries every target from one socket with up to `concurrency` requests in flight, sending the
+// next as soon as one is answered or times out. Responses are matched to their request by the
+// origin timestamp, which echoes the transmit timestamp of the request, and must come from the
+// address the request went to. Anything else arriving on the socket is dropped.
+pub(crate) fn query_all(
+    socket: &UdpSocket,
+    targets: &[SocketAddr],
+    request: &NTPMessage,
+    strata: &RangeInclusive<u8>,
+    timeout: Duration,
+    deadline: Option<Instant>,
+    concurrency: usize,
+) -> Vec<Result<NTPResult, FailureKind>> {
+    // Responses arriving faster than they are read are only lost, so the buffer size is a
+    // best effort.
+    let _ = socket::reserve_recv_buffer(socket, concurrency.saturating_mul(BUFFER_PER_RESPONSE));
+    let local = socket
+        .local_addr()
+        .ok()
+        .map(|addr| addr.ip())
+        .filter(|ip| !ip.is_unspecified());
+    let mut results: Vec<_> = targets.iter().map(|_| Err(FailureKind::Timeout)).collect();
+    let mut in_flight = HashMap::with_capacity(concurrency.min(targets.len()));
+    let mut packet = [0; parse::MAX_PACKET_LENGTH + 1];
+    let mut next = 0;
+    loop {
+        let now = Instant::now();
+        in_flight.retain(|_, flight: &mut InFlight| flight.expires > now);
+        while in_flight.len() < concurrency && next < targets.len() {
+            let index = next;
+            next += 1;
+            let expires = match deadline {
+                Some(deadline) => deadline.min(Instant::now() + timeout),
+                None => Instant::now() + timeout,
+            };
+            if expires <= Instant::now() {
+                continue;
+            }
+            let mut query = Query::new(request.clone());
+            // Two requests stamped within the same nanosecond would be indistinguishable.
+            while in_flight.contains_key(&query.sent) {
+                query.sent = NTPTimestamp::from_u64(query.sent.as_u64().wrapping_add(1));
+                query.request.set_timestamp(TRANSMIT_TIMESTAMP, query.sent);
+            }
+            let sent = query
+                .request
+                .encode()
+                .and_then(|packet| Ok(socket.send_to(&packet, targets[index])?));
+            match sent {
+                Ok(_) => {
+                    in_flight.insert(
+                        query.sent,
+                        InFlight {
+                            index,
+                            query,
+                            expires,
+                        },
+                    );
+                }
+                Err(failure) => results[index] = Err(failure),
+            }
+        }
+        let Some(expires) = in_flight.values().map(|flight| flight.expires).min() else {
+            break;
+        };
+        let wait = expires.saturating_duration_since(Instant::now());
+        if wait.is_zero() || socket.set_read_timeout(Some(wait)).is_err() {
+            continue;
+        }
+        let (len, from, ttl) = match socket::recv_from_with_ttl(socket, &mut packet) {
+            Ok(received) => received,
+            Err(e) => match FailureKind::from(e) {
+                FailureKind::Timeout => continue,
+                failure => {
+                    for (_, flight) in in_flight.drain() {
+                        results[flight.index] = Err(failure);
+                    }
+                    continue;
+                }
+            },
+        };
+        let t4 = Utc::now();
+        if len < ORIGIN_TIMESTAMP + 8 {
+            continue;
+        }
+        let mut origin = [0; 8];
+        origin.copy_from_slice(&packet[ORIGIN_TIMESTAMP..ORIGIN_TIMESTAMP + 8]);
+        let origin = NTPTimestamp::from_u64(u64::from_be_bytes(origin));
+        let flight = match in_flight.entry(origin) {
+            Entry::Occupied(flight) if targets[flight.get().index] == from => flight.remove(),
+            _ => continue,
+        };
+        results[flight.index] = flight
+            .query
+            .finish(&packet[..len], t4, ttl, strata)
+            .and_then(|exchange| exchange.result_between(local, Some(from.ip())));
+    }
+    results
+}
@@ -0,0 +1,181 @@
+use crate::LunartickError;
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    str::FromStr,
+    time::Duration,
+};
+
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const USERNAME_PASSWORD: u8 = 2;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CONNECT: u8 = 1;
+const DOMAIN_NAME: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Socks5Proxy {
+    host: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+}
+
+impl Socks5Proxy {
+    pub fn new<S: Into<String>>(host: S, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            credentials: None,
+        }
+    }
+
+    pub fn with_credentials<U: Into<String>, P: Into<String>>(
+        mut self,
+        username: U,
+        password: P,
+    ) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    pub fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        timeout: Duration,
+    ) -> Result<TcpStream, LunartickError> {
+        let mut stream = self.connect_proxy(timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        self.negotiate(&mut stream)?;
+        request_connect(&mut stream, host, port)?;
+        Ok(stream)
+    }
+
+    // Tries every address of the proxy in turn, each within `timeout`, so an unreachable proxy
+    // takes no longer than a server that does not answer.
+    fn connect_proxy(&self, timeout: Duration) -> io::Result<TcpStream> {
+        let mut result = Err(proxy_error("proxy name did not resolve"));
+        for addr in (self.host.as_str(), self.port).to_socket_addrs()? {
+            result = TcpStream::connect_timeout(&addr, timeout);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    fn negotiate(&self, stream: &mut TcpStream) -> io::Result<()> {
+        match &self.credentials {
+            Some(_) => stream.write_all(&[SOCKS_VERSION, 2, NO_AUTH, USERNAME_PASSWORD])?,
+            None => stream.write_all(&[SOCKS_VERSION, 1, NO_AUTH])?,
+        }
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply)?;
+        if reply[0] != SOCKS_VERSION {
+            return Err(proxy_error("proxy is not a SOCKS5 server"));
+        }
+        match (reply[1], &self.credentials) {
+            (NO_AUTH, _) => Ok(()),
+            (USERNAME_PASSWORD, Some((username, password))) => {
+                let mut request = vec![1];
+                push_string(&mut request, username)?;
+                push_string(&mut request, password)?;
+                stream.write_all(&request)?;
+                let mut reply = [0; 2];
+                stream.read_exact(&mut reply)?;
+                if reply[1] != 0 {
+                    return Err(proxy_error("proxy rejected the credentials"));
+                }
+                Ok(())
+            }
+            (NO_ACCEPTABLE_METHODS, _) | (USERNAME_PASSWORD, None) => {
+                Err(proxy_error("proxy requires authentication"))
+            }
+            _ => Err(proxy_error(
+                "proxy chose an unsupported authentication method",
+            )),
+        }
+    }
+}
+
+impl FromStr for Socks5Proxy {
+    type Err = LunartickError;
+
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        let invalid = || proxy_error(format!("invalid proxy address '{url}'").as_str());
+        let rest = url
+            .strip_prefix("socks5h://")
+            .or_else(|| url.strip_prefix("socks5://"))
+            .unwrap_or(url);
+        let (credentials, address) = match rest.rsplit_once('@') {
+            Some((credentials, address)) => (Some(credentials), address),
+            None => (None, rest),
+        };
+        let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(invalid().into());
+        }
+        let port = port.parse().map_err(|_| invalid())?;
+        let proxy = Self::new(host, port);
+        Ok(match credentials {
+            Some(credentials) => {
+                let (username, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+                proxy.with_credentials(username, password)
+            }
+            None => proxy,
+        })
+    }
+}
+
+fn request_connect(stream: &mut TcpStream, host: &str, port: u16) -> io::Result<()> {
+    let mut request = vec![SOCKS_VERSION, CONNECT, 0, DOMAIN_NAME];
+    push_string(&mut request, host)?;
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(proxy_error(reply_message(reply[1])));
+    }
+    let address_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        DOMAIN_NAME => {
+            let mut len = [0; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        _ => return Err(proxy_error("proxy sent an unknown address type")),
+    };
+    let mut bound = vec![0; address_len + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(())
+}
+
+fn push_string(buf: &mut Vec<u8>, value: &str) -> io::Result<()> {
+    let len = u8::try_from(value.len())
+        .map_err(|_| proxy_error("value is too long for a SOCKS5 request"))?;
+    buf.push(len);
+    buf.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+fn reply_message(code: u8) -> &'static str {
+    match code {
+        1 => "proxy reported a general failure",
+        2 => "proxy ruleset does not allow the connection",
+        3 => "proxy reported the network is unreachable",
+        4 => "proxy reported the host is unreachable",
+        5 => "proxy reported the connection was refused",
+        6 => "proxy reported a TTL expiry",
+        7 => "proxy does not support the CONNECT command",
+        8 => "proxy does not support the address type",
+        _ => "proxy returned an unknown error",
+    }
+}
+
+fn proxy_error(message: &str) -> io::Error {
+    io::Error::other(message.to_owned())
+}
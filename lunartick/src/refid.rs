@@ -0,0 +1,90 @@
+use std::{
+    fmt::{self, Display},
+    net::Ipv4Addr,
+};
+
+// Reference clock codes from RFC 5905 and the IANA registry, plus a few widely deployed ones.
+const KNOWN_CODES: &[(&str, &str)] = &[
+    ("GOES", "Geosynchronous Orbit Environment Satellite"),
+    ("GPS", "Global Positioning System"),
+    ("GAL", "Galileo Positioning System"),
+    ("GLO", "GLONASS"),
+    ("BDS", "BeiDou Navigation Satellite System"),
+    ("GNSS", "Global Navigation Satellite System"),
+    ("PPS", "pulse per second"),
+    ("IRIG", "Inter-Range Instrumentation Group"),
+    ("WWVB", "LF radio WWVB, Fort Collins"),
+    ("DCF", "LF radio DCF77, Mainflingen"),
+    ("HBG", "LF radio HBG, Prangins"),
+    ("MSF", "LF radio MSF, Anthorn"),
+    ("JJY", "LF radio JJY, Japan"),
+    ("LORC", "MF radio LORAN C"),
+    ("TDF", "MF radio Allouis"),
+    ("CHU", "HF radio CHU, Ottawa"),
+    ("WWV", "HF radio WWV, Fort Collins"),
+    ("WWVH", "HF radio WWVH, Kauai"),
+    ("NIST", "NIST telephone modem"),
+    ("ACTS", "NIST telephone modem"),
+    ("USNO", "USNO telephone modem"),
+    ("PTB", "PTB telephone modem"),
+    ("PTP", "Precision Time Protocol"),
+    ("ATOM", "atomic clock"),
+    ("LOCL", "local clock"),
+    ("XFAC", "interface association changed"),
+    ("STEP", "clock stepped"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReferenceId {
+    stratum: u8,
+    id: [u8; 4],
+}
+
+impl ReferenceId {
+    pub(crate) fn new(stratum: u8, id: [u8; 4]) -> Self {
+        Self { stratum, id }
+    }
+
+    pub fn stratum(&self) -> u8 {
+        self.stratum
+    }
+
+    pub fn as_bytes(&self) -> [u8; 4] {
+        self.id
+    }
+
+    // Stratum 0 and 1 carry a four-character code, padded with NULs.
+    pub fn code(&self) -> Option<&str> {
+        if self.stratum > 1 {
+            return None;
+        }
+        let end = self.id.iter().position(|&b| b == 0).unwrap_or(4);
+        let code = std::str::from_utf8(&self.id[..end]).ok()?;
+        (!code.is_empty() && code.bytes().all(|b| b.is_ascii_graphic())).then_some(code)
+    }
+
+    pub fn description(&self) -> Option<&'static str> {
+        let code = self.code()?;
+        KNOWN_CODES
+            .iter()
+            .find(|(known, _)| *known == code)
+            .map(|(_, description)| *description)
+    }
+
+    // Above stratum 1 the ID is the upstream server's IPv4 address. For IPv6 upstreams it is the
+    // first four bytes of an MD5 hash of the address, which cannot be told apart from an IPv4 one.
+    pub fn upstream(&self) -> Option<Ipv4Addr> {
+        (self.stratum > 1 && self.id != [0; 4]).then(|| Ipv4Addr::from(self.id))
+    }
+}
+
+impl Display for ReferenceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.code(), self.description(), self.upstream()) {
+            (Some(code), Some(description), _) => write!(f, "{code} ({description})"),
+            (Some(code), None, _) => f.write_str(code),
+            (None, _, Some(upstream)) => write!(f, "upstream {upstream}"),
+            (None, _, None) => write!(f, "unknown ({:02x?})", self.id),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// Guesses which continental NTP pool zone is nearest from the IANA time zone the machine is set
+// to, which says where it is without asking anything over the network.
+pub(crate) fn local_pool_zone() -> Option<&'static str> {
+    pool_zone(&local_time_zone()?)
+}
+
+fn local_time_zone() -> Option<String> {
+    if let Ok(tz) = std::env::var("TZ") {
+        let tz = tz.trim_start_matches(':');
+        return Some(zoneinfo_name(tz).unwrap_or(tz).to_owned());
+    }
+    #[cfg(unix)]
+    {
+        if let Ok(target) = std::fs::read_link("/etc/localtime") {
+            if let Some(name) = zoneinfo_name(&target.to_string_lossy()) {
+                return Some(name.to_owned());
+            }
+        }
+        if let Ok(contents) = std::fs::read_to_string("/etc/timezone") {
+            return contents.lines().next().map(|line| line.trim().to_owned());
+        }
+    }
+    None
+}
+
+// "/usr/share/zoneinfo/Europe/Berlin" is "Europe/Berlin".
+fn zoneinfo_name(path: &str) -> Option<&str> {
+    path.split_once("zoneinfo/").map(|(_, name)| name)
+}
+
+const SOUTH_AMERICA: &[&str] = &[
+    "Araguaina",
+    "Argentina/",
+    "Asuncion",
+    "Bahia",
+    "Belem",
+    "Boa_Vista",
+    "Bogota",
+    "Campo_Grande",
+    "Caracas",
+    "Cayenne",
+    "Cuiaba",
+    "Eirunepe",
+    "Fortaleza",
+    "Guayaquil",
+    "Guyana",
+    "La_Paz",
+    "Lima",
+    "Maceio",
+    "Manaus",
+    "Montevideo",
+    "Noronha",
+    "Paramaribo",
+    "Porto_Velho",
+    "Punta_Arenas",
+    "Recife",
+    "Rio_Branco",
+    "Santarem",
+    "Santiago",
+    "Sao_Paulo",
+];
+
+// Atlantic islands that are part of Europe, the rest are too remote to have a better zone than
+// the global one.
+const EUROPEAN_ATLANTIC: &[&str] = &["Azores", "Canary", "Faroe", "Madeira", "Reykjavik"];
+
+fn pool_zone(tz: &str) -> Option<&'static str> {
+    let (area, location) = tz.split_once('/')?;
+    Some(match area {
+        "Europe" => "europe",
+        "Africa" => "africa",
+        "Asia" => "asia",
+        "Australia" => "oceania",
+        "Pacific" if location == "Honolulu" => "north-america",
+        "Pacific" => "oceania",
+        "America" if SOUTH_AMERICA.iter().any(|city| location.starts_with(city)) => "south-america",
+        "America" | "US" | "Canada" => "north-america",
+        "Brazil" | "Chile" => "south-america",
+        "Atlantic" if EUROPEAN_ATLANTIC.contains(&location) => "europe",
+        _ => return None,
+    })
+}
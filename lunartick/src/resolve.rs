@@ -0,0 +1,103 @@
+use crate::FailureKind;
+use std::{
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+// Resolves every name at once, each on a thread of its own, so one slow lookup neither holds up
+// the others nor outlasts the timeout. A lookup cannot be cancelled, so one that times out is left
+// to finish in the background. Every address a name resolves to is returned, in the resolver's
+// order.
+pub(crate) fn resolve_all(
+    names: &[(&str, u16)],
+    timeout: Duration,
+) -> Vec<Result<Vec<SocketAddr>, FailureKind>> {
+    let deadline = Instant::now() + timeout;
+    let mut results: Vec<_> = names.iter().map(|_| Err(FailureKind::DnsTimeout)).collect();
+    let (tx, rx) = mpsc::channel();
+    let mut pending = 0;
+    for (i, &(host, port)) in names.iter().enumerate() {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            results[i] = Ok(vec![SocketAddr::new(ip, port)]);
+            continue;
+        }
+        let tx = tx.clone();
+        let host = host.to_owned();
+        let spawned = std::thread::Builder::new().spawn(move || {
+            let addrs = (host.as_str(), port)
+                .to_socket_addrs()
+                .map(Iterator::collect::<Vec<_>>)
+                .unwrap_or_default();
+            // The receiver has gone if the lookup took too long.
+            let _ = tx.send((i, addrs));
+        });
+        match spawned {
+            Ok(_) => pending += 1,
+            Err(_) => results[i] = Err(FailureKind::Dns),
+        }
+    }
+    for _ in 0..pending {
+        let Ok((i, addrs)) = rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        else {
+            break;
+        };
+        results[i] = if addrs.is_empty() {
+            Err(FailureKind::Dns)
+        } else {
+            Ok(addrs)
+        };
+    }
+    results
+}
+
+// The addresses to try in turn, in the resolver's order. A server pinned to some addresses is only
+// queried at those it resolves to, and must resolve to one of them: anything else suggests the
+// answer was tampered with and fails with the first address it did resolve to.
+pub(crate) fn select(
+    addrs: Vec<SocketAddr>,
+    pins: &[IpAddr],
+) -> Result<Vec<SocketAddr>, FailureKind> {
+    if pins.is_empty() {
+        return Ok(addrs);
+    }
+    let pinned: Vec<_> = addrs
+        .iter()
+        .filter(|addr| pins.contains(&addr.ip()))
+        .copied()
+        .collect();
+    if pinned.is_empty() {
+        Err(FailureKind::AddressMismatch(addrs[0].ip()))
+    } else {
+        Ok(pinned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(list: &[&str]) -> Vec<SocketAddr> {
+        list.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn every_address_is_kept_in_order() {
+        let resolved = addrs(&["[2001:db8::1]:123", "192.0.2.1:123", "[2001:db8::2]:123"]);
+        assert_eq!(select(resolved.clone(), &[]), Ok(resolved));
+    }
+
+    #[test]
+    fn pins_keep_only_the_pinned_addresses() {
+        let resolved = addrs(&["[2001:db8::1]:123", "192.0.2.1:123", "192.0.2.2:123"]);
+        let pins = ["192.0.2.2".parse().unwrap(), "2001:db8::1".parse().unwrap()];
+        assert_eq!(
+            select(resolved, &pins),
+            Ok(addrs(&["[2001:db8::1]:123", "192.0.2.2:123"]))
+        );
+        assert_eq!(
+            select(addrs(&["192.0.2.3:123"]), &pins),
+            Err(FailureKind::AddressMismatch("192.0.2.3".parse().unwrap()))
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use crate::LunartickError;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use std::path::Path;
+
+pub const DEFAULT_RTC_DEVICE: &str = "/dev/rtc0";
+pub const ADJTIME_PATH: &str = "/etc/adjtime";
+
+// Whether the RTC is kept in UTC or, as Windows does by default, in local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcTimescale {
+    Utc,
+    Local,
+}
+
+impl RtcTimescale {
+    // The third line of the file hwclock and timedatectl maintain says UTC or LOCAL. Without the
+    // file hwclock assumes UTC, and so does this.
+    pub fn from_adjtime(path: &Path) -> Result<Self, LunartickError> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Ok(match contents.lines().nth(2).map(str::trim) {
+                Some("LOCAL") => RtcTimescale::Local,
+                _ => RtcTimescale::Utc,
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RtcTimescale::Utc),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // A local time that falls in the gap of a daylight saving change has no UTC equivalent, and one
+    // that repeats is taken as the earlier.
+    pub fn to_utc(self, rtc: NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self {
+            RtcTimescale::Utc => Some(Utc.from_utc_datetime(&rtc)),
+            RtcTimescale::Local => Local
+                .from_local_datetime(&rtc)
+                .earliest()
+                .map(|local| local.with_timezone(&Utc)),
+        }
+    }
+
+    pub fn from_utc(self, time: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            RtcTimescale::Utc => time.naive_utc(),
+            RtcTimescale::Local => time.with_timezone(&Local).naive_local(),
+        }
+    }
+}
+
+// The hardware clock's broken-down time, read on the tick of its second, and the system time at
+// that moment. The RTC does not say whether it is kept in UTC or local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtcReading {
+    pub rtc: NaiveDateTime,
+    pub system: DateTime<Utc>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[repr(C)]
+#[derive(Default)]
+struct RtcTime {
+    tm_sec: libc::c_int,
+    tm_min: libc::c_int,
+    tm_hour: libc::c_int,
+    tm_mday: libc::c_int,
+    tm_mon: libc::c_int,
+    tm_year: libc::c_int,
+    tm_wday: libc::c_int,
+    tm_yday: libc::c_int,
+    tm_isdst: libc::c_int,
+}
+
+// The direction bits of ioctl numbers sit differently on a few architectures.
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    any(
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "sparc64"
+    )
+))]
+const IOC_READ: libc::c_ulong = 2 << 29;
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    any(
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "sparc64"
+    )
+))]
+const IOC_WRITE: libc::c_ulong = 4 << 29;
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    not(any(
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "sparc64"
+    ))
+))]
+const IOC_READ: libc::c_ulong = 2 << 30;
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    not(any(
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "sparc64"
+    ))
+))]
+const IOC_WRITE: libc::c_ulong = 1 << 30;
+// _IOR('p', 0x09, struct rtc_time)
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const RTC_RD_TIME: libc::c_ulong = IOC_READ
+    | ((std::mem::size_of::<RtcTime>() as libc::c_ulong) << 16)
+    | ((b'p' as libc::c_ulong) << 8)
+    | 0x09;
+// _IOW('p', 0x0a, struct rtc_time)
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const RTC_SET_TIME: libc::c_ulong = IOC_WRITE
+    | ((std::mem::size_of::<RtcTime>() as libc::c_ulong) << 16)
+    | ((b'p' as libc::c_ulong) << 8)
+    | 0x0a;
+
+// The RTC only counts whole seconds, so it is polled this often until its second changes.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const POLL: std::time::Duration = std::time::Duration::from_millis(1);
+// Somewhat over a second, so a clock that does not tick at all is noticed.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const EDGE_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(1500);
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn read_time(rtc: &std::fs::File) -> std::io::Result<RtcTime> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut time = RtcTime::default();
+    if unsafe { libc::ioctl(rtc.as_raw_fd(), RTC_RD_TIME as _, &mut time) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(time)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn to_naive(time: &RtcTime) -> Option<NaiveDateTime> {
+    chrono::NaiveDate::from_ymd_opt(
+        time.tm_year + 1900,
+        u32::try_from(time.tm_mon + 1).ok()?,
+        u32::try_from(time.tm_mday).ok()?,
+    )?
+    .and_hms_opt(
+        u32::try_from(time.tm_hour).ok()?,
+        u32::try_from(time.tm_min).ok()?,
+        u32::try_from(time.tm_sec).ok()?,
+    )
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn read_rtc(device: &Path) -> Result<RtcReading, LunartickError> {
+    use std::{io, time::Instant};
+
+    let rtc = std::fs::File::open(device)?;
+    let first = read_time(&rtc)?.tm_sec;
+    let deadline = Instant::now() + EDGE_TIMEOUT;
+    loop {
+        let time = read_time(&rtc)?;
+        let system = Utc::now();
+        if time.tm_sec != first {
+            let rtc = to_naive(&time).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the hardware clock holds an invalid time, its battery may be dead",
+                )
+            })?;
+            return Ok(RtcReading { rtc, system });
+        }
+        if Instant::now() > deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the hardware clock is not ticking, its battery may be dead",
+            )
+            .into());
+        }
+        std::thread::sleep(POLL);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn from_naive(time: NaiveDateTime) -> RtcTime {
+    use chrono::{Datelike, Timelike};
+
+    RtcTime {
+        tm_sec: time.second() as libc::c_int,
+        tm_min: time.minute() as libc::c_int,
+        tm_hour: time.hour() as libc::c_int,
+        tm_mday: time.day() as libc::c_int,
+        tm_mon: time.month0() as libc::c_int,
+        tm_year: time.year() - 1900,
+        tm_wday: time.weekday().num_days_from_sunday() as libc::c_int,
+        tm_yday: time.ordinal0() as libc::c_int,
+        tm_isdst: 0,
+    }
+}
+
+// Sets the RTC to the system clock. The RTC only holds whole seconds, so this waits for the system
+// clock to reach the next one and returns it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn write_rtc(device: &Path, timescale: RtcTimescale) -> Result<DateTime<Utc>, LunartickError> {
+    use chrono::Timelike;
+    use std::os::unix::io::AsRawFd;
+
+    let rtc = std::fs::File::open(device)?;
+    let now = Utc::now();
+    let second = now.with_nanosecond(0).unwrap_or(now) + chrono::Duration::seconds(1);
+    if let Ok(wait) = (second - now).to_std() {
+        std::thread::sleep(wait);
+    }
+    let time = from_naive(timescale.from_utc(second));
+    if unsafe { libc::ioctl(rtc.as_raw_fd(), RTC_SET_TIME as _, &time) } == -1 {
+        return Err(crate::set_error(std::io::Error::last_os_error()));
+    }
+    Ok(second)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn write_rtc(
+    _device: &Path,
+    _timescale: RtcTimescale,
+) -> Result<DateTime<Utc>, LunartickError> {
+    Err(LunartickError::Unsupported("setting the hardware clock"))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn read_rtc(_device: &Path) -> Result<RtcReading, LunartickError> {
+    Err(LunartickError::Unsupported("reading the hardware clock"))
+}
@@ -0,0 +1,312 @@
+use crate::LunartickError;
+#[cfg(unix)]
+use std::net::Ipv4Addr;
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+const MAX_DSCP: u8 = 63;
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+const IP_TOS: libc::c_int = 3;
+#[cfg(all(unix, not(any(target_os = "illumos", target_os = "solaris"))))]
+use libc::IP_TOS;
+
+#[cfg(unix)]
+fn setsockopt<T: ?Sized>(
+    socket: &UdpSocket,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: &T,
+    len: usize,
+) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let res = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            value as *const T as *const libc::c_void,
+            len as libc::socklen_t,
+        )
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub(crate) fn set_dscp(socket: &UdpSocket, dscp: u8) -> Result<(), LunartickError> {
+    if dscp > MAX_DSCP {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("DSCP value {dscp} is out of range (0-{MAX_DSCP})"),
+        )
+        .into());
+    }
+    #[cfg(unix)]
+    {
+        let tos = libc::c_int::from(dscp << 2);
+        setsockopt(
+            socket,
+            libc::IPPROTO_IP,
+            IP_TOS,
+            &tos,
+            std::mem::size_of::<libc::c_int>(),
+        )?;
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = socket;
+        Err(LunartickError::Unsupported("setting DSCP on NTP packets"))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn bind_interface(interface: &str, port: u16) -> Result<UdpSocket, LunartickError> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+    setsockopt(
+        &socket,
+        libc::SOL_SOCKET,
+        libc::SO_BINDTODEVICE,
+        interface.as_bytes(),
+        interface.len(),
+    )
+    .map_err(|e| io::Error::new(e.kind(), format!("unable to bind to {interface}: {e}")))?;
+    Ok(socket)
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly",
+    target_os = "illumos",
+    target_os = "solaris"
+))]
+pub(crate) fn bind_interface(interface: &str, port: u16) -> Result<UdpSocket, LunartickError> {
+    let address = interface_address(interface)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("interface {interface} has no IPv4 address"),
+        )
+    })?;
+    Ok(UdpSocket::bind((address, port))?)
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly",
+    target_os = "illumos",
+    target_os = "solaris"
+))]
+fn interface_address(interface: &str) -> io::Result<Option<Ipv4Addr>> {
+    use std::ffi::CStr;
+
+    let mut addrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut found = None;
+    let mut cursor = addrs;
+    while let Some(ifaddr) = unsafe { cursor.as_ref() } {
+        cursor = ifaddr.ifa_next;
+        let name = unsafe { CStr::from_ptr(ifaddr.ifa_name) };
+        let addr = unsafe { ifaddr.ifa_addr.as_ref() };
+        match addr {
+            Some(addr)
+                if name.to_bytes() == interface.as_bytes()
+                    && libc::c_int::from(addr.sa_family) == libc::AF_INET =>
+            {
+                let addr = unsafe { &*(addr as *const libc::sockaddr as *const libc::sockaddr_in) };
+                found = Some(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)));
+                break;
+            }
+            _ => (),
+        }
+    }
+    unsafe { libc::freeifaddrs(addrs) };
+    Ok(found)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly",
+    target_os = "illumos",
+    target_os = "solaris"
+)))]
+pub(crate) fn bind_interface(_interface: &str, _port: u16) -> Result<UdpSocket, LunartickError> {
+    Err(LunartickError::Unsupported(
+        "binding NTP queries to an interface",
+    ))
+}
+
+// Makes room for at least `bytes` of datagrams waiting to be read, never shrinking the buffer. Many
+// servers answering at once overflow the default, and the kernel drops what does not fit. It also
+// caps the size at its own configured maximum.
+#[cfg(unix)]
+pub(crate) fn reserve_recv_buffer(socket: &UdpSocket, bytes: usize) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut current: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUF,
+            (&mut current as *mut libc::c_int).cast(),
+            &mut len,
+        )
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let wanted = libc::c_int::try_from(bytes).unwrap_or(libc::c_int::MAX);
+    if current >= wanted {
+        return Ok(());
+    }
+    setsockopt(
+        socket,
+        libc::SOL_SOCKET,
+        libc::SO_RCVBUF,
+        &wanted,
+        std::mem::size_of::<libc::c_int>(),
+    )
+}
+
+#[cfg(not(unix))]
+pub(crate) fn reserve_recv_buffer(_socket: &UdpSocket, _bytes: usize) -> io::Result<()> {
+    Ok(())
+}
+
+// Asks for the TTL of each received packet to be delivered with it. The initial TTL is usually 64,
+// 128 or 255, so what is left hints at how many hops away the sender is.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn enable_ttl(socket: &UdpSocket) -> io::Result<()> {
+    let enable: libc::c_int = 1;
+    setsockopt(
+        socket,
+        libc::IPPROTO_IP,
+        libc::IP_RECVTTL,
+        &enable,
+        std::mem::size_of::<libc::c_int>(),
+    )
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn enable_ttl(_socket: &UdpSocket) -> io::Result<()> {
+    Ok(())
+}
+
+// Receives a datagram along with its TTL, if the kernel passed one along.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn recv_with_ttl(socket: &UdpSocket, buf: &mut [u8]) -> io::Result<(usize, Option<u8>)> {
+    recvmsg(socket, buf, std::ptr::null_mut())
+}
+
+// The same for a socket that is not connected, which also needs to know who sent the datagram.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn recv_from_with_ttl(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, Option<u8>)> {
+    use std::net::{Ipv6Addr, SocketAddrV6};
+
+    let mut from: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let (len, ttl) = recvmsg(socket, buf, &mut from)?;
+    let addr = match libc::c_int::from(from.ss_family) {
+        libc::AF_INET => {
+            let v4 = unsafe { &*(&from as *const _ as *const libc::sockaddr_in) };
+            SocketAddr::new(
+                Ipv4Addr::from(u32::from_be(v4.sin_addr.s_addr)).into(),
+                u16::from_be(v4.sin_port),
+            )
+        }
+        libc::AF_INET6 => {
+            let v6 = unsafe { &*(&from as *const _ as *const libc::sockaddr_in6) };
+            SocketAddrV6::new(
+                Ipv6Addr::from(v6.sin6_addr.s6_addr),
+                u16::from_be(v6.sin6_port),
+                v6.sin6_flowinfo,
+                v6.sin6_scope_id,
+            )
+            .into()
+        }
+        family => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("datagram from unknown address family {family}"),
+            ))
+        }
+    };
+    Ok((len, addr, ttl))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn recvmsg(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+    from: *mut libc::sockaddr_storage,
+) -> io::Result<(usize, Option<u8>)> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    // Aligned for cmsghdr, with room to spare for the one control message asked for.
+    let mut control = [0u64; 8];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    if !from.is_null() {
+        msg.msg_name = from.cast();
+        msg.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as _;
+    }
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = std::mem::size_of_val(&control) as _;
+    let len = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut ttl = None;
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while let Some(header) = unsafe { cmsg.as_ref() } {
+        if header.cmsg_level == libc::IPPROTO_IP && header.cmsg_type == libc::IP_TTL {
+            let value =
+                unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<libc::c_int>()) };
+            ttl = u8::try_from(value).ok();
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+    Ok((len as usize, ttl))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn recv_with_ttl(socket: &UdpSocket, buf: &mut [u8]) -> io::Result<(usize, Option<u8>)> {
+    Ok((socket.recv(buf)?, None))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn recv_from_with_ttl(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, Option<u8>)> {
+    let (len, addr) = socket.recv_from(buf)?;
+    Ok((len, addr, None))
+}
@@ -0,0 +1,93 @@
+use crate::Offset;
+#[cfg(feature = "net")]
+use crate::{LunartickError, NTPClient};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use std::time::{Duration, Instant};
+
+// Over a shorter interval the error of the new anchor says more about the noise of the
+// measurements than about the drift.
+const MIN_DRIFT_INTERVAL: Duration = Duration::from_secs(64);
+
+// NTP-corrected time read off the monotonic clock. The offset is measured once to tie an Instant
+// to the true time, and every reading after that is the anchor plus the monotonic time elapsed
+// since, corrected for the drift of the monotonic clock. Reading it costs about as much as
+// Instant::now, and neither the system clock nor its steps are involved.
+#[derive(Debug, Clone, Copy)]
+pub struct SteadyClock {
+    anchor: Instant,
+    anchor_time: DateTime<Utc>,
+    // How much faster than true time the monotonic clock runs, in parts per million.
+    drift_ppm: f64,
+}
+
+impl SteadyClock {
+    #[cfg(feature = "net")]
+    pub fn new(ntp_client: &NTPClient) -> Result<Self, LunartickError> {
+        let offset = ntp_client.test()?.get_combined_offset()?;
+        Ok(Self::from_offset(offset))
+    }
+
+    // Anchors to the system clock corrected by an offset measured just now.
+    pub fn from_offset(offset: Offset) -> Self {
+        let anchor = Instant::now();
+        Self {
+            anchor,
+            anchor_time: Utc::now() + offset.as_duration(),
+            drift_ppm: 0.0,
+        }
+    }
+
+    // Starts from a drift measured before, such as the one a previous SteadyClock learned.
+    pub fn with_drift_ppm(mut self, drift_ppm: f64) -> Self {
+        self.drift_ppm = drift_ppm;
+        self
+    }
+
+    pub fn get_drift_ppm(&self) -> f64 {
+        self.drift_ppm
+    }
+
+    // How long ago the clock was last anchored.
+    pub fn get_age(&self) -> Duration {
+        self.anchor.elapsed()
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.at(Instant::now())
+    }
+
+    // The corrected time at some instant, which may be before the anchor.
+    pub fn at(&self, instant: Instant) -> DateTime<Utc> {
+        let elapsed = match instant.checked_duration_since(self.anchor) {
+            Some(after) => after.as_nanos() as f64,
+            None => -(self.anchor.duration_since(instant).as_nanos() as f64),
+        };
+        let corrected = elapsed / (1.0 + self.drift_ppm / 1e6);
+        self.anchor_time + ChronoDuration::nanoseconds(corrected as i64)
+    }
+
+    // Anchors again to an offset measured just now. How far the old anchor had strayed from it
+    // over the time elapsed is the drift of the monotonic clock, which later readings correct for.
+    pub fn reanchor(&mut self, offset: Offset) {
+        let now = Instant::now();
+        let actual = Utc::now() + offset.as_duration();
+        let elapsed = now.duration_since(self.anchor);
+        let error = (self.at(now) - actual).num_nanoseconds();
+        if let Some(error) = error.filter(|_| elapsed >= MIN_DRIFT_INTERVAL) {
+            let monotonic = elapsed.as_secs_f64();
+            let true_elapsed = monotonic / (1.0 + self.drift_ppm / 1e6) - error as f64 / 1e9;
+            if true_elapsed > 0.0 {
+                self.drift_ppm = (monotonic / true_elapsed - 1.0) * 1e6;
+            }
+        }
+        self.anchor = now;
+        self.anchor_time = actual;
+    }
+
+    #[cfg(feature = "net")]
+    pub fn resync(&mut self, ntp_client: &NTPClient) -> Result<(), LunartickError> {
+        let offset = ntp_client.test()?.get_combined_offset()?;
+        self.reanchor(offset);
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{FailureKind, LunartickError, Socks5Proxy, Transport, DEFAULT_TIMEOUT};
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+// Carries NTP packets over TCP for networks that block UDP port 123, to a relay that forwards them
+// over UDP. Each packet is preceded by its length as a 16-bit big-endian integer, the framing DNS
+// uses over TCP.
+#[derive(Debug)]
+pub struct TcpTransport {
+    proxy: Option<Socks5Proxy>,
+    timeout: Duration,
+    stream: Option<TcpStream>,
+}
+
+impl TcpTransport {
+    pub fn new() -> Self {
+        Self {
+            proxy: None,
+            timeout: DEFAULT_TIMEOUT,
+            stream: None,
+        }
+    }
+
+    pub fn with_proxy(proxy: Socks5Proxy) -> Self {
+        Self {
+            proxy: Some(proxy),
+            ..Self::new()
+        }
+    }
+
+    fn stream(&mut self) -> Result<&mut TcpStream, FailureKind> {
+        self.stream
+            .as_mut()
+            .ok_or(FailureKind::Network(io::ErrorKind::NotConnected))
+    }
+}
+
+impl Default for TcpTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn connect_error(e: LunartickError) -> FailureKind {
+    match e {
+        LunartickError::IO(e) => e.into(),
+        _ => FailureKind::Network(io::ErrorKind::Other),
+    }
+}
+
+impl Transport for TcpTransport {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), FailureKind> {
+        self.timeout = timeout;
+        if let Some(stream) = &self.stream {
+            stream.set_read_timeout(Some(timeout))?;
+            stream.set_write_timeout(Some(timeout))?;
+        }
+        Ok(())
+    }
+
+    fn connect(&mut self, server: &str, port: u16) -> Result<(), FailureKind> {
+        let stream = match &self.proxy {
+            Some(proxy) => proxy
+                .connect(server, port, self.timeout)
+                .map_err(connect_error)?,
+            None => {
+                let addr = (server, port)
+                    .to_socket_addrs()
+                    .map_err(|_| FailureKind::Dns)?
+                    .next()
+                    .ok_or(FailureKind::Dns)?;
+                let stream = TcpStream::connect_timeout(&addr, self.timeout)?;
+                stream.set_read_timeout(Some(self.timeout))?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                stream
+            }
+        };
+        stream.set_nodelay(true)?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn send(&mut self, request: &[u8]) -> Result<(), FailureKind> {
+        let len = u16::try_from(request.len())
+            .map_err(|_| FailureKind::Network(io::ErrorKind::InvalidInput))?;
+        let mut frame = len.to_be_bytes().to_vec();
+        frame.extend_from_slice(request);
+        self.stream()?.write_all(&frame)?;
+        Ok(())
+    }
+
+    // Frames longer than the buffer are truncated to it, which the parser reports as oversized.
+    fn recv(&mut self, response: &mut [u8]) -> Result<usize, FailureKind> {
+        let stream = self.stream()?;
+        let mut len = [0; 2];
+        stream.read_exact(&mut len)?;
+        let len = usize::from(u16::from_be_bytes(len));
+        let kept = len.min(response.len());
+        stream.read_exact(&mut response[..kept])?;
+        io::copy(&mut stream.take((len - kept) as u64), &mut io::sink())?;
+        Ok(kept)
+    }
+
+    fn local_addr(&self) -> Option<IpAddr> {
+        self.stream
+            .as_ref()?
+            .local_addr()
+            .ok()
+            .map(|addr| addr.ip())
+    }
+
+    // Behind a proxy this is the proxy, not the relay.
+    fn peer_addr(&self) -> Option<IpAddr> {
+        self.stream.as_ref()?.peer_addr().ok().map(|addr| addr.ip())
+    }
+}
@@ -0,0 +1,105 @@
+#[cfg(feature = "net")]
+use crate::NTPClient;
+use crate::{LunartickError, SteadyClock, TestResults};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use std::time::Duration;
+
+// Assumed when nothing better is known: the frequency tolerance of the cheapest crystals.
+const DEFAULT_DRIFT_BOUND_PPM: f64 = 100.0;
+
+// A reading of a Timestamper: true time lies within `uncertainty` of `time`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    time: DateTime<Utc>,
+    uncertainty: Duration,
+}
+
+impl Timestamp {
+    pub fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    pub fn uncertainty(&self) -> Duration {
+        self.uncertainty
+    }
+
+    pub fn earliest(&self) -> DateTime<Utc> {
+        self.time - self.chrono_uncertainty()
+    }
+
+    pub fn latest(&self) -> DateTime<Utc> {
+        self.time + self.chrono_uncertainty()
+    }
+
+    fn chrono_uncertainty(&self) -> ChronoDuration {
+        ChronoDuration::from_std(self.uncertainty).unwrap_or(ChronoDuration::MAX)
+    }
+}
+
+// Bounded-error timestamps for event logging and tracing, read off a SteadyClock so that neither
+// root nor a query per event is needed. A reading is as uncertain as the measurement it is
+// anchored to, see TestResults::get_uncertainty, plus what the monotonic clock may have drifted
+// since at the drift bound.
+#[derive(Debug, Clone, Copy)]
+pub struct Timestamper {
+    clock: SteadyClock,
+    anchor_uncertainty: Duration,
+    drift_bound_ppm: f64,
+}
+
+impl Timestamper {
+    #[cfg(feature = "net")]
+    pub fn new(ntp_client: &NTPClient) -> Result<Self, LunartickError> {
+        Self::from_results(&ntp_client.test()?)
+    }
+
+    // Anchors to results measured just now.
+    pub fn from_results(results: &TestResults) -> Result<Self, LunartickError> {
+        Ok(Self {
+            clock: SteadyClock::from_offset(results.get_combined_offset()?),
+            anchor_uncertainty: anchor_uncertainty(results),
+            drift_bound_ppm: DEFAULT_DRIFT_BOUND_PPM,
+        })
+    }
+
+    // How fast the monotonic clock may drift from true time, 100ppm unless told otherwise.
+    pub fn with_drift_bound_ppm(mut self, drift_bound_ppm: f64) -> Self {
+        self.drift_bound_ppm = drift_bound_ppm.abs();
+        self
+    }
+
+    pub fn get_drift_bound_ppm(&self) -> f64 {
+        self.drift_bound_ppm
+    }
+
+    pub fn get_clock(&self) -> &SteadyClock {
+        &self.clock
+    }
+
+    pub fn stamp(&self) -> Timestamp {
+        let drift = self.clock.get_age().mul_f64(self.drift_bound_ppm / 1e6);
+        Timestamp {
+            time: self.clock.now(),
+            uncertainty: self.anchor_uncertainty + drift,
+        }
+    }
+
+    // Anchors again to results measured just now, which also teaches the clock its drift.
+    pub fn reanchor(&mut self, results: &TestResults) -> Result<(), LunartickError> {
+        self.clock.reanchor(results.get_combined_offset()?);
+        self.anchor_uncertainty = anchor_uncertainty(results);
+        Ok(())
+    }
+
+    #[cfg(feature = "net")]
+    pub fn resync(&mut self, ntp_client: &NTPClient) -> Result<(), LunartickError> {
+        self.reanchor(&ntp_client.test()?)
+    }
+}
+
+fn anchor_uncertainty(results: &TestResults) -> Duration {
+    results
+        .get_uncertainty()
+        .and_then(|uncertainty| uncertainty.to_std().ok())
+        .unwrap_or_default()
+}
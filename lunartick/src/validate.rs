@@ -0,0 +1,100 @@
+use crate::{parse::MAX_PACKET_LENGTH, NTPMessage, NTPTimestamp};
+use std::fmt::{self, Display};
+
+const LEAP_ALARM: u8 = 3;
+const MODE_SERVER: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidPacket {
+    Length(usize),
+    Oversized,
+    Version(u8),
+    Mode(u8),
+    Unsynchronized,
+    ZeroReceive,
+    ZeroTransmit,
+    TransmitBeforeReceive,
+    OriginMismatch,
+    ExtensionLength(usize),
+    TrailingData(usize),
+    TimestampOutOfRange,
+}
+
+impl Display for InvalidPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidPacket::Length(len) => write!(f, "unexpected length of {len} bytes"),
+            InvalidPacket::Oversized => write!(f, "larger than {MAX_PACKET_LENGTH} bytes"),
+            InvalidPacket::Version(version) => write!(f, "unsupported version {version}"),
+            InvalidPacket::Mode(mode) => write!(f, "unexpected mode {mode}"),
+            InvalidPacket::Unsynchronized => write!(f, "server clock is not synchronized"),
+            InvalidPacket::ZeroReceive => write!(f, "receive timestamp is zero"),
+            InvalidPacket::ZeroTransmit => write!(f, "transmit timestamp is zero"),
+            InvalidPacket::TransmitBeforeReceive => {
+                write!(f, "transmit timestamp precedes receive timestamp")
+            }
+            InvalidPacket::OriginMismatch => {
+                write!(f, "origin timestamp does not match the request")
+            }
+            InvalidPacket::ExtensionLength(len) => {
+                write!(f, "invalid extension field length {len}")
+            }
+            InvalidPacket::TrailingData(len) => {
+                write!(f, "{len} bytes of trailing data after extension fields")
+            }
+            InvalidPacket::TimestampOutOfRange => write!(f, "timestamp out of range"),
+        }
+    }
+}
+
+// Checks that can be made before looking at the stratum, so kiss-o'-death packets get this far.
+pub(crate) fn validate_header(response: &NTPMessage) -> Result<(), InvalidPacket> {
+    let version = response.version();
+    if !(1..=4).contains(&version) {
+        return Err(InvalidPacket::Version(version));
+    }
+    if response.mode() != MODE_SERVER {
+        return Err(InvalidPacket::Mode(response.mode()));
+    }
+    Ok(())
+}
+
+// Whether the response answers this request, which is all a kiss-o'-death is checked for before
+// it is obeyed, so that an off-path attacker cannot silence a server without seeing its requests.
+// Returns whether the response is interleaved: those echo the receive timestamp of the request
+// instead of its transmit timestamp, and carry the transmit timestamp of the previous response.
+pub(crate) fn validate_origin(
+    request: &NTPMessage,
+    response: &NTPMessage,
+) -> Result<bool, InvalidPacket> {
+    let origin = response.origin_timestamp();
+    let echoed = request.receive_timestamp();
+    let interleaved = echoed != NTPTimestamp::default() && origin == echoed;
+    if origin != request.transmit_timestamp() && !interleaved {
+        return Err(InvalidPacket::OriginMismatch);
+    }
+    Ok(interleaved)
+}
+
+pub(crate) fn validate_timestamps(
+    request: &NTPMessage,
+    response: &NTPMessage,
+) -> Result<(), InvalidPacket> {
+    if response.leap() == LEAP_ALARM {
+        return Err(InvalidPacket::Unsynchronized);
+    }
+    let receive = response.receive_timestamp();
+    let transmit = response.transmit_timestamp();
+    if receive == NTPTimestamp::default() {
+        return Err(InvalidPacket::ZeroReceive);
+    }
+    if transmit == NTPTimestamp::default() {
+        return Err(InvalidPacket::ZeroTransmit);
+    }
+    let interleaved = validate_origin(request, response)?;
+    // Compared as a wrapping difference so the check survives the 2036 era rollover.
+    if !interleaved && (transmit.as_u64().wrapping_sub(receive.as_u64()) as i64) < 0 {
+        return Err(InvalidPacket::TransmitBeforeReceive);
+    }
+    Ok(())
+}
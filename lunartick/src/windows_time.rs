@@ -0,0 +1,180 @@
+use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
+use std::io;
+
+// The fields of a SYSTEMTIME, apart from the Win32 type so that they can be checked anywhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SystemTime {
+    pub(crate) year: u16,
+    pub(crate) month: u16,
+    pub(crate) day_of_week: u16,
+    pub(crate) day: u16,
+    pub(crate) hour: u16,
+    pub(crate) minute: u16,
+    pub(crate) second: u16,
+    pub(crate) milliseconds: u16,
+}
+
+// The fields of a time as it reads in its own time zone. SYSTEMTIME has no second 60, so a leap
+// second is held at the last millisecond of the second before it.
+pub(crate) fn system_time<Tz: TimeZone>(t: &DateTime<Tz>) -> SystemTime {
+    SystemTime {
+        year: t.year() as u16,
+        month: t.month() as u16,
+        day_of_week: t.weekday().num_days_from_sunday() as u16,
+        day: t.day() as u16,
+        hour: t.hour() as u16,
+        minute: t.minute() as u16,
+        second: t.second() as u16,
+        milliseconds: (t.nanosecond() / 1_000_000).min(999) as u16,
+    }
+}
+
+// The calls that set the clock, SetSystemTime and SetLocalTime on Windows.
+pub(crate) trait Win32Time {
+    fn set_system_time(&self, time: &SystemTime) -> io::Result<()>;
+
+    fn set_local_time(&self, time: &SystemTime) -> io::Result<()>;
+}
+
+// SetSystemTime takes the fields of the time in UTC, whatever offset it was made with.
+pub(crate) fn set_system_time<Tz: TimeZone>(
+    api: &impl Win32Time,
+    t: &DateTime<Tz>,
+) -> io::Result<()> {
+    api.set_system_time(&system_time(&t.with_timezone(&Utc)))
+}
+
+// SetLocalTime takes them in `zone`, the system's time zone.
+pub(crate) fn set_local_time<Tz: TimeZone, Z: TimeZone>(
+    api: &impl Win32Time,
+    t: &DateTime<Tz>,
+    zone: &Z,
+) -> io::Result<()> {
+    api.set_local_time(&system_time(&t.with_timezone(zone)))
+}
+
+#[cfg(windows)]
+pub(crate) struct Win32;
+
+#[cfg(windows)]
+impl Win32 {
+    fn systime(time: &SystemTime) -> windows::Win32::Foundation::SYSTEMTIME {
+        windows::Win32::Foundation::SYSTEMTIME {
+            wYear: time.year,
+            wMonth: time.month,
+            wDayOfWeek: time.day_of_week,
+            wDay: time.day,
+            wHour: time.hour,
+            wMinute: time.minute,
+            wSecond: time.second,
+            wMilliseconds: time.milliseconds,
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Win32Time for Win32 {
+    fn set_system_time(&self, time: &SystemTime) -> io::Result<()> {
+        use windows::Win32::System::SystemInformation::SetSystemTime;
+
+        if unsafe { SetSystemTime(&Self::systime(time)) }.as_bool() {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    fn set_local_time(&self, time: &SystemTime) -> io::Result<()> {
+        use windows::Win32::System::SystemInformation::SetLocalTime;
+
+        if unsafe { SetLocalTime(&Self::systime(time)) }.as_bool() {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+    use std::sync::Mutex;
+
+    #[derive(Debug, PartialEq)]
+    enum Call {
+        System(SystemTime),
+        Local(SystemTime),
+    }
+
+    // Records the fields each call is given.
+    #[derive(Default)]
+    struct MockWin32 {
+        calls: Mutex<Vec<Call>>,
+    }
+
+    impl Win32Time for MockWin32 {
+        fn set_system_time(&self, time: &SystemTime) -> io::Result<()> {
+            self.calls.lock().unwrap().push(Call::System(*time));
+            Ok(())
+        }
+
+        fn set_local_time(&self, time: &SystemTime) -> io::Result<()> {
+            self.calls.lock().unwrap().push(Call::Local(*time));
+            Ok(())
+        }
+    }
+
+    fn time(rfc3339: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap()
+    }
+
+    #[test]
+    fn system_time_is_set_in_utc_and_local_time_in_the_zone() {
+        let api = MockWin32::default();
+        // A Sunday in Kiribati, still Saturday in UTC and in Hawaii.
+        let t = time("2024-03-03T09:30:15.250+14:00");
+        let hawaii = FixedOffset::west_opt(10 * 3600).unwrap();
+        set_system_time(&api, &t).unwrap();
+        set_local_time(&api, &t, &hawaii).unwrap();
+        set_local_time(&api, &t, t.offset()).unwrap();
+        let fields = |day_of_week, day, hour| SystemTime {
+            year: 2024,
+            month: 3,
+            day_of_week,
+            day,
+            hour,
+            minute: 30,
+            second: 15,
+            milliseconds: 250,
+        };
+        assert_eq!(
+            *api.calls.lock().unwrap(),
+            [
+                Call::System(fields(6, 2, 19)),
+                Call::Local(fields(6, 2, 9)),
+                Call::Local(fields(0, 3, 9)),
+            ]
+        );
+    }
+
+    #[test]
+    fn fields_roll_over_the_year_in_utc() {
+        let fields = system_time(&time("2025-01-01T01:00:00+02:00").with_timezone(&Utc));
+        assert_eq!((fields.year, fields.month, fields.day), (2024, 12, 31));
+        assert_eq!((fields.day_of_week, fields.hour), (2, 23));
+    }
+
+    #[test]
+    fn milliseconds_are_truncated_and_clamped_in_a_leap_second() {
+        let fields = system_time(&time("2024-01-01T00:00:00.999999999Z"));
+        assert_eq!((fields.second, fields.milliseconds), (0, 999));
+        let leap = Utc
+            .with_ymd_and_hms(2016, 12, 31, 23, 59, 59)
+            .unwrap()
+            .with_nanosecond(1_500_000_000)
+            .unwrap();
+        let fields = system_time(&leap);
+        assert_eq!((fields.second, fields.milliseconds), (59, 999));
+    }
+}
@@ -0,0 +1,69 @@
+use lunartick::{FailureKind, InvalidPacket, NTPClient, Transport};
+
+// Answers every request with a RATE kiss-o'-death, echoing its transmit timestamp as the origin
+// or, as an off-path attacker that cannot see the request would, a made-up one.
+struct KissOfDeath {
+    echo_origin: bool,
+    origin: [u8; 8],
+    requests: usize,
+}
+
+impl KissOfDeath {
+    fn new(echo_origin: bool) -> Self {
+        Self {
+            echo_origin,
+            origin: [0; 8],
+            requests: 0,
+        }
+    }
+}
+
+impl Transport for KissOfDeath {
+    fn connect(&mut self, _server: &str, _port: u16) -> Result<(), FailureKind> {
+        Ok(())
+    }
+
+    fn send(&mut self, request: &[u8]) -> Result<(), FailureKind> {
+        self.requests += 1;
+        self.origin.copy_from_slice(&request[40..48]);
+        if !self.echo_origin {
+            self.origin[7] ^= 1;
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self, response: &mut [u8]) -> Result<usize, FailureKind> {
+        response[..48].fill(0);
+        response[..4].copy_from_slice(&[0x24, 0, 6, 0xec]);
+        response[12..16].copy_from_slice(b"RATE");
+        response[24..32].copy_from_slice(&self.origin);
+        Ok(48)
+    }
+}
+
+#[test]
+fn kiss_of_death_answering_the_request_is_obeyed() {
+    let mut transport = KissOfDeath::new(true);
+    let results = NTPClient::new_with_server("192.0.2.1")
+        .with_burst(2)
+        .test_with(&mut transport);
+    assert_eq!(
+        results.get_offset(0),
+        Some(Err(FailureKind::KissOfDeath(*b"RATE")))
+    );
+    // The server is left alone for the rest of the burst.
+    assert_eq!(transport.requests, 1);
+}
+
+#[test]
+fn kiss_of_death_with_another_origin_is_malformed() {
+    let mut transport = KissOfDeath::new(false);
+    let results = NTPClient::new_with_server("192.0.2.1")
+        .with_burst(2)
+        .test_with(&mut transport);
+    assert_eq!(
+        results.get_offset(0),
+        Some(Err(FailureKind::Malformed(InvalidPacket::OriginMismatch)))
+    );
+    assert_eq!(transport.requests, 2);
+}
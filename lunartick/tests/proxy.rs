@@ -0,0 +1,25 @@
+#![cfg(target_os = "linux")]
+
+use lunartick::Socks5Proxy;
+use std::{
+    net::{TcpListener, TcpStream},
+    os::unix::io::AsRawFd,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn unreachable_proxies_time_out() {
+    // With a backlog of 0 the listener takes one pending connection and then drops further SYNs
+    // unanswered, as a host that cannot be reached does.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    assert_eq!(unsafe { libc::listen(listener.as_raw_fd(), 0) }, 0);
+    let addr = listener.local_addr().unwrap();
+    let _pending = TcpStream::connect(addr).unwrap();
+
+    let proxy = Socks5Proxy::new(addr.ip().to_string(), addr.port());
+    let started = Instant::now();
+    assert!(proxy
+        .connect("time.example.com", 123, Duration::from_millis(300))
+        .is_err());
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
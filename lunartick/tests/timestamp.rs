@@ -0,0 +1,111 @@
+use chrono::{DateTime, TimeZone, Utc};
+use lunartick::{InvalidPacket, NTPTimestamp};
+
+const HALF_ERA: i64 = 1 << 31;
+const CASES: usize = 5_000;
+
+// xorshift64, so that every run checks the same inputs without a property testing crate.
+struct Inputs(u64);
+
+impl Inputs {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // Any time from 1901 to 2106, either side of the wrap in 2036.
+    fn pivot(&mut self) -> DateTime<Utc> {
+        let secs = -HALF_ERA + (self.next() % (3 * HALF_ERA as u64)) as i64;
+        Utc.timestamp_opt(secs, (self.next() % 1_000_000_000) as u32)
+            .unwrap()
+    }
+}
+
+fn utc(rfc3339: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(rfc3339)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+#[test]
+fn datetimes_near_the_pivot_round_trip_exactly() {
+    let mut inputs = Inputs(0x5eed_1900);
+    for _ in 0..CASES {
+        let pivot = inputs.pivot();
+        let ahead = (inputs.next() % (2 * HALF_ERA as u64)) as i64 - HALF_ERA;
+        let nanos = (inputs.next() % 1_000_000_000) as u32;
+        let time = Utc.timestamp_opt(pivot.timestamp() + ahead, nanos).unwrap();
+        let ntp = NTPTimestamp::from_datetime(time, pivot).unwrap();
+        assert_eq!(ntp.to_datetime(pivot), Ok(time), "{time} around {pivot}");
+    }
+}
+
+#[test]
+fn timestamps_round_trip_to_within_a_nanosecond() {
+    let mut inputs = Inputs(0x5eed_2036);
+    for _ in 0..CASES {
+        let pivot = inputs.pivot();
+        let ntp = NTPTimestamp::from_u64(inputs.next());
+        let time = ntp.to_datetime(pivot).unwrap();
+        assert!((time.timestamp() - pivot.timestamp()).abs() <= HALF_ERA);
+        let back = NTPTimestamp::from_datetime(time, pivot).unwrap();
+        assert_eq!(back.seconds(), ntp.seconds());
+        // Rounding down to the nanosecond loses up to 2^32 / 10^9 steps of the fraction.
+        assert!(
+            ntp.fraction() - back.fraction() <= 4,
+            "{ntp:?} became {back:?}"
+        );
+    }
+}
+
+#[test]
+fn timestamps_after_2036_are_read_in_era_1() {
+    let pivot = utc("2035-06-01T00:00:00Z");
+    let time = utc("2040-01-01T12:00:00.5Z");
+    let ntp = NTPTimestamp::from_datetime(time, pivot).unwrap();
+    // Era 1 starts on 2036-02-07.
+    assert_eq!(ntp.seconds(), 123_053_504);
+    assert_eq!(ntp.to_datetime(pivot), Ok(time));
+    assert_eq!(ntp.to_datetime(utc("2041-01-01T00:00:00Z")), Ok(time));
+    // The same seconds read from 1970 are in era 0.
+    assert_eq!(
+        ntp.to_datetime(utc("1970-01-01T00:00:00Z")),
+        Ok(utc("1903-11-26T05:31:44.5Z"))
+    );
+}
+
+#[test]
+fn times_half_an_era_from_the_pivot_are_rejected() {
+    let pivot = utc("2026-10-14T00:00:00Z");
+    let last = Utc
+        .timestamp_opt(pivot.timestamp() + HALF_ERA - 1, 999_999_999)
+        .unwrap();
+    let first = Utc.timestamp_opt(pivot.timestamp() - HALF_ERA, 0).unwrap();
+    assert!(NTPTimestamp::from_datetime(last, pivot).is_ok());
+    assert!(NTPTimestamp::from_datetime(first, pivot).is_ok());
+    for time in [
+        Utc.timestamp_opt(pivot.timestamp() + HALF_ERA, 0).unwrap(),
+        Utc.timestamp_opt(pivot.timestamp() - HALF_ERA - 1, 999_999_999)
+            .unwrap(),
+        utc("2100-01-01T00:00:00Z"),
+        utc("1900-01-01T00:00:00Z"),
+    ] {
+        assert_eq!(
+            NTPTimestamp::from_datetime(time, pivot),
+            Err(InvalidPacket::TimestampOutOfRange),
+            "{time}"
+        );
+    }
+}
+
+#[test]
+fn conversions_use_the_system_clock() {
+    let now = Utc::now();
+    let ntp = NTPTimestamp::try_from(now).unwrap();
+    assert_eq!(NTPTimestamp::local(now), ntp);
+    assert!(DateTime::<Utc>::try_from(ntp)
+        .is_ok_and(|back| (back - now).abs() < chrono::Duration::microseconds(1)));
+    assert!(NTPTimestamp::try_from(utc("1900-01-01T00:00:00Z")).is_err());
+}
@@ -0,0 +1,55 @@
+use chrono::Utc;
+use lunartick::{FailureKind, NTPClient, NTPTimestamp, Transport, UdpTransport};
+use std::net::{SocketAddr, UdpSocket};
+
+// Answers every request on `listen` like a stratum 1 server with the correct time.
+fn serve(listen: &str) -> SocketAddr {
+    let socket = UdpSocket::bind(listen).unwrap();
+    let addr = socket.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let mut request = [0; 48];
+        while let Ok((_, peer)) = socket.recv_from(&mut request) {
+            let now = NTPTimestamp::local(Utc::now()).as_u64().to_be_bytes();
+            let mut packet = [0; 48];
+            packet[..4].copy_from_slice(&[0x24, 1, 6, 0xec]);
+            packet[12..16].copy_from_slice(b"GPS\0");
+            packet[24..32].copy_from_slice(&request[40..48]);
+            packet[32..40].copy_from_slice(&now);
+            packet[40..48].copy_from_slice(&now);
+            let _ = socket.send_to(&packet, peer);
+        }
+    });
+    addr
+}
+
+#[test]
+fn ipv6_servers_are_queried_over_ipv6() {
+    let server = serve("[::1]:0").to_string();
+    let ipv4 = serve("127.0.0.1:0").to_string();
+    for concurrency in [1, 4] {
+        let results = NTPClient::new_with_multiple_servers([server.clone(), ipv4.clone()])
+            .with_concurrency(concurrency)
+            .test()
+            .unwrap();
+        for i in 0..2 {
+            let offset = results.get_offset(i).unwrap().unwrap();
+            assert!(offset.as_millis_f64().abs() < 100.0, "{offset}");
+        }
+    }
+}
+
+#[test]
+fn sockets_do_not_connect_across_address_families() {
+    let mut ipv4 = UdpTransport::bind().unwrap();
+    assert_eq!(
+        ipv4.connect("::1", 123),
+        Err(FailureKind::AddressFamily("::1".parse().unwrap()))
+    );
+    assert_eq!(ipv4.connect("127.0.0.1", 123), Ok(()));
+    let mut ipv6 = UdpTransport::bind_ipv6_port(0).unwrap();
+    assert_eq!(
+        ipv6.connect("127.0.0.1", 123),
+        Err(FailureKind::AddressFamily("127.0.0.1".parse().unwrap()))
+    );
+    assert_eq!(ipv6.connect("::1", 123), Ok(()));
+}